@@ -0,0 +1,3 @@
+ALTER TABLE inputs
+  ADD faults TEXT,
+  ADD warnings TEXT;
@@ -0,0 +1,9 @@
+ALTER TABLE inputs
+  ADD p_load INTEGER NOT NULL DEFAULT 0,
+  ADD e_load_day DOUBLE NOT NULL DEFAULT 0,
+  ADD self_consumption DOUBLE,
+  ADD self_sufficiency DOUBLE,
+  ADD battery_efficiency DOUBLE,
+  ADD inverter_efficiency DOUBLE,
+  ADD time_to_empty INTEGER,
+  ADD time_to_full INTEGER;
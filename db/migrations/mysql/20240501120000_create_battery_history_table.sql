@@ -0,0 +1,13 @@
+CREATE TABLE battery_history (
+  id INT AUTO_INCREMENT PRIMARY KEY,
+  soh INTEGER NOT NULL,
+  cycle_count INTEGER NOT NULL,
+  equivalent_cycles DOUBLE NOT NULL,
+  rated_capacity DOUBLE NOT NULL,
+  expected_capacity DOUBLE NOT NULL,
+  estimated_capacity DOUBLE,
+  fading BOOLEAN NOT NULL,
+
+  datalog TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL
+)
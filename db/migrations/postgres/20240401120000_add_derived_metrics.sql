@@ -0,0 +1,9 @@
+ALTER TABLE inputs
+  ADD p_load INTEGER NOT NULL DEFAULT 0,
+  ADD e_load_day NUMERIC NOT NULL DEFAULT 0,
+  ADD self_consumption NUMERIC,
+  ADD self_sufficiency NUMERIC,
+  ADD battery_efficiency NUMERIC,
+  ADD inverter_efficiency NUMERIC,
+  ADD time_to_empty INTEGER,
+  ADD time_to_full INTEGER;
@@ -0,0 +1,13 @@
+CREATE TABLE battery_history (
+  id SERIAL PRIMARY KEY,
+  soh INTEGER NOT NULL,
+  cycle_count INTEGER NOT NULL,
+  equivalent_cycles NUMERIC NOT NULL,
+  rated_capacity NUMERIC NOT NULL,
+  expected_capacity NUMERIC NOT NULL,
+  estimated_capacity NUMERIC,
+  fading BOOLEAN NOT NULL,
+
+  datalog TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL
+)
@@ -0,0 +1,2 @@
+ALTER TABLE inputs ADD faults TEXT;
+ALTER TABLE inputs ADD warnings TEXT;
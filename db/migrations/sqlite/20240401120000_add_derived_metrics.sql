@@ -0,0 +1,8 @@
+ALTER TABLE inputs ADD p_load INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE inputs ADD e_load_day NUMERIC NOT NULL DEFAULT 0;
+ALTER TABLE inputs ADD self_consumption NUMERIC;
+ALTER TABLE inputs ADD self_sufficiency NUMERIC;
+ALTER TABLE inputs ADD battery_efficiency NUMERIC;
+ALTER TABLE inputs ADD inverter_efficiency NUMERIC;
+ALTER TABLE inputs ADD time_to_empty INTEGER;
+ALTER TABLE inputs ADD time_to_full INTEGER;
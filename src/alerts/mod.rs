@@ -0,0 +1,351 @@
+use crate::prelude::*;
+
+pub mod sinks;
+
+use lxp::packet::{BmsStatus, DeviceFunction, ReadInput, TranslatedData};
+use serde::Serialize;
+use std::collections::HashMap;
+
+type Time = chrono::DateTime<chrono::Utc>;
+
+// Condition {{{
+// Something we can alert about. Each is tracked separately per inverter.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Condition {
+    Fault(u8),   // bit of fault_code
+    Warning(u8), // bit of warning_code
+    Offline,
+    LowSoc,
+    CellImbalance,
+    GridLoss,
+    CapacityFade,
+}
+
+impl Condition {
+    pub fn key(&self) -> String {
+        match self {
+            Self::Fault(bit) => format!("fault_{}", bit),
+            Self::Warning(bit) => format!("warning_{}", bit),
+            Self::Offline => "offline".to_owned(),
+            Self::LowSoc => "low_soc".to_owned(),
+            Self::CellImbalance => "cell_imbalance".to_owned(),
+            Self::GridLoss => "grid_loss".to_owned(),
+            Self::CapacityFade => "capacity_fade".to_owned(),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        use lxp::packet::{FaultCodeString, WarningCodeString};
+
+        match self {
+            Self::Fault(bit) => FaultCodeString::from_value(1 << bit).to_owned(),
+            Self::Warning(bit) => WarningCodeString::from_value(1 << bit).to_owned(),
+            Self::Offline => "Inverter offline".to_owned(),
+            Self::LowSoc => "Battery SOC low".to_owned(),
+            Self::CellImbalance => "Battery cells imbalanced".to_owned(),
+            Self::GridLoss => "Grid lost, running on EPS".to_owned(),
+            Self::CapacityFade => "Battery capacity fading faster than expected".to_owned(),
+        }
+    }
+} // }}}
+
+// Alert {{{
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub datalog: Serial,
+    pub condition: String,
+    pub state: AlertState,
+    pub message: String,
+    pub time: UnixTime,
+}
+
+impl Alert {
+    pub fn new(datalog: Serial, condition: &Condition, state: AlertState) -> Self {
+        Self {
+            datalog,
+            condition: condition.key(),
+            state,
+            message: condition.description(),
+            time: UnixTime::now(),
+        }
+    }
+
+    // one-line summary for sinks which want a subject
+    pub fn title(&self) -> String {
+        match self.state {
+            AlertState::Firing => format!("{}: {}", self.datalog, self.message),
+            AlertState::Resolved => format!("{}: resolved: {}", self.datalog, self.message),
+        }
+    }
+} // }}}
+
+// Rules {{{
+#[derive(Debug, Default)]
+struct Tracker {
+    active: bool,        // what we last told the sinks
+    since: Option<Time>, // when observations started disagreeing with that
+}
+
+// Turns observations into alerts. A condition has to be seen (or not seen)
+// continuously for the debounce time before we announce it starting (or
+// being resolved), so one that flaps doesn't spam.
+pub struct Rules {
+    config: config::Alerts,
+    trackers: HashMap<(Serial, Condition), Tracker>,
+}
+
+impl Rules {
+    pub fn new(config: config::Alerts) -> Self {
+        Self {
+            config,
+            trackers: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, datalog: Serial, condition: Condition, present: bool, now: Time) {
+        if !Self::enabled(&self.config, &condition) {
+            return;
+        }
+
+        let tracker = self.trackers.entry((datalog, condition)).or_default();
+        if present == tracker.active {
+            tracker.since = None;
+        } else if tracker.since.is_none() {
+            tracker.since = Some(now);
+        }
+    }
+
+    // inverters we should hear from. each counts as offline from now until it
+    // connects, so one that never does still gets an alert
+    pub fn expect(&mut self, datalogs: impl IntoIterator<Item = Serial>, now: Time) {
+        for datalog in datalogs {
+            self.disconnected(datalog, now);
+        }
+    }
+
+    pub fn connected(&mut self, datalog: Serial, now: Time) {
+        self.observe(datalog, Condition::Offline, false, now);
+    }
+
+    // the inverter task sends these on every failed reconnect too, which is fine;
+    // the condition keeps the time it was first seen
+    pub fn disconnected(&mut self, datalog: Serial, now: Time) {
+        self.observe(datalog, Condition::Offline, true, now);
+    }
+
+    pub fn observe_packet(&mut self, td: &TranslatedData, now: Time) {
+        if td.device_function != DeviceFunction::ReadInput {
+            return;
+        }
+
+        let datalog = td.datalog;
+
+        match td.read_input() {
+            Ok(ReadInput::ReadInputAll(r_all)) => {
+                self.observe_status(datalog, r_all.status, r_all.soc, now);
+                self.observe_codes(datalog, r_all.fault_code, r_all.warning_code, now);
+                self.observe_bms(datalog, &BmsStatus::from_input_all(&r_all), now);
+            }
+            Ok(ReadInput::ReadInput1(r1)) => self.observe_status(datalog, r1.status, r1.soc, now),
+            Ok(ReadInput::ReadInput2(r2)) => {
+                self.observe_codes(datalog, r2.fault_code, r2.warning_code, now)
+            }
+            Ok(ReadInput::ReadInput3(r3)) => {
+                self.observe_bms(datalog, &BmsStatus::from_input_3(&r3), now)
+            }
+            _ => {}
+        }
+    }
+
+    pub fn observe_battery(&mut self, stats: &battery::Stats, now: Time) {
+        self.observe(stats.datalog, Condition::CapacityFade, stats.fading, now);
+    }
+
+    // alerts for every condition which has now persisted long enough
+    pub fn evaluate(&mut self, now: Time) -> Vec<Alert> {
+        let mut r = Vec::new();
+
+        for ((datalog, condition), tracker) in self.trackers.iter_mut() {
+            let since = match tracker.since {
+                Some(since) => since,
+                None => continue,
+            };
+
+            if now - since < Self::hold_time(&self.config, condition, !tracker.active) {
+                continue;
+            }
+
+            tracker.active = !tracker.active;
+            tracker.since = None;
+
+            let state = if tracker.active {
+                AlertState::Firing
+            } else {
+                AlertState::Resolved
+            };
+            r.push(Alert::new(*datalog, condition, state));
+        }
+
+        r
+    }
+
+    fn observe_status(&mut self, datalog: Serial, status: u16, soc: i8, now: Time) {
+        // the off-grid statuses StatusString knows of
+        let off_grid = matches!(status, 0x40 | 0x80 | 0xC0 | 0x88);
+        self.observe(datalog, Condition::GridLoss, off_grid, now);
+
+        if let Some(soc_below) = self.config.soc_below() {
+            self.observe(datalog, Condition::LowSoc, soc < soc_below, now);
+        }
+    }
+
+    fn observe_codes(&mut self, datalog: Serial, fault_code: u32, warning_code: u32, now: Time) {
+        for bit in 0..32 {
+            let set = fault_code & (1 << bit) != 0;
+            self.observe(datalog, Condition::Fault(bit), set, now);
+            let set = warning_code & (1 << bit) != 0;
+            self.observe(datalog, Condition::Warning(bit), set, now);
+        }
+    }
+
+    fn observe_bms(&mut self, datalog: Serial, bms: &BmsStatus, now: Time) {
+        let imbalanced = bms.cell_imbalance == "ON";
+        self.observe(datalog, Condition::CellImbalance, imbalanced, now);
+    }
+
+    fn enabled(config: &config::Alerts, condition: &Condition) -> bool {
+        match condition {
+            Condition::Fault(_) => config.faults(),
+            Condition::Warning(_) => config.warnings(),
+            Condition::Offline => true,
+            Condition::LowSoc => config.soc_below().is_some(),
+            Condition::CellImbalance => config.cell_imbalance(),
+            Condition::GridLoss => config.grid_loss(),
+            Condition::CapacityFade => config.capacity_fade(),
+        }
+    }
+
+    // going offline has its own, longer, threshold; coming back is debounced as normal
+    fn hold_time(config: &config::Alerts, condition: &Condition, firing: bool) -> chrono::Duration {
+        if *condition == Condition::Offline && firing {
+            chrono::Duration::minutes(config.offline_minutes() as i64)
+        } else {
+            chrono::Duration::seconds(config.debounce() as i64)
+        }
+    }
+} // }}}
+
+pub struct Alerts {
+    config: ConfigWrapper,
+    channels: Channels,
+}
+
+impl Alerts {
+    pub fn new(config: ConfigWrapper, channels: Channels) -> Self {
+        Self { config, channels }
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        let config = match &*self.config.alerts() {
+            Some(alerts) if alerts.enabled() => alerts.clone(),
+            _ => {
+                info!("alerts disabled, skipping");
+                return Ok(());
+            }
+        };
+
+        let sinks: Vec<Box<dyn sinks::Sink>> = config
+            .sinks()
+            .iter()
+            .map(|sink| sinks::build(sink, &self.channels))
+            .collect();
+
+        if sinks.is_empty() {
+            warn!("alerts enabled but no sinks configured");
+        }
+
+        // sinks can be slow (SMTP especially), so they get alerts through a
+        // queue rather than holding up the receiver
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut rules = Rules::new(config);
+        let inverters = self.config.enabled_inverters();
+        rules.expect(inverters.iter().map(|i| i.datalog()), Utils::utc());
+
+        futures::try_join!(self.receiver(rules, tx), Self::notifier(rx, sinks))?;
+
+        info!("alerts loop exiting");
+
+        Ok(())
+    }
+
+    async fn receiver(
+        &self,
+        mut rules: Rules,
+        alerts: tokio::sync::mpsc::UnboundedSender<Alert>,
+    ) -> Result<()> {
+        use lxp::inverter::ChannelData::*;
+        use tokio::sync::broadcast::error::RecvError;
+
+        let mut receiver = self.channels.from_inverter.subscribe();
+        // battery stats are worked out by the coordinator, and sent on to_outputs
+        let mut outputs = self.channels.to_outputs.subscribe();
+        // conditions can become due with nothing arriving, eg offline
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+
+        loop {
+            tokio::select! {
+                data = receiver.recv() => match data {
+                    Ok(Shutdown) | Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(n)) => warn!("alerts: fell behind, dropped {} packet(s)", n),
+                    Ok(Connected(datalog)) => rules.connected(datalog, Utils::utc()),
+                    Ok(Disconnect(datalog)) => rules.disconnected(datalog, Utils::utc()),
+                    Ok(Packet(lxp::packet::Packet::TranslatedData(td))) => {
+                        rules.observe_packet(&td, Utils::utc())
+                    }
+                    Ok(Packet(_)) => {}
+                },
+                data = outputs.recv() => match data {
+                    Ok(output::ChannelData::Data(output::OutputData::Battery(stats))) => {
+                        rules.observe_battery(&stats, Utils::utc())
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                _ = interval.tick() => {}
+            }
+
+            for alert in rules.evaluate(Utils::utc()) {
+                info!("alert: {}", alert.title());
+                if alerts.send(alert).is_err() {
+                    bail!("send(alerts) failed - channel closed?");
+                }
+            }
+        }
+
+        // dropping alerts lets the notifier finish what's queued and return
+        Ok(())
+    }
+
+    async fn notifier(
+        mut alerts: tokio::sync::mpsc::UnboundedReceiver<Alert>,
+        sinks: Vec<Box<dyn sinks::Sink>>,
+    ) -> Result<()> {
+        while let Some(alert) = alerts.recv().await {
+            for sink in &sinks {
+                if let Err(e) = sink.send(&alert).await {
+                    warn!("sending alert: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use crate::prelude::*;
+
+use super::{Alert, AlertState};
+use async_trait::async_trait;
+
+#[async_trait(?Send)]
+pub trait Sink {
+    async fn send(&self, alert: &Alert) -> Result<()>;
+}
+
+pub fn build(sink: &config::AlertSink, channels: &Channels) -> Box<dyn Sink> {
+    use config::AlertSink::*;
+
+    match sink {
+        Mqtt => Box::new(MqttSink {
+            channels: channels.clone(),
+        }),
+        Webhook { url } => Box::new(WebhookSink { url: url.clone() }),
+        Email {
+            host,
+            port,
+            username,
+            password,
+            from,
+            to,
+        } => Box::new(EmailSink {
+            host: host.clone(),
+            port: port.unwrap_or(587),
+            username: username.clone(),
+            password: password.clone(),
+            from: from.clone(),
+            to: to.clone(),
+        }),
+        Ntfy { url, token } => Box::new(NtfySink {
+            url: url.clone(),
+            token: token.clone(),
+        }),
+        Gotify { url, token } => Box::new(GotifySink {
+            url: url.clone(),
+            token: token.clone(),
+        }),
+    }
+}
+
+// MqttSink {{{
+// publishes to <datalog>/alerts, under the usual namespace
+pub struct MqttSink {
+    channels: Channels,
+}
+
+#[async_trait(?Send)]
+impl Sink for MqttSink {
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let message = mqtt::Message {
+            topic: format!("{}/alerts", alert.datalog),
+            retain: false,
+            payload: serde_json::to_string(alert)?,
+        };
+
+        if self
+            .channels
+            .to_mqtt
+            .send(mqtt::ChannelData::Message(message))
+            .is_err()
+        {
+            bail!("send(to_mqtt) failed - channel closed?");
+        }
+
+        Ok(())
+    }
+} // }}}
+
+// WebhookSink {{{
+// POSTs the alert as JSON
+pub struct WebhookSink {
+    url: String,
+}
+
+#[async_trait(?Send)]
+impl Sink for WebhookSink {
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        reqwest::Client::new()
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(alert)?)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+} // }}}
+
+// EmailSink {{{
+// SMTP with STARTTLS
+pub struct EmailSink {
+    host: String,
+    port: u16,
+    username: Option<String>,
+    password: Option<String>,
+    from: String,
+    to: Vec<String>,
+}
+
+#[async_trait(?Send)]
+impl Sink for EmailSink {
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        use lettre::{
+            transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport,
+            Tokio1Executor,
+        };
+
+        let mut builder = lettre::Message::builder()
+            .from(self.from.parse()?)
+            .subject(alert.title());
+        for to in &self.to {
+            builder = builder.to(to.parse()?);
+        }
+        let email = builder.body(format!(
+            "{}\n\ncondition: {}\nstate: {:?}\n",
+            alert.message, alert.condition, alert.state
+        ))?;
+
+        let mut transport =
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?.port(self.port);
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            transport =
+                transport.credentials(Credentials::new(username.to_owned(), password.to_owned()));
+        }
+
+        transport.build().send(email).await?;
+
+        Ok(())
+    }
+} // }}}
+
+// NtfySink {{{
+// https://docs.ntfy.sh/publish/ ; url includes the topic
+pub struct NtfySink {
+    url: String,
+    token: Option<String>,
+}
+
+#[async_trait(?Send)]
+impl Sink for NtfySink {
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let (priority, tags) = match alert.state {
+            AlertState::Firing => ("high", "warning"),
+            AlertState::Resolved => ("default", "white_check_mark"),
+        };
+
+        let mut request = reqwest::Client::new()
+            .post(&self.url)
+            .header("Title", alert.title())
+            .header("Priority", priority)
+            .header("Tags", tags)
+            .body(alert.message.clone());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+} // }}}
+
+// GotifySink {{{
+// https://gotify.net/docs/pushmsg ; url is the server root
+pub struct GotifySink {
+    url: String,
+    token: String,
+}
+
+#[async_trait(?Send)]
+impl Sink for GotifySink {
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let priority = match alert.state {
+            AlertState::Firing => 8,
+            AlertState::Resolved => 4,
+        };
+
+        let body = serde_json::json!({
+            "title": alert.title(),
+            "message": alert.message,
+            "priority": priority,
+        });
+
+        reqwest::Client::new()
+            .post(format!("{}/message", self.url.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.token)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+} // }}}
@@ -0,0 +1,135 @@
+use crate::prelude::*;
+
+use chrono::NaiveDate;
+use lxp::packet::ReadInputAll;
+use serde::Serialize;
+
+pub type BatteryStore = std::collections::HashMap<Serial, Tracker>;
+
+// Stats {{{
+// One battery's long term health, published on {datalog}/battery and kept in
+// the battery_history table
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Stats {
+    pub datalog: Serial,
+    pub soh: i8,
+    pub cycle_count: u16, // as counted by the BMS
+    // from the inverter's lifetime counters, so includes any earlier battery
+    pub equivalent_cycles: f64,
+    // kWh
+    pub rated_capacity: f64,
+    pub expected_capacity: f64,
+    pub estimated_capacity: Option<f64>, // from the last full to empty run
+    // estimated_capacity is further below expected_capacity than we'd like
+    pub fading: bool,
+    pub time: UnixTime,
+} // }}}
+
+// where the current full to empty run started
+#[derive(Clone, Debug)]
+struct Run {
+    soc: i8,
+    e_chg_all: f64,
+    e_dischg_all: f64,
+}
+
+// Tracker {{{
+// Follows one battery through its inputs. The inverter's lifetime energy
+// counters survive us restarting, so cycles are counted from those rather
+// than from anything we'd have to remember. They don't know about a battery
+// being replaced though, so cycles (and the fade expected from them) are the
+// inverter's, not the battery's.
+#[derive(Clone, Debug, Default)]
+pub struct Tracker {
+    run: Option<Run>,
+    estimated_capacity: Option<f64>,
+    date: Option<NaiveDate>,
+}
+
+impl Tracker {
+    // Some once a day, on the inverter's date, and whenever a run gives a new
+    // capacity estimate. None without a BMS, as then we don't know the rated
+    // capacity.
+    pub fn update(
+        &mut self,
+        config: &config::Battery,
+        inverter: &config::Inverter,
+        input: &ReadInputAll,
+    ) -> Option<Stats> {
+        if input.bat_capacity == 0 {
+            return None;
+        }
+
+        let estimated = self.update_run(config, input);
+
+        let date = inverter.local_time(input.time.0).date();
+        if self.date == Some(date) && !estimated {
+            return None;
+        }
+        self.date = Some(date);
+
+        Some(self.stats(config, input))
+    }
+
+    pub fn estimated_capacity(&self) -> Option<f64> {
+        self.estimated_capacity
+    }
+
+    // true if this input finished a run
+    fn update_run(&mut self, config: &config::Battery, input: &ReadInputAll) -> bool {
+        if input.soc >= config.full_soc() {
+            // keep moving the start up while it's full, so the run begins
+            // where discharging does
+            self.run = Some(Run {
+                soc: input.soc,
+                e_chg_all: input.e_chg_all,
+                e_dischg_all: input.e_dischg_all,
+            });
+            return false;
+        }
+
+        if input.soc > config.empty_soc() {
+            return false;
+        }
+
+        let run = match self.run.take() {
+            Some(run) => run,
+            None => return false,
+        };
+
+        // anything charged along the way came back out again
+        let used = (input.e_dischg_all - run.e_dischg_all) - (input.e_chg_all - run.e_chg_all);
+        let span = (run.soc - input.soc) as f64 / 100.0;
+        if used <= 0.0 || span <= 0.0 {
+            return false;
+        }
+
+        self.estimated_capacity = Some(Utils::round(used / span, 2));
+
+        true
+    }
+
+    fn stats(&self, config: &config::Battery, input: &ReadInputAll) -> Stats {
+        let rated_capacity = input.bat_capacity as f64 * config.nominal_voltage() / 1000.0;
+        let equivalent_cycles = (input.e_chg_all + input.e_dischg_all) / 2.0 / rated_capacity;
+
+        let fade = config.expected_fade() / 100.0 * equivalent_cycles / 1000.0;
+        let expected_capacity = rated_capacity * (1.0 - fade).max(0.0);
+
+        let tolerance = 1.0 - config.fade_tolerance() / 100.0;
+        let fading =
+            matches!(self.estimated_capacity, Some(e) if e < expected_capacity * tolerance);
+
+        Stats {
+            datalog: input.datalog,
+            soh: input.soh,
+            cycle_count: input.cycle_count,
+            equivalent_cycles: Utils::round(equivalent_cycles, 1),
+            rated_capacity: Utils::round(rated_capacity, 2),
+            expected_capacity: Utils::round(expected_capacity, 2),
+            estimated_capacity: self.estimated_capacity,
+            fading,
+            time: input.time.clone(),
+        }
+    }
+} // }}}
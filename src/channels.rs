@@ -6,8 +6,7 @@ pub struct Channels {
     pub to_inverter: broadcast::Sender<lxp::inverter::ChannelData>,
     pub from_mqtt: broadcast::Sender<mqtt::ChannelData>,
     pub to_mqtt: broadcast::Sender<mqtt::ChannelData>,
-    pub to_influx: broadcast::Sender<influx::ChannelData>,
-    pub to_database: broadcast::Sender<database::ChannelData>,
+    pub to_outputs: broadcast::Sender<output::ChannelData>,
     pub read_register_cache: broadcast::Sender<register_cache::ChannelData>,
     pub to_register_cache: broadcast::Sender<register_cache::ChannelData>,
 }
@@ -25,8 +24,7 @@ impl Channels {
             to_inverter: Self::channel(),
             from_mqtt: Self::channel(),
             to_mqtt: Self::channel(),
-            to_influx: Self::channel(),
-            to_database: Self::channel(),
+            to_outputs: Self::channel(),
             read_register_cache: Self::channel(),
             to_register_cache: Self::channel(),
         }
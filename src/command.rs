@@ -24,6 +24,10 @@ pub enum Command {
     AcChargeRate(config::Inverter, u16),
     AcChargeSocLimit(config::Inverter, u16),
     DischargeCutoffSocLimit(config::Inverter, u16),
+    WorkingMode(config::Inverter, lxp::packet::WorkingMode),
+    TimeSync(config::Inverter),
+    ReadHoldings(config::Inverter),
+    Reconnect(config::Inverter),
 }
 
 impl Command {
@@ -83,6 +87,10 @@ impl Command {
             DischargeCutoffSocLimit(inverter, _) => {
                 format!("{}/set/discharge_cutoff_soc_limit_pct", inverter.datalog())
             }
+            WorkingMode(inverter, _) => format!("{}/set/working_mode", inverter.datalog()),
+            TimeSync(inverter) => format!("{}/set/timesync", inverter.datalog()),
+            ReadHoldings(inverter) => format!("{}/read/holdings", inverter.datalog()),
+            Reconnect(inverter) => format!("{}/reconnect", inverter.datalog()),
         };
 
         format!("result/{}", rest)
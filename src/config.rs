@@ -1,5 +1,6 @@
 use crate::prelude::*;
 
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use serde::Deserialize;
 use serde_with::serde_as; //, OneOrMany;
 
@@ -7,15 +8,30 @@ use serde_with::serde_as; //, OneOrMany;
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     pub inverters: Vec<Inverter>,
+    #[serde(default = "Vec::new")]
+    pub systems: Vec<System>,
     //#[serde_as(deserialize_as = "OneOrMany<_>")]
     pub mqtt: Mqtt,
     //#[serde_as(deserialize_as = "OneOrMany<_>")]
     pub influx: Influx,
     #[serde(default = "Vec::new")]
     pub databases: Vec<Database>,
+    #[serde(default = "Vec::new")]
+    pub webhooks: Vec<Webhook>,
 
     pub scheduler: Option<Scheduler>,
 
+    pub alerts: Option<Alerts>,
+
+    pub pvoutput: Option<PvOutput>,
+
+    pub optimiser: Option<Optimiser>,
+
+    #[serde(default)]
+    pub hold_writes: HoldWrites,
+
+    pub battery: Option<Battery>,
+
     #[serde(default = "Config::default_loglevel")]
     pub loglevel: String,
 }
@@ -36,6 +52,15 @@ pub struct Inverter {
     pub heartbeats: Option<bool>,
     pub publish_holdings_on_connect: Option<bool>,
     pub read_timeout: Option<u64>,
+
+    // IANA name, eg Europe/London. defaults to the host's timezone
+    #[serde(default, deserialize_with = "de_timezone")]
+    pub timezone: Option<chrono_tz::Tz>,
+    pub timesync_on_connect: Option<bool>,
+    pub timesync_tolerance: Option<u64>,
+
+    // something is wired to the generator port
+    pub generator: Option<bool>,
 }
 impl Inverter {
     pub fn enabled(&self) -> bool {
@@ -69,6 +94,86 @@ impl Inverter {
     pub fn read_timeout(&self) -> u64 {
         self.read_timeout.unwrap_or(900) // 15 minutes
     }
+
+    pub fn timezone(&self) -> Option<chrono_tz::Tz> {
+        self.timezone
+    }
+
+    // the inverter has no idea of timezones, so its clock is kept on local time
+    pub fn local_time(&self, at: DateTime<Utc>) -> NaiveDateTime {
+        match self.timezone {
+            Some(tz) => at.with_timezone(&tz).naive_local(),
+            None => at.with_timezone(&chrono::Local).naive_local(),
+        }
+    }
+
+    // the other way round. an ambiguous time (clocks going back) is the first
+    // of the two, and one which doesn't exist (clocks going forward) is read
+    // with the offset from before the change, landing just after it.
+    pub fn utc_time(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        match self.timezone {
+            Some(tz) => Self::to_utc(&tz, local),
+            None => Self::to_utc(&chrono::Local, local),
+        }
+    }
+
+    fn to_utc<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+        use chrono::{LocalResult, Offset};
+
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => at.with_timezone(&Utc),
+            LocalResult::None => {
+                let before = local - chrono::Duration::hours(3);
+                let offset = tz.offset_from_utc_datetime(&before).fix().local_minus_utc();
+                Utc.from_utc_datetime(&(local - chrono::Duration::seconds(offset as i64)))
+            }
+        }
+    }
+
+    pub fn timesync_on_connect(&self) -> bool {
+        self.timesync_on_connect != Some(false)
+    }
+
+    // seconds of drift allowed before the inverter's clock is set
+    pub fn timesync_tolerance(&self) -> u64 {
+        self.timesync_tolerance.unwrap_or(120)
+    }
+
+    pub fn generator(&self) -> bool {
+        self.generator == Some(true)
+    }
+} // }}}
+
+// System {{{
+// Inverters running in parallel, published together as a virtual inverter
+#[derive(Clone, Debug, Deserialize)]
+pub struct System {
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    pub name: String,
+    // made up, but must be 10 characters like a real one
+    #[serde(deserialize_with = "de_serial")]
+    pub datalog: Serial,
+    #[serde(deserialize_with = "de_serials")]
+    pub inverters: Vec<Serial>,
+}
+impl System {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn datalog(&self) -> Serial {
+        self.datalog
+    }
+
+    pub fn inverters(&self) -> &Vec<Serial> {
+        &self.inverters
+    }
 } // }}}
 
 // HomeAssistant {{{
@@ -107,10 +212,27 @@ pub struct Mqtt {
     #[serde(default = "Config::default_mqtt_namespace")]
     pub namespace: String,
 
+    #[serde(default = "Config::default_mqtt_client_id")]
+    pub client_id: String,
+
+    pub websockets: Option<bool>,
+    pub websocket_path: Option<String>,
+
+    #[serde(default = "Config::default_mqtt_tls")]
+    pub tls: MqttTls,
+
+    pub v5: Option<bool>,
+    pub message_expiry_interval: Option<u32>,
+
+    pub site: Option<String>,
+    #[serde(default = "Config::default_mqtt_topics")]
+    pub topics: MqttTopics,
+
     #[serde(default = "Config::default_mqtt_homeassistant")]
     pub homeassistant: HomeAssistant,
 
     pub publish_individual_input: Option<bool>,
+    pub publish_named_inputs: Option<bool>,
 }
 impl Mqtt {
     pub fn enabled(&self) -> bool {
@@ -137,6 +259,40 @@ impl Mqtt {
         &self.namespace
     }
 
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    pub fn websockets(&self) -> bool {
+        self.websockets == Some(true)
+    }
+
+    pub fn websocket_path(&self) -> &str {
+        self.websocket_path.as_deref().unwrap_or("/mqtt")
+    }
+
+    pub fn tls(&self) -> &MqttTls {
+        &self.tls
+    }
+
+    pub fn v5(&self) -> bool {
+        self.v5 == Some(true)
+    }
+
+    // only used in v5 mode, applied to non-retained telemetry
+    pub fn message_expiry_interval(&self) -> u32 {
+        self.message_expiry_interval.unwrap_or(300) // 5 minutes
+    }
+
+    // available as {site} in topic templates
+    pub fn site(&self) -> &str {
+        self.site.as_deref().unwrap_or("default")
+    }
+
+    pub fn topics(&self) -> &MqttTopics {
+        &self.topics
+    }
+
     pub fn homeassistant(&self) -> &HomeAssistant {
         &self.homeassistant
     }
@@ -144,6 +300,113 @@ impl Mqtt {
     pub fn publish_individual_input(&self) -> bool {
         self.publish_individual_input == Some(true)
     }
+
+    pub fn publish_named_inputs(&self) -> bool {
+        self.publish_named_inputs == Some(true)
+    }
+} // }}}
+
+// MqttTls {{{
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MqttTls {
+    // defaults to true when a tls section is present, false when it is absent
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    // all of these are paths to PEM files
+    pub ca: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+
+    pub insecure_skip_verify: Option<bool>,
+}
+impl MqttTls {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn ca(&self) -> &Option<String> {
+        &self.ca
+    }
+
+    pub fn client_cert(&self) -> &Option<String> {
+        &self.client_cert
+    }
+
+    pub fn client_key(&self) -> &Option<String> {
+        &self.client_key
+    }
+
+    pub fn insecure_skip_verify(&self) -> bool {
+        self.insecure_skip_verify == Some(true)
+    }
+} // }}}
+
+// MqttTopics {{{
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MqttTopics {
+    #[serde(default)]
+    pub inputs: MqttTopic, // <datalog>/inputs/*
+    #[serde(default)]
+    pub input: MqttTopic, // <datalog>/input/*
+    #[serde(default)]
+    pub hold: MqttTopic, // <datalog>/hold/*
+    #[serde(default)]
+    pub param: MqttTopic, // <datalog>/param/*
+    #[serde(default)]
+    pub result: MqttTopic, // result/<datalog>/*
+    #[serde(default)]
+    pub availability: MqttTopic, // <datalog>/availability
+    #[serde(default)]
+    pub time_registers: MqttTopic, // <datalog>/ac_charge/*, ac_first/* etc
+    #[serde(default)]
+    pub other: MqttTopic, // anything else under <datalog>/, eg battery or time_drift
+    #[serde(default)]
+    pub commands: MqttTopic, // only qos is used, for the cmd/ subscriptions
+}
+impl MqttTopics {
+    pub fn for_class(&self, class: mqtt::TopicClass) -> &MqttTopic {
+        use mqtt::TopicClass::*;
+
+        match class {
+            Inputs => &self.inputs,
+            Input => &self.input,
+            Hold => &self.hold,
+            Param => &self.param,
+            Result => &self.result,
+            Availability => &self.availability,
+            TimeRegister => &self.time_registers,
+            Other => &self.other,
+        }
+    }
+
+    pub fn commands(&self) -> &MqttTopic {
+        &self.commands
+    }
+} // }}}
+
+// MqttTopic {{{
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MqttTopic {
+    #[serde(default, deserialize_with = "de_qos")]
+    pub qos: Option<u8>,
+    pub retain: Option<bool>,
+    pub template: Option<String>,
+}
+impl MqttTopic {
+    // None means use the default for this class
+    pub fn qos(&self) -> Option<u8> {
+        self.qos
+    }
+
+    // None means use whatever the message would normally be sent with
+    pub fn retain(&self) -> Option<bool> {
+        self.retain
+    }
+
+    pub fn template(&self) -> Option<&str> {
+        self.template.as_deref()
+    }
 } // }}}
 
 // Influx {{{
@@ -157,6 +420,7 @@ pub struct Influx {
     pub password: Option<String>,
 
     pub database: String,
+    pub batch_size: Option<usize>,
 }
 impl Influx {
     pub fn enabled(&self) -> bool {
@@ -178,6 +442,11 @@ impl Influx {
     pub fn database(&self) -> &str {
         &self.database
     }
+
+    // inputs to collect before sending them in one request
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(1).max(1)
+    }
 } // }}}
 
 // Database {{{
@@ -198,6 +467,47 @@ impl Database {
     }
 } // }}}
 
+// Webhook {{{
+#[derive(Clone, Debug, Deserialize)]
+pub struct Webhook {
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    pub url: String,
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    pub secret: Option<String>,
+    pub min_interval: Option<u64>,
+    pub retries: Option<u32>,
+}
+impl Webhook {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn headers(&self) -> &std::collections::HashMap<String, String> {
+        &self.headers
+    }
+
+    // used to sign the body with HMAC-SHA256, if set
+    pub fn secret(&self) -> &Option<String> {
+        &self.secret
+    }
+
+    // minimum seconds between posting inputs; 0 posts every one
+    pub fn min_interval(&self) -> u64 {
+        self.min_interval.unwrap_or(0)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(3)
+    }
+} // }}}
+
 // Scheduler {{{
 #[derive(Clone, Debug, Deserialize)]
 pub struct Scheduler {
@@ -205,6 +515,16 @@ pub struct Scheduler {
     pub enabled: bool,
 
     pub timesync_cron: Option<String>,
+    pub timesync_on_dst_change: Option<bool>,
+
+    pub tariff: Option<Tariff>,
+
+    #[serde(default = "Vec::new")]
+    pub jobs: Vec<Job>,
+
+    // only needed for solar jobs
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
 }
 impl Scheduler {
     pub fn enabled(&self) -> bool {
@@ -214,6 +534,381 @@ impl Scheduler {
     pub fn timesync_cron(&self) -> &Option<String> {
         &self.timesync_cron
     }
+
+    pub fn timesync_on_dst_change(&self) -> bool {
+        self.timesync_on_dst_change != Some(false)
+    }
+
+    pub fn tariff(&self) -> &Option<Tariff> {
+        &self.tariff
+    }
+
+    pub fn jobs(&self) -> &Vec<Job> {
+        &self.jobs
+    }
+
+    // (latitude, longitude), north and east positive
+    pub fn location(&self) -> Option<(f64, f64)> {
+        Some((self.latitude?, self.longitude?))
+    }
+} // }}}
+
+// Job {{{
+// A command run on a schedule, as if it had arrived on cmd/{datalog}/{command}.
+// Either cron or solar (eg "sunset-30m") says when.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Job {
+    pub name: String,
+    pub cron: Option<String>,
+    pub solar: Option<String>,
+    pub command: String,
+    #[serde(default)]
+    pub payload: String,
+    // empty means every enabled inverter
+    #[serde(default = "Vec::new", deserialize_with = "de_serials")]
+    pub inverters: Vec<Serial>,
+}
+impl Job {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cron(&self) -> &Option<String> {
+        &self.cron
+    }
+
+    pub fn solar(&self) -> &Option<String> {
+        &self.solar
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    pub fn inverters(&self) -> &Vec<Serial> {
+        &self.inverters
+    }
+} // }}}
+
+// Tariff {{{
+#[derive(Clone, Debug, Deserialize)]
+pub struct Tariff {
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    pub plan_cron: Option<String>,
+    pub target_soc: u16,
+    pub charge_minutes: Option<u32>,
+    pub discharge_minutes: Option<u32>,
+
+    // static time-of-use bands, used if prices isn't set
+    #[serde(default = "Vec::new")]
+    pub bands: Vec<TariffBand>,
+    // file path or http(s) url of Octopus Agile style half-hourly prices
+    pub prices: Option<String>,
+}
+impl Tariff {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Agile prices for tomorrow are published around 4pm
+    pub fn plan_cron(&self) -> &str {
+        self.plan_cron.as_deref().unwrap_or("0 17 * * *")
+    }
+
+    pub fn target_soc(&self) -> u16 {
+        self.target_soc.min(100)
+    }
+
+    pub fn charge_minutes(&self) -> u32 {
+        self.charge_minutes.unwrap_or(180)
+    }
+
+    // None leaves forced discharge alone
+    pub fn discharge_minutes(&self) -> Option<u32> {
+        self.discharge_minutes
+    }
+
+    pub fn bands(&self) -> &Vec<TariffBand> {
+        &self.bands
+    }
+
+    pub fn prices(&self) -> &Option<String> {
+        &self.prices
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TariffBand {
+    pub start: String, // HH:MM
+    pub end: String,   // HH:MM, may be before start to span midnight
+    pub price: f64,
+} // }}}
+
+// Alerts {{{
+#[derive(Clone, Debug, Deserialize)]
+pub struct Alerts {
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    pub debounce: Option<u64>,
+    pub offline_minutes: Option<u64>,
+    pub soc_below: Option<i8>,
+    pub faults: Option<bool>,
+    pub warnings: Option<bool>,
+    pub cell_imbalance: Option<bool>,
+    pub grid_loss: Option<bool>,
+    pub capacity_fade: Option<bool>,
+
+    #[serde(default = "Vec::new")]
+    pub sinks: Vec<AlertSink>,
+}
+impl Alerts {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // seconds a condition must persist, starting or clearing, before we say so
+    pub fn debounce(&self) -> u64 {
+        self.debounce.unwrap_or(60)
+    }
+
+    pub fn offline_minutes(&self) -> u64 {
+        self.offline_minutes.unwrap_or(10)
+    }
+
+    pub fn soc_below(&self) -> Option<i8> {
+        self.soc_below
+    }
+
+    pub fn faults(&self) -> bool {
+        self.faults != Some(false)
+    }
+
+    pub fn warnings(&self) -> bool {
+        self.warnings != Some(false)
+    }
+
+    pub fn cell_imbalance(&self) -> bool {
+        self.cell_imbalance != Some(false)
+    }
+
+    pub fn grid_loss(&self) -> bool {
+        self.grid_loss != Some(false)
+    }
+
+    // needs battery tracking enabled too
+    pub fn capacity_fade(&self) -> bool {
+        self.capacity_fade != Some(false)
+    }
+
+    pub fn sinks(&self) -> &Vec<AlertSink> {
+        &self.sinks
+    }
+} // }}}
+
+// PvOutput {{{
+#[derive(Clone, Debug, Deserialize)]
+pub struct PvOutput {
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "Config::default_pvoutput_url")]
+    pub url: String,
+    pub system_id: String,
+    pub api_key: String,
+    #[serde(deserialize_with = "de_serial")]
+    pub datalog: Serial,
+
+    pub interval: Option<u32>,
+    pub end_of_day: Option<bool>,
+}
+impl PvOutput {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn system_id(&self) -> &str {
+        &self.system_id
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    pub fn datalog(&self) -> Serial {
+        self.datalog
+    }
+
+    // minutes per status update; should match the system's status interval on pvoutput.org
+    pub fn interval(&self) -> u32 {
+        self.interval.unwrap_or(5).clamp(1, 60)
+    }
+
+    pub fn end_of_day(&self) -> bool {
+        self.end_of_day != Some(false)
+    }
+} // }}}
+
+// HoldWrites {{{
+// Protects the inverter's EEPROM from over-eager holding register writes
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HoldWrites {
+    pub skip_unchanged: Option<bool>,
+    pub debounce: Option<u64>,
+    pub daily_budget: Option<u32>,
+}
+impl HoldWrites {
+    // don't write a value the register is already known to hold
+    pub fn skip_unchanged(&self) -> bool {
+        self.skip_unchanged != Some(false)
+    }
+
+    // seconds to wait for a later write to the same register, which replaces
+    // the earlier one. 0 writes straight away
+    pub fn debounce(&self) -> u64 {
+        self.debounce.unwrap_or(0)
+    }
+
+    // writes allowed per inverter per day; None is unlimited
+    pub fn daily_budget(&self) -> Option<u32> {
+        self.daily_budget
+    }
+} // }}}
+
+// Battery {{{
+// Long term battery health tracking; the same settings apply to every inverter's battery
+#[derive(Clone, Debug, Deserialize)]
+pub struct Battery {
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    pub nominal_voltage: Option<f64>,
+    pub full_soc: Option<i8>,
+    pub empty_soc: Option<i8>,
+    pub expected_fade: Option<f64>,
+    pub fade_tolerance: Option<f64>,
+}
+impl Battery {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // turns the BMS's Ah into kWh; 51.2 suits the usual 16 cell LFP pack
+    pub fn nominal_voltage(&self) -> f64 {
+        self.nominal_voltage.unwrap_or(51.2)
+    }
+
+    // a capacity estimate runs from the last time SOC was at least full_soc
+    // to when it next drops to empty_soc
+    pub fn full_soc(&self) -> i8 {
+        self.full_soc.unwrap_or(95)
+    }
+
+    pub fn empty_soc(&self) -> i8 {
+        self.empty_soc.unwrap_or(20)
+    }
+
+    // percent of rated capacity lost per 1000 equivalent full cycles
+    pub fn expected_fade(&self) -> f64 {
+        self.expected_fade.unwrap_or(3.0)
+    }
+
+    // percent below the expected capacity an estimate can be before we warn
+    pub fn fade_tolerance(&self) -> f64 {
+        self.fade_tolerance.unwrap_or(5.0)
+    }
+} // }}}
+
+// Optimiser {{{
+#[derive(Clone, Debug, Deserialize)]
+pub struct Optimiser {
+    #[serde(default = "Config::default_enabled")]
+    pub enabled: bool,
+
+    #[serde(deserialize_with = "de_serial")]
+    pub datalog: Serial,
+    // watts at a 100% charge/discharge rate
+    pub battery_power: u32,
+
+    // watts; 0 for zero export. None leaves the charge rate alone
+    pub export_limit: Option<u32>,
+    pub reserve_soc: Option<u16>,
+    // HH:MM local time to stop holding reserve_soc; None holds it all day
+    pub reserve_until: Option<String>,
+
+    pub hysteresis: Option<u32>,
+    pub min_write_interval: Option<u64>,
+}
+impl Optimiser {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn datalog(&self) -> Serial {
+        self.datalog
+    }
+
+    pub fn battery_power(&self) -> u32 {
+        self.battery_power.max(1)
+    }
+
+    pub fn export_limit(&self) -> Option<u32> {
+        self.export_limit
+    }
+
+    pub fn reserve_soc(&self) -> Option<u16> {
+        self.reserve_soc
+    }
+
+    pub fn reserve_until(&self) -> &Option<String> {
+        &self.reserve_until
+    }
+
+    // watts either side of the goal we don't bother correcting
+    pub fn hysteresis(&self) -> u32 {
+        self.hysteresis.unwrap_or(100)
+    }
+
+    // seconds between writes to any one register, to spare the inverter's EEPROM
+    pub fn min_write_interval(&self) -> u64 {
+        self.min_write_interval.unwrap_or(300)
+    }
+} // }}}
+
+// AlertSink {{{
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AlertSink {
+    Mqtt,
+    Webhook {
+        url: String,
+    },
+    Email {
+        host: String,
+        port: Option<u16>,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    Ntfy {
+        url: String,
+        token: Option<String>,
+    },
+    Gotify {
+        url: String,
+        token: String,
+    },
 } // }}}
 
 #[derive(Debug)]
@@ -275,16 +970,47 @@ impl ConfigWrapper {
 
         let r = match target_inverter {
             All => inverters,
-            Serial(datalog) => inverters
-                .iter()
-                .filter(|i| i.datalog == datalog)
-                .cloned()
-                .collect(),
+            // commands to a system go to all of its inverters
+            Serial(datalog) => match self.enabled_system_with_datalog(datalog) {
+                Some(system) => inverters
+                    .iter()
+                    .filter(|i| system.inverters().contains(&i.datalog))
+                    .cloned()
+                    .collect(),
+                None => inverters
+                    .iter()
+                    .filter(|i| i.datalog == datalog)
+                    .cloned()
+                    .collect(),
+            },
         };
 
         Ok(r)
     }
 
+    pub fn systems(&self) -> Ref<Vec<System>> {
+        Ref::map(self.config.borrow(), |b| &b.systems)
+    }
+
+    pub fn enabled_systems(&self) -> Vec<System> {
+        self.systems()
+            .iter()
+            .filter(|system| system.enabled())
+            .cloned()
+            .collect()
+    }
+
+    pub fn enabled_system_with_datalog(&self, datalog: Serial) -> Option<System> {
+        self.enabled_systems()
+            .into_iter()
+            .find(|system| system.datalog() == datalog)
+    }
+
+    pub fn set_systems(&self, new: Vec<System>) {
+        let mut c = self.config.borrow_mut();
+        c.systems = new;
+    }
+
     pub fn mqtt(&self) -> Ref<Mqtt> {
         Ref::map(self.config.borrow(), |b| &b.mqtt)
     }
@@ -322,10 +1048,89 @@ impl ConfigWrapper {
             .collect()
     }
 
+    pub fn webhooks(&self) -> Ref<Vec<Webhook>> {
+        Ref::map(self.config.borrow(), |b| &b.webhooks)
+    }
+
+    pub fn have_enabled_webhook(&self) -> bool {
+        self.webhooks().iter().any(|webhook| webhook.enabled)
+    }
+
+    pub fn enabled_webhooks(&self) -> Vec<Webhook> {
+        self.webhooks()
+            .iter()
+            .filter(|webhook| webhook.enabled)
+            .cloned()
+            .collect()
+    }
+
     pub fn scheduler(&self) -> Ref<Option<Scheduler>> {
         Ref::map(self.config.borrow(), |b| &b.scheduler)
     }
 
+    pub fn alerts(&self) -> Ref<Option<Alerts>> {
+        Ref::map(self.config.borrow(), |b| &b.alerts)
+    }
+
+    pub fn alerts_mut(&self) -> RefMut<Option<Alerts>> {
+        RefMut::map(self.config.borrow_mut(), |b: &mut Config| &mut b.alerts)
+    }
+
+    pub fn have_enabled_alerts(&self) -> bool {
+        matches!(&*self.alerts(), Some(alerts) if alerts.enabled())
+    }
+
+    pub fn pvoutput(&self) -> Ref<Option<PvOutput>> {
+        Ref::map(self.config.borrow(), |b| &b.pvoutput)
+    }
+
+    // anything listening on to_outputs; Outputs, and the optimiser
+    pub fn have_enabled_output(&self) -> bool {
+        self.influx().enabled()
+            || self.have_enabled_database()
+            || self.have_enabled_webhook()
+            || self.have_enabled_pvoutput()
+            || self.have_enabled_optimiser()
+    }
+
+    pub fn have_enabled_pvoutput(&self) -> bool {
+        matches!(&*self.pvoutput(), Some(pvoutput) if pvoutput.enabled())
+    }
+
+    pub fn hold_writes(&self) -> Ref<HoldWrites> {
+        Ref::map(self.config.borrow(), |b| &b.hold_writes)
+    }
+
+    pub fn hold_writes_mut(&self) -> RefMut<HoldWrites> {
+        RefMut::map(self.config.borrow_mut(), |b: &mut Config| {
+            &mut b.hold_writes
+        })
+    }
+
+    pub fn battery(&self) -> Ref<Option<Battery>> {
+        Ref::map(self.config.borrow(), |b| &b.battery)
+    }
+
+    pub fn battery_mut(&self) -> RefMut<Option<Battery>> {
+        RefMut::map(self.config.borrow_mut(), |b: &mut Config| &mut b.battery)
+    }
+
+    pub fn enabled_battery(&self) -> Option<Battery> {
+        self.battery().clone().filter(|battery| battery.enabled())
+    }
+
+    pub fn optimiser(&self) -> Ref<Option<Optimiser>> {
+        Ref::map(self.config.borrow(), |b| &b.optimiser)
+    }
+
+    pub fn optimiser_mut(&self) -> RefMut<Option<Optimiser>> {
+        RefMut::map(self.config.borrow_mut(), |b: &mut Config| &mut b.optimiser)
+    }
+
+    pub fn have_enabled_optimiser(&self) -> bool {
+        matches!(&*self.optimiser(), Some(optimiser) if optimiser.enabled())
+    }
+
     pub fn loglevel(&self) -> String {
         self.config.borrow().loglevel.to_owned()
     }
@@ -346,6 +1151,20 @@ impl Config {
         "lxp".to_string()
     }
 
+    // two bridges connecting with the same client_id will repeatedly kick each other
+    // off the broker, so default to something that differs between hosts.
+    fn default_mqtt_client_id() -> String {
+        format!("lxp-bridge-{}", Utils::hostname())
+    }
+
+    fn default_mqtt_tls() -> MqttTls {
+        MqttTls::default()
+    }
+
+    fn default_mqtt_topics() -> MqttTopics {
+        MqttTopics::default()
+    }
+
     fn default_mqtt_homeassistant() -> HomeAssistant {
         HomeAssistant {
             enabled: Self::default_enabled(),
@@ -357,6 +1176,10 @@ impl Config {
         "homeassistant".to_string()
     }
 
+    fn default_pvoutput_url() -> String {
+        "https://pvoutput.org".to_string()
+    }
+
     fn default_enabled() -> bool {
         true
     }
@@ -373,3 +1196,35 @@ where
     let raw = String::deserialize(deserializer)?;
     raw.parse().map_err(serde::de::Error::custom)
 }
+
+fn de_serials<'de, D>(deserializer: D) -> Result<Vec<Serial>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|raw| raw.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn de_timezone<'de, D>(deserializer: D) -> Result<Option<chrono_tz::Tz>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|raw| raw.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn de_qos<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match Option::<u8>::deserialize(deserializer)? {
+        Some(qos) if qos > 2 => Err(serde::de::Error::custom(format!(
+            "qos must be 0, 1 or 2, not {}",
+            qos
+        ))),
+        r => Ok(r),
+    }
+}
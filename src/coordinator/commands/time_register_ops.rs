@@ -27,7 +27,7 @@ pub enum Action {
 }
 
 impl Action {
-    fn register(&self) -> Result<u16> {
+    pub fn register(&self) -> Result<u16> {
         use Action::*;
         match self {
             AcCharge(1) => Ok(68),
@@ -1,6 +1,6 @@
 use crate::prelude::*;
 
-use chrono::TimeZone;
+use chrono::{NaiveDate, NaiveDateTime};
 
 use lxp::{
     inverter::WaitForReply,
@@ -17,7 +17,8 @@ impl TimeSync {
         Self { channels, inverter }
     }
 
-    pub async fn run(&self) -> Result<()> {
+    // returns how far the inverter's clock was out; positive means it was ahead
+    pub async fn run(&self) -> Result<chrono::Duration> {
         let packet = Packet::TranslatedData(TranslatedData {
             datalog: self.inverter.datalog(),
             device_function: DeviceFunction::ReadHold,
@@ -37,57 +38,61 @@ impl TimeSync {
             bail!("send(to_inverter) failed - channel closed?");
         }
 
-        if let Packet::TranslatedData(td) = receiver.wait_for_reply(&packet).await? {
-            let year = td.values[0] as u32;
-            let month = td.values[1] as u32;
-            let day = td.values[2] as u32;
-            let hour = td.values[3] as u32;
-            let minute = td.values[4] as u32;
-            let second = td.values[5] as u32;
-
-            // pretend inverter is in Utc to avoid ambiguous time conversions and timezones
-            let dt = chrono::Utc
-                .with_ymd_and_hms(2000 + year as i32, month, day, hour, minute, second)
-                .unwrap();
-
-            // get current time in Utc, then add the appropriate offset so we can compare to
-            // inverter time correctly
-            let offset_in_sec =
-                chrono::Duration::seconds(chrono::Local::now().offset().local_minus_utc() as i64);
-            let now = Utils::utc() + offset_in_sec;
-
-            debug!(
-                "inverter {} time difference is {}",
+        let Packet::TranslatedData(td) = receiver.wait_for_reply(&packet).await? else {
+            bail!("didn't get expected reply from inverter");
+        };
+
+        let year = td.values[0] as i32;
+        let month = td.values[1] as u32;
+        let day = td.values[2] as u32;
+        let hour = td.values[3] as u32;
+        let minute = td.values[4] as u32;
+        let second = td.values[5] as u32;
+
+        // the inverter only knows local time, so compare it to ours in the inverter's timezone
+        let dt = NaiveDate::from_ymd_opt(2000 + year, month, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, second))
+            .ok_or_else(|| anyhow!("inverter sent invalid time {:?}", td.values))?;
+        let now = self.inverter.local_time(Utils::utc());
+        let drift = dt - now;
+
+        debug!(
+            "inverter {} time difference is {}",
+            self.inverter.datalog(),
+            drift
+        );
+
+        let limit = chrono::Duration::seconds(self.inverter.timesync_tolerance() as i64);
+
+        if drift > limit || -drift > limit {
+            info!(
+                "inverter {} clock is out by {}, setting it",
                 self.inverter.datalog(),
-                dt - now
+                drift
             );
 
-            let limit = chrono::Duration::seconds(120);
-
-            if dt - now > limit || now - dt > limit {
-                let packet = self.set_time_packet(now);
-
-                if self
-                    .channels
-                    .to_inverter
-                    .send(lxp::inverter::ChannelData::Packet(packet.clone()))
-                    .is_err()
-                {
-                    bail!("send(to_inverter) failed - channel closed?");
-                }
-
-                if let Packet::TranslatedData(_) = receiver.wait_for_reply(&packet).await? {
-                    debug!("time set ok");
-                } else {
-                    warn!("time set didn't get confirmation reply!");
-                }
+            let packet = self.set_time_packet(now);
+
+            if self
+                .channels
+                .to_inverter
+                .send(lxp::inverter::ChannelData::Packet(packet.clone()))
+                .is_err()
+            {
+                bail!("send(to_inverter) failed - channel closed?");
+            }
+
+            if let Packet::TranslatedData(_) = receiver.wait_for_reply(&packet).await? {
+                debug!("time set ok");
+            } else {
+                warn!("time set didn't get confirmation reply!");
             }
         }
 
-        Ok(())
+        Ok(drift)
     }
 
-    fn set_time_packet(&self, now: chrono::DateTime<chrono::Utc>) -> Packet {
+    fn set_time_packet(&self, now: NaiveDateTime) -> Packet {
         use chrono::{Datelike, Timelike};
 
         Packet::TranslatedData(TranslatedData {
@@ -9,20 +9,66 @@ pub enum ChannelData {
     Shutdown,
 }
 
+// Where a command's OK or FAIL goes. A debounced write holds on to this until
+// it's actually been made.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reply {
+    pub topic: String,
+    pub target: Option<mqtt::ResponseTarget>,
+}
+
+// whether a command is finished with, or will reply later
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Done,
+    Deferred,
+}
+
 pub type InputsStore = std::collections::HashMap<Serial, lxp::packet::ReadInputs>;
+pub type DeviceInfoStore = std::collections::HashMap<Serial, lxp::packet::DeviceInfo>;
 
 pub struct Coordinator {
     config: ConfigWrapper,
     channels: Channels,
+    device_info: RefCell<DeviceInfoStore>,
+    energy: RefCell<energy::EnergyStore>,
+    battery: RefCell<battery::BatteryStore>,
+    hold_writes: RefCell<hold_writes::Tracker>,
+    systems: RefCell<Vec<system::Aggregator>>,
 }
 
 impl Coordinator {
     pub fn new(config: ConfigWrapper, channels: Channels) -> Self {
-        Self { config, channels }
+        let systems = config
+            .enabled_systems()
+            .into_iter()
+            .map(system::Aggregator::new)
+            .collect();
+
+        Self {
+            config,
+            channels,
+            device_info: RefCell::new(DeviceInfoStore::new()),
+            energy: RefCell::new(energy::EnergyStore::new()),
+            battery: RefCell::new(battery::BatteryStore::new()),
+            hold_writes: RefCell::new(hold_writes::Tracker::new()),
+            systems: RefCell::new(systems),
+        }
     }
 
     pub async fn start(&self) -> Result<()> {
-        futures::try_join!(self.inverter_receiver(), self.mqtt_receiver())?;
+        // nothing is connected yet, whatever was retained from our last run.
+        // mqtt is started ahead of us in app(), so its sender is listening
+        for inverter in self.config.enabled_inverters() {
+            let _ = self.publish_availability(inverter.datalog(), false);
+        }
+
+        futures::try_join!(
+            self.inverter_receiver(),
+            self.mqtt_receiver(),
+            self.home_assistant_receiver(),
+            self.hold_write_flusher()
+        )?;
 
         Ok(())
     }
@@ -37,31 +83,132 @@ impl Coordinator {
     }
 
     async fn mqtt_receiver(&self) -> Result<()> {
+        use mqtt::ChannelData::*;
+
+        let mut receiver = self.channels.from_mqtt.subscribe();
+
+        loop {
+            match receiver.recv().await? {
+                Message(message) => {
+                    let _ = self.process_message(message, None, false).await;
+                }
+                Internal(message) => {
+                    let _ = self.process_message(message, None, true).await;
+                }
+                Request(message, target) => {
+                    let _ = self.process_message(message, Some(target), false).await;
+                }
+                HomeAssistantOnline => {} // home_assistant_receiver deals with this
+                Response(_, _) | HomeAssistant(_) => {} // never sent to this channel
+                Shutdown => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Runs alongside mqtt_receiver so commands aren't held up while we wait on
+    // inverters to answer the input reads.
+    async fn home_assistant_receiver(&self) -> Result<()> {
+        use mqtt::ChannelData::*;
+
         let mut receiver = self.channels.from_mqtt.subscribe();
 
-        while let mqtt::ChannelData::Message(message) = receiver.recv().await? {
-            let _ = self.process_message(message).await;
+        loop {
+            match receiver.recv().await? {
+                HomeAssistantOnline => {
+                    if let Err(e) = self.home_assistant_online().await {
+                        error!("{}", e);
+                    }
+                }
+                Shutdown => break,
+                _ => {}
+            }
         }
 
         Ok(())
     }
 
-    async fn process_message(&self, message: mqtt::Message) -> Result<()> {
+    // Home Assistant forgets discovery configs and any non-retained state when it
+    // restarts, so send discovery again and ask the inverters for fresh inputs.
+    async fn home_assistant_online(&self) -> Result<()> {
+        for system in self.config.enabled_systems() {
+            self.publish_system_discovery(&system)?;
+        }
+
+        let inverters = self.config.enabled_inverters();
+
+        for inverter in &inverters {
+            self.publish_home_assistant_discovery(inverter)?;
+        }
+
+        // holdings are retained so HA will already have those. one offline
+        // inverter shouldn't delay the others, so read them all at once.
+        let reads = inverters.into_iter().map(|inverter| async move {
+            for register in [0_u16, 40, 80, 120] {
+                if let Err(e) = self.read_inputs(inverter.clone(), register, 40).await {
+                    warn!("inverter {}: {}", inverter.datalog(), e);
+                }
+            }
+        });
+        futures::future::join_all(reads).await;
+
+        Ok(())
+    }
+
+    fn publish_home_assistant_discovery(&self, inverter: &config::Inverter) -> Result<()> {
+        let device_info = self.device_info.borrow().get(&inverter.datalog()).cloned();
+        let ha = home_assistant::Config::new(inverter, &self.config.mqtt())
+            .with_device_info(device_info);
+
+        for message in ha.all()? {
+            let channel_data = mqtt::ChannelData::HomeAssistant(message);
+            if self.channels.to_mqtt.send(channel_data).is_err() {
+                bail!("send(to_mqtt) failed - channel closed?");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn publish_system_discovery(&self, system: &config::System) -> Result<()> {
+        if !self.config.mqtt().homeassistant().enabled() {
+            return Ok(());
+        }
+
+        let ha = home_assistant::Config::for_system(system, &self.config.mqtt());
+
+        for message in ha.system() {
+            let channel_data = mqtt::ChannelData::HomeAssistant(message);
+            if self.channels.to_mqtt.send(channel_data).is_err() {
+                bail!("send(to_mqtt) failed - channel closed?");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn process_message(
+        &self,
+        message: mqtt::Message,
+        response_target: Option<mqtt::ResponseTarget>,
+        internal: bool,
+    ) -> Result<()> {
         for inverter in self.config.inverters_for_message(&message)? {
             match message.to_command(inverter) {
                 Ok(command) => {
                     debug!("parsed command {:?}", command);
 
-                    let topic_reply = command.to_result_topic();
-                    let result = self.process_command(command).await;
-
-                    let reply = mqtt::ChannelData::Message(mqtt::Message {
-                        topic: topic_reply,
-                        retain: false,
-                        payload: if result.is_ok() { "OK" } else { "FAIL" }.to_string(),
-                    });
-                    if self.channels.to_mqtt.send(reply).is_err() {
-                        bail!("send(to_mqtt) failed - channel closed?");
+                    let reply = Reply {
+                        topic: command.to_result_topic(),
+                        target: response_target.clone(),
+                    };
+                    // our own commands are written straight away, so their
+                    // result is known before the next one is sent
+                    let deferrable = (!internal).then_some(&reply);
+                    match self.process_command(command, deferrable).await {
+                        Ok(Outcome::Deferred) => {}
+                        result => self.send_reply(&reply, result.is_ok())?,
                     }
                 }
                 Err(err) => {
@@ -73,12 +220,39 @@ impl Coordinator {
         Ok(())
     }
 
-    async fn process_command(&self, command: Command) -> Result<()> {
+    fn send_reply(&self, reply: &Reply, ok: bool) -> Result<()> {
+        let message = mqtt::Message {
+            topic: reply.topic.to_owned(),
+            retain: false,
+            payload: if ok { "OK" } else { "FAIL" }.to_string(),
+        };
+
+        // v5 requesters that asked for a response get one on their own topic,
+        // with their correlation data echoed back. result/ is still published
+        // so existing consumers keep working.
+        if let Some(target) = &reply.target {
+            let response = mqtt::ChannelData::Response(message.clone(), target.clone());
+            if self.channels.to_mqtt.send(response).is_err() {
+                bail!("send(to_mqtt) failed - channel closed?");
+            }
+        }
+
+        let message = mqtt::ChannelData::Message(message);
+        if self.channels.to_mqtt.send(message).is_err() {
+            bail!("send(to_mqtt) failed - channel closed?");
+        }
+
+        Ok(())
+    }
+
+    // holding register writes may be debounced when there's a reply to send
+    // later, so return their own outcome
+    async fn process_command(&self, command: Command, reply: Option<&Reply>) -> Result<Outcome> {
         use commands::time_register_ops::Action;
         use lxp::packet::{Register, RegisterBit};
         use Command::*;
 
-        match command {
+        let result = match command {
             ReadInputs(inverter, 1) => self.read_inputs(inverter, 0_u16, 40).await,
             ReadInputs(inverter, 2) => self.read_inputs(inverter, 40_u16, 40).await,
             ReadInputs(inverter, 3) => self.read_inputs(inverter, 80_u16, 40).await,
@@ -105,7 +279,9 @@ impl Coordinator {
                 self.read_time_register(inverter, Action::ForcedDischarge(num))
                     .await
             }
-            SetHold(inverter, register, value) => self.set_hold(inverter, register, value).await,
+            SetHold(inverter, register, value) => {
+                return self.set_hold(inverter, register, value, reply).await
+            }
             WriteParam(inverter, register, value) => {
                 self.write_param(inverter, register, value).await
             }
@@ -154,29 +330,49 @@ impl Coordinator {
                 .await
             }
             ChargeRate(inverter, pct) => {
-                self.set_hold(inverter, Register::ChargePowerPercentCmd, pct)
+                return self
+                    .set_hold(inverter, Register::ChargePowerPercentCmd, pct, reply)
                     .await
             }
             DischargeRate(inverter, pct) => {
-                self.set_hold(inverter, Register::DischgPowerPercentCmd, pct)
+                return self
+                    .set_hold(inverter, Register::DischgPowerPercentCmd, pct, reply)
                     .await
             }
 
             AcChargeRate(inverter, pct) => {
-                self.set_hold(inverter, Register::AcChargePowerCmd, pct)
+                return self
+                    .set_hold(inverter, Register::AcChargePowerCmd, pct, reply)
                     .await
             }
 
             AcChargeSocLimit(inverter, pct) => {
-                self.set_hold(inverter, Register::AcChargeSocLimit, pct)
+                return self
+                    .set_hold(inverter, Register::AcChargeSocLimit, pct, reply)
                     .await
             }
 
             DischargeCutoffSocLimit(inverter, pct) => {
-                self.set_hold(inverter, Register::DischgCutOffSocEod, pct)
+                return self
+                    .set_hold(inverter, Register::DischgCutOffSocEod, pct, reply)
                     .await
             }
-        }
+            WorkingMode(inverter, mode) => {
+                return self.set_working_mode(inverter, mode, reply).await
+            }
+            TimeSync(inverter) => self.timesync(inverter).await,
+            ReadHoldings(inverter) => self.read_holdings(inverter).await,
+            Reconnect(inverter) => {
+                // the inverter sender bails on this, and the usual reconnect logic kicks in
+                let channel_data = lxp::inverter::ChannelData::Disconnect(inverter.datalog());
+                if self.channels.to_inverter.send(channel_data).is_err() {
+                    bail!("send(to_inverter) failed - channel closed?");
+                }
+                Ok(())
+            }
+        };
+
+        result.map(|_| Outcome::Done)
     }
 
     async fn read_inputs<U>(
@@ -268,6 +464,33 @@ impl Coordinator {
         action: commands::time_register_ops::Action,
         values: [u8; 4],
     ) -> Result<()> {
+        // two holding registers, start and end, so tracked like any other write
+        let register = action.register()?;
+        let wanted = [
+            (register, u16::from_le_bytes([values[0], values[1]])),
+            (register + 1, u16::from_le_bytes([values[2], values[3]])),
+        ];
+
+        if self.config.hold_writes().skip_unchanged() {
+            let datalog = inverter.datalog();
+            let known = [
+                RegisterCache::get(&self.channels, datalog, register).await,
+                RegisterCache::get(&self.channels, datalog, register + 1).await,
+            ];
+            if known == wanted.map(|(_, v)| Some(v)) {
+                debug!(
+                    "inverter {}: registers {}-{} are already {:?}, not writing",
+                    inverter.datalog(),
+                    register,
+                    register + 1,
+                    values
+                );
+                return Ok(());
+            }
+        }
+
+        self.check_hold_writes(&inverter, 2)?;
+
         commands::time_register_ops::SetTimeRegister::new(
             self.channels.clone(),
             inverter.clone(),
@@ -275,20 +498,179 @@ impl Coordinator {
             values,
         )
         .run()
-        .await
+        .await?;
+
+        for (register, _) in wanted {
+            self.record_hold_write(&inverter, register)?;
+        }
+
+        Ok(())
     }
 
-    async fn set_hold<U>(&self, inverter: config::Inverter, register: U, value: u16) -> Result<()>
+    async fn set_hold<U>(
+        &self,
+        inverter: config::Inverter,
+        register: U,
+        value: u16,
+        reply: Option<&Reply>,
+    ) -> Result<Outcome>
     where
         U: Into<u16>,
     {
+        let register = register.into();
+
+        // HA sliders can send a burst of these; only the last one gets written,
+        // and everyone who asked hears how that went. register 21 is also
+        // written bit by bit from what the inverter holds now, which a stale
+        // debounced value would undo, so it always goes straight out
+        if self.config.hold_writes().debounce() > 0
+            && reply.is_some()
+            && register != u16::from(lxp::packet::Register::Register21)
+        {
+            self.hold_writes.borrow_mut().queue(
+                inverter,
+                register,
+                value,
+                reply.cloned(),
+                std::time::Instant::now(),
+            );
+            return Ok(Outcome::Deferred);
+        }
+
+        self.write_hold(inverter, register, value).await?;
+
+        Ok(Outcome::Done)
+    }
+
+    async fn write_hold(
+        &self,
+        inverter: config::Inverter,
+        register: u16,
+        value: u16,
+    ) -> Result<()> {
+        let datalog = inverter.datalog();
+
+        if self.config.hold_writes().skip_unchanged()
+            && RegisterCache::get(&self.channels, datalog, register).await == Some(value)
+        {
+            debug!(
+                "inverter {}: register {} is already {}, not writing",
+                datalog, register, value
+            );
+            return Ok(());
+        }
+
+        self.check_hold_writes(&inverter, 1)?;
+
         commands::set_hold::SetHold::new(self.channels.clone(), inverter.clone(), register, value)
             .run()
             .await?;
 
+        self.record_hold_write(&inverter, register)
+    }
+
+    // fails if the daily budget hasn't room for this many more writes
+    fn check_hold_writes(&self, inverter: &config::Inverter, writes: u32) -> Result<()> {
+        let today = inverter.local_time(Utils::utc()).date();
+        let daily_budget = self.config.hold_writes().daily_budget();
+
+        self.hold_writes
+            .borrow_mut()
+            .check(inverter.datalog(), writes, today, daily_budget)
+    }
+
+    // counts an acknowledged write towards the daily budget
+    fn record_hold_write(&self, inverter: &config::Inverter, register: u16) -> Result<()> {
+        let datalog = inverter.datalog();
+        let today = inverter.local_time(Utils::utc()).date();
+        let daily_budget = self.config.hold_writes().daily_budget();
+
+        let mut hold_writes = self.hold_writes.borrow_mut();
+        hold_writes.record(datalog, register, today, daily_budget);
+
+        if self.config.mqtt().enabled() {
+            if let Some(counts) = hold_writes.counts(datalog) {
+                let message = mqtt::Message::for_hold_writes(datalog, counts)?;
+                let channel_data = mqtt::ChannelData::Message(message);
+                if self.channels.to_mqtt.send(channel_data).is_err() {
+                    bail!("send(to_mqtt) failed - channel closed?");
+                }
+            }
+        }
+
         Ok(())
     }
 
+    // writes debounced set_holds once nothing has replaced them for a while
+    async fn hold_write_flusher(&self) -> Result<()> {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let debounce = self.config.hold_writes().debounce();
+        if debounce == 0 {
+            return Ok(());
+        }
+        let debounce = std::time::Duration::from_secs(debounce);
+
+        let mut receiver = self.channels.from_mqtt.subscribe();
+        let mut interval = tokio::time::interval(std::time::Duration::from_millis(250));
+
+        loop {
+            tokio::select! {
+                data = receiver.recv() => {
+                    if matches!(data, Ok(mqtt::ChannelData::Shutdown) | Err(RecvError::Closed)) {
+                        break;
+                    }
+                }
+                _ = interval.tick() => {
+                    let due = self
+                        .hold_writes
+                        .borrow_mut()
+                        .due(std::time::Instant::now(), debounce);
+                    for pending in due {
+                        let (datalog, register) = (pending.inverter.datalog(), pending.register);
+                        let result = self
+                            .write_hold(pending.inverter, register, pending.value)
+                            .await;
+                        if let Err(err) = &result {
+                            warn!("inverter {}: writing register {}: {}", datalog, register, err);
+                        }
+                        for reply in &pending.replies {
+                            self.send_reply(reply, result.is_ok())?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn set_working_mode(
+        &self,
+        inverter: config::Inverter,
+        mode: lxp::packet::WorkingMode,
+        reply: Option<&Reply>,
+    ) -> Result<Outcome> {
+        use lxp::packet::{Register, WorkingMode};
+
+        let packet = commands::read_hold::ReadHold::new(
+            self.channels.clone(),
+            inverter.clone(),
+            Register::Register21,
+            1,
+        )
+        .run()
+        .await?;
+
+        let mut value = packet.value() & !WorkingMode::mask();
+        if let Some(bit) = mode.bit() {
+            value |= u16::from(bit);
+        }
+
+        self.set_hold(inverter, Register::Register21, value, reply)
+            .await
+    }
+
     async fn update_hold<U>(
         &self,
         inverter: config::Inverter,
@@ -299,6 +681,24 @@ impl Coordinator {
     where
         U: Into<u16>,
     {
+        let register = register.into();
+
+        if self.config.hold_writes().skip_unchanged() {
+            let known = RegisterCache::get(&self.channels, inverter.datalog(), register).await;
+            if matches!(known, Some(value) if (value & u16::from(bit.clone()) != 0) == enable) {
+                debug!(
+                    "inverter {}: register {} bit {:?} is already {}, not writing",
+                    inverter.datalog(),
+                    register,
+                    bit,
+                    enable
+                );
+                return Ok(());
+            }
+        }
+
+        self.check_hold_writes(&inverter, 1)?;
+
         commands::update_hold::UpdateHold::new(
             self.channels.clone(),
             inverter.clone(),
@@ -309,7 +709,7 @@ impl Coordinator {
         .run()
         .await?;
 
-        Ok(())
+        self.record_hold_write(&inverter, register)
     }
 
     async fn inverter_receiver(&self) -> Result<()> {
@@ -326,12 +726,15 @@ impl Coordinator {
                         .await?;
                 }
                 Connected(serial) => {
+                    self.publish_availability(serial, true)?;
                     if let Err(e) = self.inverter_connected(serial).await {
                         error!("{}", e);
                     }
                 }
-                // this loop holds no state so doesn't care about inverter disconnects
-                Disconnect(_) => {}
+                Disconnect(serial) => {
+                    self.publish_availability(serial, false)?;
+                    self.system_member_disconnected(serial)?;
+                }
                 Shutdown => break,
             }
         }
@@ -346,6 +749,9 @@ impl Coordinator {
     ) -> Result<()> {
         debug!("RX: {:?}", packet);
 
+        // published after the packet's own messages, if this packet changed them
+        let mut energy_totals = None;
+
         if let Packet::TranslatedData(td) = &packet {
             // temporary special greppable logging for Param packets as I try to
             // work out what they do :)
@@ -367,12 +773,20 @@ impl Coordinator {
 
                 match td.read_input() {
                     Ok(ReadInput::ReadInputAll(r_all)) => {
+                        energy_totals =
+                            self.update_energy(td.datalog, |m| m.update_input_all(&r_all));
                         // no need for MQTT here, done below
                         self.save_input_all(r_all).await?
                     }
 
-                    Ok(ReadInput::ReadInput1(r1)) => entry.set_read_input_1(r1),
-                    Ok(ReadInput::ReadInput2(r2)) => entry.set_read_input_2(r2),
+                    Ok(ReadInput::ReadInput1(r1)) => {
+                        energy_totals = self.update_energy(td.datalog, |m| m.update_input_1(&r1));
+                        entry.set_read_input_1(r1)
+                    }
+                    Ok(ReadInput::ReadInput2(r2)) => {
+                        energy_totals = self.update_energy(td.datalog, |m| m.update_input_2(&r2));
+                        entry.set_read_input_2(r2)
+                    }
                     Ok(ReadInput::ReadInput3(r3)) => entry.set_read_input_3(r3),
                     Ok(ReadInput::ReadInput4(r4)) => {
                         let datalog = r4.datalog;
@@ -396,11 +810,15 @@ impl Coordinator {
             } else if td.device_function == DeviceFunction::ReadHold
                 || td.device_function == DeviceFunction::WriteSingle
             {
-                let channel_data =
-                    register_cache::ChannelData::RegisterData(td.register, td.value());
-                if self.channels.to_register_cache.send(channel_data).is_err() {
-                    bail!("send(to_register_cache) failed - channel closed?");
+                for (register, value) in td.pairs() {
+                    let channel_data =
+                        register_cache::ChannelData::RegisterData(td.datalog, register, value);
+                    if self.channels.to_register_cache.send(channel_data).is_err() {
+                        bail!("send(to_register_cache) failed - channel closed?");
+                    }
                 }
+
+                self.send_to_outputs(output::OutputData::Hold(td.datalog, td.pairs()));
             }
         }
 
@@ -408,7 +826,11 @@ impl Coordinator {
             // returns a Vec of messages to send. could be none;
             // not every packet produces an MQ message (eg, heartbeats),
             // and some produce >1 (multi-register ReadHold)
-            match Self::packet_to_messages(packet, self.config.mqtt().publish_individual_input()) {
+            let (publish_individual_input, publish_named_inputs) = {
+                let mqtt = self.config.mqtt();
+                (mqtt.publish_individual_input(), mqtt.publish_named_inputs())
+            };
+            match Self::packet_to_messages(packet, publish_individual_input, publish_named_inputs) {
                 Ok(messages) => {
                     for message in messages {
                         let message = mqtt::ChannelData::Message(message);
@@ -423,6 +845,26 @@ impl Coordinator {
                     error!("{}", e);
                 }
             }
+
+            if let Some((datalog, totals)) = energy_totals {
+                let message = mqtt::Message::for_energy(datalog, &totals)?;
+                let channel_data = mqtt::ChannelData::Message(message);
+                if self.channels.to_mqtt.send(channel_data).is_err() {
+                    bail!("send(to_mqtt) failed - channel closed?");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn publish_availability(&self, datalog: Serial, online: bool) -> Result<()> {
+        if self.config.mqtt().enabled() {
+            let message = mqtt::Message::for_availability(datalog, online);
+            let channel_data = mqtt::ChannelData::Message(message);
+            if self.channels.to_mqtt.send(channel_data).is_err() {
+                bail!("send(to_mqtt) failed - channel closed?");
+            }
         }
 
         Ok(())
@@ -438,11 +880,48 @@ impl Coordinator {
             None => bail!("Unknown inverter connected: {}", datalog),
         };
 
+        if self.config.mqtt().enabled() && self.config.mqtt().homeassistant().enabled() {
+            if let Err(e) = self.read_device_info(&inverter).await {
+                warn!("inverter {}: reading device info: {}", datalog, e);
+            }
+        }
+
+        // a dongle reconnect can follow an inverter power cycle, which may lose the time
+        if inverter.timesync_on_connect() {
+            if let Err(e) = self.timesync(inverter.clone()).await {
+                warn!("inverter {}: timesync: {}", datalog, e);
+            }
+        }
+
         if !inverter.publish_holdings_on_connect() {
             return Ok(());
         }
 
-        info!("Reading holding registers for inverter {}", datalog);
+        self.read_holdings(inverter).await
+    }
+
+    async fn timesync(&self, inverter: config::Inverter) -> Result<()> {
+        let datalog = inverter.datalog();
+        let drift = commands::timesync::TimeSync::new(self.channels.clone(), inverter)
+            .run()
+            .await?;
+
+        if self.config.mqtt().enabled() {
+            let message = mqtt::Message::for_time_drift(datalog, drift);
+            let channel_data = mqtt::ChannelData::Message(message);
+            if self.channels.to_mqtt.send(channel_data).is_err() {
+                bail!("send(to_mqtt) failed - channel closed?");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn read_holdings(&self, inverter: config::Inverter) -> Result<()> {
+        info!(
+            "Reading holding registers for inverter {}",
+            inverter.datalog()
+        );
 
         // We can only read holding registers in blocks of 40. Provisionally,
         // there are 6 pages of 40 values.
@@ -484,33 +963,179 @@ impl Coordinator {
         Ok(())
     }
 
+    // model, serial and firmware, for the HA device page. sends discovery again
+    // once we have them.
+    async fn read_device_info(&self, inverter: &config::Inverter) -> Result<()> {
+        let packet = commands::read_hold::ReadHold::new(
+            self.channels.clone(),
+            inverter.clone(),
+            0_u16,
+            lxp::packet::DeviceInfo::REGISTER_COUNT,
+        )
+        .run()
+        .await?;
+
+        let Packet::TranslatedData(td) = packet else {
+            bail!("didn't get expected reply from inverter");
+        };
+        let device_info = lxp::packet::DeviceInfo::from_hold(&td)?;
+        debug!("inverter {}: {:?}", inverter.datalog(), device_info);
+
+        self.device_info
+            .borrow_mut()
+            .insert(inverter.datalog(), device_info);
+
+        self.publish_home_assistant_discovery(inverter)
+    }
+
+    // feed new readings into the energy meter for this inverter, returning the
+    // resulting totals once they're all known
+    fn update_energy<F>(&self, datalog: Serial, update: F) -> Option<(Serial, energy::Totals)>
+    where
+        F: FnOnce(&mut energy::Meter),
+    {
+        let mut store = self.energy.borrow_mut();
+        let meter = store.entry(datalog).or_default();
+        update(meter);
+        meter.totals().map(|totals| (datalog, totals))
+    }
+
     async fn save_input_all(&self, input: Box<lxp::packet::ReadInputAll>) -> Result<()> {
-        if self.config.influx().enabled() {
-            let channel_data = influx::ChannelData::InputData(serde_json::to_value(&input)?);
-            if self.channels.to_influx.send(channel_data).is_err() {
-                bail!("send(to_influx) failed - channel closed?");
+        self.update_systems(&input)?;
+        self.update_battery(&input)?;
+
+        self.send_to_outputs(output::OutputData::InputAll(input));
+
+        Ok(())
+    }
+
+    fn update_battery(&self, input: &lxp::packet::ReadInputAll) -> Result<()> {
+        let config = match self.config.enabled_battery() {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+        let inverter = match self.config.enabled_inverter_with_datalog(input.datalog) {
+            Some(inverter) => inverter,
+            None => return Ok(()),
+        };
+
+        let stats = self
+            .battery
+            .borrow_mut()
+            .entry(input.datalog)
+            .or_default()
+            .update(&config, &inverter, input);
+        let stats = match stats {
+            Some(stats) => stats,
+            None => return Ok(()),
+        };
+
+        if stats.fading {
+            warn!(
+                "inverter {}: battery capacity estimated at {:?}kWh, expected {}kWh",
+                stats.datalog, stats.estimated_capacity, stats.expected_capacity
+            );
+        }
+
+        if self.config.mqtt().enabled() {
+            let message = mqtt::Message::for_battery(&stats)?;
+            let channel_data = mqtt::ChannelData::Message(message);
+            if self.channels.to_mqtt.send(channel_data).is_err() {
+                bail!("send(to_mqtt) failed - channel closed?");
+            }
+        }
+
+        self.send_to_outputs(output::OutputData::Battery(stats));
+
+        Ok(())
+    }
+
+    // systems are published like any other inverter, under their own datalog
+    fn update_systems(&self, input: &lxp::packet::ReadInputAll) -> Result<()> {
+        let mut totals = Vec::new();
+        for aggregator in self.systems.borrow_mut().iter_mut() {
+            let was_online = aggregator.online();
+            if let Some(total) = aggregator.add(input) {
+                totals.push((aggregator.system().clone(), was_online, total));
             }
         }
 
-        if self.config.have_enabled_database() {
-            let channel_data = database::ChannelData::ReadInputAll(input);
-            if self.channels.to_database.send(channel_data).is_err() {
-                bail!("send(to_database) failed - channel closed?");
+        for (system, was_online, total) in totals {
+            let datalog = system.datalog();
+            debug!("system {}: new totals from {}", datalog, input.datalog);
+
+            if self.config.mqtt().enabled() {
+                if !was_online {
+                    self.publish_system_discovery(&system)?;
+                    self.publish_availability(datalog, true)?;
+                }
+
+                let message = mqtt::Message::for_input_all(&total, datalog)?;
+                let channel_data = mqtt::ChannelData::Message(message);
+                if self.channels.to_mqtt.send(channel_data).is_err() {
+                    bail!("send(to_mqtt) failed - channel closed?");
+                }
             }
+
+            self.send_to_outputs(output::OutputData::InputAll(Box::new(total)));
         }
 
         Ok(())
     }
 
+    // a system is only as available as all of its inverters
+    fn system_member_disconnected(&self, datalog: Serial) -> Result<()> {
+        let mut offline = Vec::new();
+        for aggregator in self.systems.borrow_mut().iter_mut() {
+            if aggregator.contains(datalog) {
+                if aggregator.online() {
+                    offline.push(aggregator.system().datalog());
+                }
+                aggregator.disconnected(datalog);
+            }
+        }
+
+        for system in offline {
+            self.publish_availability(system, false)?;
+        }
+
+        Ok(())
+    }
+
+    fn send_to_outputs(&self, data: output::OutputData) {
+        // alerts watch battery stats too, with or without any outputs
+        let wanted = self.config.have_enabled_output()
+            || (matches!(data, output::OutputData::Battery(_))
+                && self.config.have_enabled_alerts());
+        if wanted {
+            let channel_data = output::ChannelData::Data(data);
+            // nobody listening just means every output failed to connect, and
+            // they've already said so; not a reason to stop handling packets
+            if self.channels.to_outputs.send(channel_data).is_err() {
+                debug!("send(to_outputs) failed - no outputs running");
+            }
+        }
+    }
+
     fn packet_to_messages(
         packet: Packet,
         publish_individual_input: bool,
+        publish_named_inputs: bool,
     ) -> Result<Vec<mqtt::Message>> {
         match packet {
             Packet::Heartbeat(_) => Ok(Vec::new()), // always no message
             Packet::TranslatedData(td) => match td.device_function {
                 DeviceFunction::ReadHold => mqtt::Message::for_hold(td),
-                DeviceFunction::ReadInput => mqtt::Message::for_input(td, publish_individual_input),
+                DeviceFunction::ReadInput => {
+                    let mut r = mqtt::Message::for_input(td.clone(), publish_individual_input)?;
+                    r.extend(mqtt::Message::for_bms(&td)?);
+                    r.extend(mqtt::Message::for_generator(&td)?);
+                    r.append(&mut mqtt::Message::for_codes(&td)?);
+                    if publish_named_inputs {
+                        r.append(&mut mqtt::Message::for_input_keys(td)?);
+                    }
+                    Ok(r)
+                }
                 DeviceFunction::WriteSingle => mqtt::Message::for_hold(td),
                 DeviceFunction::WriteMulti => Ok(Vec::new()), // TODO, for_hold might just work
             },
@@ -1,15 +1,9 @@
 use crate::prelude::*;
 
+use async_trait::async_trait;
+use output::{Output, OutputData};
 use sqlx::{any::AnyConnectOptions, AnyPool, ConnectOptions};
 
-#[derive(PartialEq, Clone, Debug)]
-pub enum ChannelData {
-    ReadInputAll(Box<lxp::packet::ReadInputAll>),
-    Shutdown,
-}
-
-pub type Sender = broadcast::Sender<ChannelData>;
-
 enum DatabaseType {
     MySQL,
     Postgres,
@@ -19,36 +13,21 @@ enum DatabaseType {
 #[derive(Clone, Debug)]
 pub struct Database {
     config: config::Database,
-    channels: Channels,
     pool: RefCell<Option<AnyPool>>,
+    query: RefCell<String>,
 }
 
 impl Database {
     // databases don't bother with a ConfigWrapper yet as they don't care about any
     // changes once running; there's only enabled/url anyway and we'd use url to key off.
-    pub fn new(config: config::Database, channels: Channels) -> Self {
+    pub fn new(config: config::Database) -> Self {
         Self {
             config,
-            channels,
             pool: RefCell::new(None),
+            query: RefCell::new(String::new()),
         }
     }
 
-    pub async fn start(&self) -> Result<()> {
-        // TODO: could log the url but would need to redact password
-        info!("initializing database");
-
-        futures::try_join!(self.inserter())?;
-
-        info!("database loop exiting");
-
-        Ok(())
-    }
-
-    pub fn stop(&self) {
-        let _ = self.channels.to_database.send(ChannelData::Shutdown);
-    }
-
     fn database(&self) -> Result<DatabaseType> {
         let prefix: Vec<&str> = self.config.url().splitn(2, ':').collect();
         match prefix[0] {
@@ -59,7 +38,7 @@ impl Database {
         }
     }
 
-    async fn connect(&self) -> Result<()> {
+    async fn open(&self) -> Result<()> {
         let mut options = AnyConnectOptions::from_str(self.config.url())?;
         options.disable_statement_logging();
         let pool = sqlx::any::AnyPool::connect_with(options).await?;
@@ -97,21 +76,13 @@ impl Database {
         Ok(())
     }
 
-    async fn inserter(&self) -> Result<()> {
-        self.connect().await?;
-
-        info!("database connected");
-
-        self.migrate().await?;
-
-        let mut receiver = self.channels.to_database.subscribe();
-
+    fn insert_query(&self) -> Result<String> {
         let values = match self.database()? {
             DatabaseType::MySQL => Self::values_for_mysql(),
             _ => Self::values_for_not_mysql(),
         };
 
-        let query = format!(
+        Ok(format!(
             r#"
             INSERT INTO inputs
               ( status,
@@ -147,30 +118,21 @@ impl Database {
                 max_cell_voltage, min_cell_voltage, max_cell_temp, min_cell_temp,
                 bms_fw_update_state, cycle_count, vbat_inv,
 
+                faults, warnings,
+
+                p_load, e_load_day, self_consumption, self_sufficiency,
+                battery_efficiency, inverter_efficiency, time_to_empty, time_to_full,
+
                 datalog, created_at
               )
             VALUES {} "#,
             values
-        );
-
-        loop {
-            use ChannelData::*;
-
-            match receiver.recv().await? {
-                Shutdown => break,
-                ReadInputAll(data) => {
-                    while let Err(err) = self.insert(&query, &data).await {
-                        error!("INSERT failed: {:?} - retrying in 10s", err);
-                        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-                    }
-                }
-            }
-        }
-
-        Ok(())
+        ))
     }
 
     async fn insert(&self, query: &str, data: &lxp::packet::ReadInputAll) -> Result<()> {
+        use lxp::packet::{ActiveCode, FaultCodeString, WarningCodeString};
+
         let mut conn = self.connection().await?;
 
         sqlx::query(query)
@@ -263,6 +225,20 @@ impl Database {
             .bind(data.bms_fw_update_state as i32)
             .bind(data.cycle_count as i32)
             .bind(data.vbat_inv)
+            .bind(ActiveCode::codes_json(&FaultCodeString::active(
+                data.fault_code,
+            )))
+            .bind(ActiveCode::codes_json(&WarningCodeString::active(
+                data.warning_code,
+            )))
+            .bind(data.p_load as i32)
+            .bind(data.e_load_day)
+            .bind(data.self_consumption)
+            .bind(data.self_sufficiency)
+            .bind(data.battery_efficiency)
+            .bind(data.inverter_efficiency)
+            .bind(data.time_to_empty.map(|minutes| minutes as i32))
+            .bind(data.time_to_full.map(|minutes| minutes as i32))
             .bind(data.datalog.to_string())
             .bind(data.time.0)
             .persistent(true)
@@ -272,11 +248,47 @@ impl Database {
         Ok(())
     }
 
+    // rare enough that there's no point keeping the query around
+    async fn insert_battery(&self, stats: &battery::Stats) -> Result<()> {
+        let values = match self.database()? {
+            DatabaseType::MySQL => "(?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            _ => "($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        };
+        let query = format!(
+            r#"
+            INSERT INTO battery_history
+              ( soh, cycle_count, equivalent_cycles,
+                rated_capacity, expected_capacity, estimated_capacity, fading,
+                datalog, created_at
+              )
+            VALUES {} "#,
+            values
+        );
+
+        let mut conn = self.connection().await?;
+
+        sqlx::query(&query)
+            .bind(stats.soh as i16)
+            .bind(stats.cycle_count as i32)
+            .bind(stats.equivalent_cycles)
+            .bind(stats.rated_capacity)
+            .bind(stats.expected_capacity)
+            .bind(stats.estimated_capacity)
+            .bind(stats.fading)
+            .bind(stats.datalog.to_string())
+            .bind(stats.time.0)
+            .fetch_optional(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
     fn values_for_mysql() -> &'static str {
         r#"(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
             ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
             ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
-            ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+            ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
+            ?)"#
     }
 
     fn values_for_not_mysql() -> &'static str {
@@ -286,6 +298,48 @@ impl Database {
             $43, $44, $45, $46, $47, $48, $49, $50, $51, $52, $53, $54, $55, $56,
             $57, $58, $59, $60, $61, $62, $63, $64, $65, $66, $67, $68, $69, $70,
             $71, $72, $73, $74, $75, $76, $77, $78, $79, $80, $81, $82, $83, $84,
-            $85, $86, $87, $88, $89, $90, $91)"#
+            $85, $86, $87, $88, $89, $90, $91, $92, $93, $94, $95, $96, $97, $98,
+            $99, $100, $101)"#
+    }
+}
+
+#[async_trait(?Send)]
+impl Output for Database {
+    // TODO: could use the url but would need to redact password
+    fn name(&self) -> String {
+        "database".to_owned()
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.open().await?;
+
+        info!("database connected");
+
+        self.migrate().await?;
+
+        *self.query.borrow_mut() = self.insert_query()?;
+
+        Ok(())
+    }
+
+    fn filter(&self, data: OutputData) -> Option<OutputData> {
+        match data {
+            OutputData::InputAll(_) | OutputData::Battery(_) => Some(data),
+            OutputData::Hold(..) => None,
+        }
+    }
+
+    async fn write(&self, batch: &[OutputData]) -> Result<()> {
+        let query = self.query.borrow().clone();
+
+        for data in batch {
+            match data {
+                OutputData::InputAll(input) => self.insert(&query, input).await?,
+                OutputData::Battery(stats) => self.insert_battery(stats).await?,
+                OutputData::Hold(..) => {}
+            }
+        }
+
+        Ok(())
     }
 }
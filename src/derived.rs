@@ -0,0 +1,120 @@
+use crate::prelude::*;
+
+use lxp::packet::ReadInputAll;
+
+// below these, ratios are mostly noise (a 20W standby load reading 60% inverter
+// efficiency, or a battery delivered charged reading over 100%)
+const MIN_EFFICIENCY_POWER: u16 = 100; // W
+const MIN_EFFICIENCY_ENERGY: f64 = 10.0; // kWh
+
+// a battery barely charging or discharging would take weeks to fill or empty
+const MIN_BATTERY_POWER: u16 = 50; // W
+
+// Fills in the values we calculate from the rest of the inputs. Called as each
+// ReadInputAll is put together, so they go everywhere the inputs do.
+pub fn apply(mut input: ReadInputAll) -> ReadInputAll {
+    input.p_load = load_power(&input);
+    input.e_load_day = load_energy_day(&input);
+    input.self_consumption = self_consumption(&input);
+    input.self_sufficiency = self_sufficiency(&input);
+    input.battery_efficiency = battery_efficiency(&input);
+    input.inverter_efficiency = inverter_efficiency(&input);
+    input.time_to_empty = time_to_empty(&input);
+    input.time_to_full = time_to_full(&input);
+
+    input
+}
+
+// power used by the house; what the inverter and grid supply, less what goes
+// back into the battery or out to the grid
+pub fn load_power(input: &ReadInputAll) -> u16 {
+    let supplied = input.p_inv as i32 + input.p_to_user as i32 + input.p_eps as i32;
+    let diverted = input.p_rec as i32 + input.p_to_grid as i32;
+    (supplied - diverted).clamp(0, u16::MAX as i32) as u16
+}
+
+pub fn load_energy_day(input: &ReadInputAll) -> f64 {
+    let supplied = input.e_inv_day + input.e_to_user_day + input.e_eps_day;
+    let diverted = input.e_rec_day + input.e_to_grid_day;
+    Utils::round((supplied - diverted).max(0.0), 1)
+}
+
+// how much of today's solar was used here rather than exported
+fn self_consumption(input: &ReadInputAll) -> Option<f64> {
+    if input.e_pv_day <= 0.0 {
+        return None;
+    }
+
+    let used = (input.e_pv_day - input.e_to_grid_day).max(0.0);
+    Some(percent(used / input.e_pv_day))
+}
+
+// how much of today's load was met without importing
+fn self_sufficiency(input: &ReadInputAll) -> Option<f64> {
+    let load = load_energy_day(input);
+    if load <= 0.0 {
+        return None;
+    }
+
+    let own = (load - input.e_to_user_day).max(0.0);
+    Some(percent(own / load))
+}
+
+// round trip, from the lifetime counters so it isn't thrown by the battery
+// ending the day at a different SOC than it started
+fn battery_efficiency(input: &ReadInputAll) -> Option<f64> {
+    if input.e_chg_all < MIN_EFFICIENCY_ENERGY {
+        return None;
+    }
+
+    Some(percent(input.e_dischg_all / input.e_chg_all))
+}
+
+// of the whole power stage; what goes out to AC and the battery against what
+// comes in from PV, the battery and AC
+fn inverter_efficiency(input: &ReadInputAll) -> Option<f64> {
+    let power_in = input.p_pv as u32 + input.p_discharge as u32 + input.p_rec as u32;
+    let power_out = input.p_inv as u32 + input.p_charge as u32;
+    if power_in < MIN_EFFICIENCY_POWER as u32 {
+        return None;
+    }
+
+    Some(percent(power_out as f64 / power_in as f64))
+}
+
+// at the current discharge rate, down to 0% SOC
+fn time_to_empty(input: &ReadInputAll) -> Option<u32> {
+    if input.p_discharge < MIN_BATTERY_POWER {
+        return None;
+    }
+
+    let remaining = battery_energy(input)? * input.soc.max(0) as f64 / 100.0;
+    Some(minutes(remaining, input.p_discharge))
+}
+
+// at the current charge rate, up to 100% SOC
+fn time_to_full(input: &ReadInputAll) -> Option<u32> {
+    if input.p_charge < MIN_BATTERY_POWER {
+        return None;
+    }
+
+    let remaining = battery_energy(input)? * (100 - input.soc.clamp(0, 100)) as f64 / 100.0;
+    Some(minutes(remaining, input.p_charge))
+}
+
+// usable capacity in Wh, from the BMS's Ah; None if there's no BMS to ask
+fn battery_energy(input: &ReadInputAll) -> Option<f64> {
+    if input.bat_capacity == 0 || input.v_bat <= 0.0 {
+        return None;
+    }
+
+    Some(input.bat_capacity as f64 * input.v_bat)
+}
+
+fn percent(ratio: f64) -> f64 {
+    Utils::round((ratio * 100.0).clamp(0.0, 100.0), 1)
+}
+
+fn minutes(wh: f64, watts: u16) -> u32 {
+    (wh / watts as f64 * 60.0).round() as u32
+}
@@ -0,0 +1,219 @@
+use crate::prelude::*;
+
+use lxp::packet::{ReadInput1, ReadInput2, ReadInputAll};
+use serde::Serialize;
+
+// The biggest increase we'll believe between two consecutive readings of one
+// counter. Anything larger is assumed to be a corrupt packet, not real energy,
+// unless the next reading carries on from it.
+const MAX_DELTA_KWH: f64 = 25.0;
+
+// How far a daily counter can have got past the inverter's midnight by the
+// time we see it. A drop to more than this is a bad reading, not a reset.
+const MAX_RESET_KWH: f64 = 1.0;
+
+pub type EnergyStore = std::collections::HashMap<Serial, Meter>;
+
+// Counter {{{
+// One monotonic energy total, in kWh.
+//
+// It is seeded from the inverter's lifetime (e_*_all) counter, then advanced
+// with the difference between successive daily (e_*_day) readings, as those
+// arrive far more often. Later lifetime readings only pull the total up if the
+// daily differences have fallen behind. The total never goes backwards; the
+// inverter's own midnight reset of the daily counter just starts a new run of
+// differences.
+//
+// A reading that jumps too far, or drops without going back to about 0, is
+// held as suspect rather than used. If the next reading carries on from it,
+// it was real and we resync to it, leaving any energy across the gap to the
+// lifetime counter; otherwise it was garbage and is forgotten.
+#[derive(Clone, Debug, Default)]
+pub struct Counter {
+    total: Option<f64>,
+    last_day: Option<f64>,
+    suspect_day: Option<f64>,
+    suspect_all: Option<f64>,
+}
+
+impl Counter {
+    pub fn total(&self) -> Option<f64> {
+        // we only ever add tenths, but floats don't know that
+        self.total.map(|total| (total * 10.0).round() / 10.0)
+    }
+
+    // A lifetime and daily reading taken together, as in a ReadInputAll. The
+    // daily reading goes first; the lifetime one is then only a floor, so the
+    // same energy isn't counted by both.
+    pub fn update(&mut self, all: f64, day: f64) {
+        self.update_day(day);
+        self.raise_to(all);
+    }
+
+    // A lifetime reading on its own. If it moves us on we can't tell how much
+    // of the daily counter's progress it already includes, so only count daily
+    // differences again from the next daily reading.
+    pub fn update_all(&mut self, all: f64) {
+        if self.raise_to(all) {
+            self.last_day = None;
+        }
+    }
+
+    pub fn update_day(&mut self, day: f64) {
+        if !day.is_finite() || day < 0.0 {
+            return;
+        }
+
+        let delta = match (self.last_day, self.suspect_day.take()) {
+            (None, _) => 0.0,
+            (Some(last_day), _) if Self::follows(day, last_day) => day - last_day,
+            (Some(last_day), _) if day < last_day && day <= MAX_RESET_KWH => day, // midnight
+            (_, Some(suspect)) if Self::follows(day, suspect) => {
+                info!("energy reading {} follows {}, resyncing", day, suspect);
+                day - suspect
+            }
+            (last_day, _) => {
+                warn!("ignoring energy reading {} (last {:?})", day, last_day);
+                self.suspect_day = Some(day);
+                return;
+            }
+        };
+
+        if let Some(total) = self.total {
+            self.total = Some(total + delta);
+        }
+        self.last_day = Some(day);
+    }
+
+    // seed from, or catch up to, a lifetime reading. true if the total moved
+    fn raise_to(&mut self, all: f64) -> bool {
+        if !all.is_finite() || all < 0.0 {
+            return false;
+        }
+
+        let suspect = self.suspect_all.take();
+        match self.total {
+            None => {}
+            Some(total) if all <= total => return false, // behind us (rounding)
+            Some(total) if Self::follows(all, total) => {}
+            Some(_) if matches!(suspect, Some(suspect) if Self::follows(all, suspect)) => {
+                info!(
+                    "lifetime energy reading {} follows {:?}, resyncing",
+                    all, suspect
+                );
+            }
+            Some(total) => {
+                warn!("ignoring lifetime energy reading {} (total {})", all, total);
+                self.suspect_all = Some(all);
+                return false;
+            }
+        }
+
+        self.total = Some(all);
+
+        true
+    }
+
+    // whether reading is a believable next reading after previous
+    fn follows(reading: f64, previous: f64) -> bool {
+        reading >= previous && reading - previous <= MAX_DELTA_KWH
+    }
+} // }}}
+
+// Totals {{{
+// What we publish to inputs/energy. Keys keep the e_ prefix so they get kWh
+// units like the inverter's own energy counters.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Totals {
+    pub e_pv_total: f64,
+    pub e_grid_import_total: f64,
+    pub e_grid_export_total: f64,
+    pub e_chg_total: f64,
+    pub e_dischg_total: f64,
+    pub e_eps_total: f64,
+} // }}}
+
+// Meter {{{
+#[derive(Clone, Debug, Default)]
+pub struct Meter {
+    pv: Counter,
+    grid_import: Counter,
+    grid_export: Counter,
+    chg: Counter,
+    dischg: Counter,
+    eps: Counter,
+}
+
+impl Meter {
+    pub fn update_input_all(&mut self, input: &ReadInputAll) {
+        let readings = [
+            (input.e_pv_all, input.e_pv_day),
+            (input.e_to_user_all, input.e_to_user_day),
+            (input.e_to_grid_all, input.e_to_grid_day),
+            (input.e_chg_all, input.e_chg_day),
+            (input.e_dischg_all, input.e_dischg_day),
+            (input.e_eps_all, input.e_eps_day),
+        ];
+        for (counter, (all, day)) in self.counters().into_iter().zip(readings) {
+            counter.update(all, day);
+        }
+    }
+
+    pub fn update_input_1(&mut self, input: &ReadInput1) {
+        self.update_day([
+            input.e_pv_day,
+            input.e_to_user_day,
+            input.e_to_grid_day,
+            input.e_chg_day,
+            input.e_dischg_day,
+            input.e_eps_day,
+        ]);
+    }
+
+    pub fn update_input_2(&mut self, input: &ReadInput2) {
+        self.update_all([
+            input.e_pv_all,
+            input.e_to_user_all,
+            input.e_to_grid_all,
+            input.e_chg_all,
+            input.e_dischg_all,
+            input.e_eps_all,
+        ]);
+    }
+
+    // None until every counter has been seeded from a lifetime reading
+    pub fn totals(&self) -> Option<Totals> {
+        Some(Totals {
+            e_pv_total: self.pv.total()?,
+            e_grid_import_total: self.grid_import.total()?,
+            e_grid_export_total: self.grid_export.total()?,
+            e_chg_total: self.chg.total()?,
+            e_dischg_total: self.dischg.total()?,
+            e_eps_total: self.eps.total()?,
+        })
+    }
+
+    fn counters(&mut self) -> [&mut Counter; 6] {
+        [
+            &mut self.pv,
+            &mut self.grid_import,
+            &mut self.grid_export,
+            &mut self.chg,
+            &mut self.dischg,
+            &mut self.eps,
+        ]
+    }
+
+    // values are in the same order as counters()
+    fn update_all(&mut self, values: [f64; 6]) {
+        for (counter, value) in self.counters().into_iter().zip(values) {
+            counter.update_all(value);
+        }
+    }
+
+    fn update_day(&mut self, values: [f64; 6]) {
+        for (counter, value) in self.counters().into_iter().zip(values) {
+            counter.update_day(value);
+        }
+    }
+} // }}}
@@ -0,0 +1,162 @@
+use crate::prelude::*;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+// Counts {{{
+// Holding register writes to one inverter, published as metrics
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Counts {
+    #[serde(skip)]
+    date: NaiveDate,
+    pub today: u32,
+    pub daily_budget: Option<u32>,
+    pub total: u64,
+    // since we started, by register
+    pub registers: BTreeMap<u16, u64>,
+}
+
+impl Counts {
+    fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            today: 0,
+            daily_budget: None,
+            total: 0,
+            registers: BTreeMap::new(),
+        }
+    }
+} // }}}
+
+// A debounced write, and who to tell how it went once it's made
+pub struct Pending {
+    pub inverter: config::Inverter,
+    pub register: u16,
+    pub value: u16,
+    pub replies: Vec<coordinator::Reply>,
+    at: Instant,
+}
+
+// Tracker {{{
+// What we've written to each inverter's holding registers, and what's waiting
+// to be. The inverter keeps these in EEPROM, which only takes so many writes.
+#[derive(Default)]
+pub struct Tracker {
+    pending: HashMap<(Serial, u16), Pending>,
+    counts: HashMap<Serial, Counts>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // replaces any write to the same register still waiting out the debounce.
+    // whoever asked for that one hears how this one goes instead
+    pub fn queue(
+        &mut self,
+        inverter: config::Inverter,
+        register: u16,
+        value: u16,
+        reply: Option<coordinator::Reply>,
+        now: Instant,
+    ) {
+        let key = (inverter.datalog(), register);
+        let mut replies = Vec::new();
+        if let Some(previous) = self.pending.remove(&key) {
+            debug!(
+                "inverter {}: register {} write of {} superseded by {}",
+                key.0, register, previous.value, value
+            );
+            replies = previous.replies;
+        }
+        replies.extend(reply);
+
+        self.pending.insert(
+            key,
+            Pending {
+                inverter,
+                register,
+                value,
+                replies,
+                at: now,
+            },
+        );
+    }
+
+    // writes that have gone debounce without being superseded
+    pub fn due(&mut self, now: Instant, debounce: Duration) -> Vec<Pending> {
+        let keys: Vec<(Serial, u16)> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.at) >= debounce)
+            .map(|(key, _)| *key)
+            .collect();
+
+        keys.into_iter()
+            .filter_map(|key| self.pending.remove(&key))
+            .collect()
+    }
+
+    // refuses if today's budget hasn't room for this many more writes. today
+    // is the inverter's local date, so the budget resets at its midnight
+    pub fn check(
+        &mut self,
+        datalog: Serial,
+        writes: u32,
+        today: NaiveDate,
+        daily_budget: Option<u32>,
+    ) -> Result<()> {
+        let counts = self.today(datalog, today, daily_budget);
+
+        if matches!(daily_budget, Some(budget) if counts.today + writes > budget) {
+            bail!(
+                "inverter {}: daily budget of {} holding register writes used up",
+                datalog,
+                counts.today
+            );
+        }
+
+        Ok(())
+    }
+
+    // counts a write the inverter has acknowledged
+    pub fn record(
+        &mut self,
+        datalog: Serial,
+        register: u16,
+        today: NaiveDate,
+        daily_budget: Option<u32>,
+    ) {
+        let counts = self.today(datalog, today, daily_budget);
+
+        counts.today += 1;
+        counts.total += 1;
+        *counts.registers.entry(register).or_default() += 1;
+    }
+
+    fn today(
+        &mut self,
+        datalog: Serial,
+        today: NaiveDate,
+        daily_budget: Option<u32>,
+    ) -> &mut Counts {
+        let counts = self
+            .counts
+            .entry(datalog)
+            .or_insert_with(|| Counts::new(today));
+        if counts.date != today {
+            counts.date = today;
+            counts.today = 0;
+        }
+        counts.daily_budget = daily_budget;
+
+        counts
+    }
+
+    pub fn counts(&self, datalog: Serial) -> Option<&Counts> {
+        self.counts.get(&datalog)
+    }
+} // }}}
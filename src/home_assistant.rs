@@ -1,5 +1,5 @@
 use crate::prelude::*;
-use lxp::packet::Register;
+use lxp::packet::{ModelFamily, Register};
 
 use serde::{Serialize, Serializer};
 
@@ -43,12 +43,46 @@ pub struct Device {
     manufacturer: String,
     name: String,
     identifiers: [String; 1],
-    // model: String, // TODO: provide inverter model
+    // these are only known once we've read holding registers 0-10 from the inverter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sw_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hw_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    via_device: Option<String>,
 }
 
 pub struct Config {
-    inverter: config::Inverter,
+    datalog: Serial,
     mqtt_config: config::Mqtt,
+    device_info: Option<lxp::packet::DeviceInfo>,
+    generator: bool,
+}
+
+// https://www.home-assistant.io/integrations/binary_sensor.mqtt/
+#[derive(Debug, Serialize)]
+pub struct BinarySensor {
+    name: String,
+    state_topic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_template: Option<String>,
+    payload_on: String,
+    payload_off: String,
+    device_class: String,
+    entity_category: String,
+    unique_id: String,
+    device: Device,
+    availability: Vec<Availability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    availability_mode: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_attributes_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_attributes_template: Option<&'static str>,
 }
 
 // https://www.home-assistant.io/integrations/sensor.mqtt/
@@ -82,7 +116,8 @@ pub struct Entity<'a> {
     icon: Option<&'a str>,
 
     device: Device,
-    availability: Availability,
+    availability: Vec<Availability>,
+    availability_mode: &'static str,
 }
 
 // https://www.home-assistant.io/integrations/switch.mqtt/
@@ -94,7 +129,8 @@ pub struct Switch {
     value_template: String,
     unique_id: String,
     device: Device,
-    availability: Availability,
+    availability: Vec<Availability>,
+    availability_mode: &'static str,
 }
 
 // https://www.home-assistant.io/integrations/number.mqtt/
@@ -104,15 +140,46 @@ pub struct Number {
     state_topic: String,
     command_topic: String,
     value_template: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command_template: Option<String>,
     unique_id: String,
     device: Device,
-    availability: Availability,
+    availability: Vec<Availability>,
+    availability_mode: &'static str,
     min: f64,
     max: f64,
     step: f64,
     unit_of_measurement: String,
 }
 
+// https://www.home-assistant.io/integrations/select.mqtt/
+#[derive(Debug, Serialize)]
+pub struct Select {
+    name: String,
+    state_topic: String,
+    command_topic: String,
+    value_template: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command_template: Option<String>,
+    options: Vec<String>,
+    unique_id: String,
+    device: Device,
+    availability: Vec<Availability>,
+    availability_mode: &'static str,
+}
+
+// https://www.home-assistant.io/integrations/button.mqtt/
+#[derive(Debug, Serialize)]
+pub struct Button {
+    name: String,
+    command_topic: String,
+    payload_press: String,
+    unique_id: String,
+    device: Device,
+    availability: Vec<Availability>,
+    availability_mode: &'static str,
+}
+
 // https://www.home-assistant.io/integrations/text.mqtt/
 #[derive(Debug, Serialize)]
 pub struct Text {
@@ -123,18 +190,36 @@ pub struct Text {
     value_template: String,
     unique_id: String,
     device: Device,
-    availability: Availability,
+    availability: Vec<Availability>,
+    availability_mode: &'static str,
     pattern: String,
 }
 
 impl Config {
     pub fn new(inverter: &config::Inverter, mqtt_config: &config::Mqtt) -> Self {
         Self {
-            inverter: inverter.clone(),
+            datalog: inverter.datalog(),
             mqtt_config: mqtt_config.clone(),
+            device_info: None,
+            generator: inverter.generator(),
         }
     }
 
+    // a parallel system is a virtual inverter publishing the members' summed inputs
+    pub fn for_system(system: &config::System, mqtt_config: &config::Mqtt) -> Self {
+        Self {
+            datalog: system.datalog(),
+            mqtt_config: mqtt_config.clone(),
+            device_info: None,
+            generator: false,
+        }
+    }
+
+    pub fn with_device_info(mut self, device_info: Option<lxp::packet::DeviceInfo>) -> Self {
+        self.device_info = device_info;
+        self
+    }
+
     pub fn sensors(&self) -> Vec<mqtt::Message> {
         let base = Entity {
             key: &String::default(),
@@ -148,13 +233,10 @@ impl Config {
             value_template: ValueTemplate::Default, // "{{ value_json.$key }}"
             // TODO: might change this to an enum that defaults to InputsAll but can be replaced
             // with a string for a specific topic?
-            state_topic: &format!(
-                "{}/{}/inputs/all",
-                self.mqtt_config.namespace(),
-                self.inverter.datalog()
-            ),
+            state_topic: &self.state_topic("inputs/all"),
             device: self.device(),
             availability: self.availability(),
+            availability_mode: "all",
         };
 
         let voltage = Entity {
@@ -199,6 +281,19 @@ impl Config {
             ..base.clone()
         };
 
+        let percentage = Entity {
+            state_class: Some("measurement"),
+            unit_of_measurement: Some("%"),
+            ..base.clone()
+        };
+
+        let minutes = Entity {
+            device_class: Some("duration"),
+            state_class: Some("measurement"),
+            unit_of_measurement: Some("min"),
+            ..base.clone()
+        };
+
         // now each entry in here should only have to specify specific overrides for each key.
         // if we have multiple things sharing keys, consider whether to make a new variable to
         // inherit from.
@@ -206,11 +301,7 @@ impl Config {
             Entity {
                 key: "status",
                 name: "Status",
-                state_topic: &format!(
-                    "{}/{}/input/0/parsed",
-                    self.mqtt_config.namespace(),
-                    self.inverter.datalog()
-                ),
+                state_topic: &self.state_topic("input/0/parsed"),
                 value_template: ValueTemplate::None,
                 ..base.clone()
             },
@@ -226,11 +317,7 @@ impl Config {
                 key: "fault_code",
                 name: "Fault Code",
                 entity_category: Some("diagnostic"),
-                state_topic: &format!(
-                    "{}/{}/input/fault_code/parsed",
-                    self.mqtt_config.namespace(),
-                    self.inverter.datalog()
-                ),
+                state_topic: &self.state_topic("input/fault_code/parsed"),
                 value_template: ValueTemplate::None,
                 icon: Some("mdi:alert"),
                 ..base.clone()
@@ -239,11 +326,7 @@ impl Config {
                 key: "warning_code",
                 name: "Warning Code",
                 entity_category: Some("diagnostic"),
-                state_topic: &format!(
-                    "{}/{}/input/warning_code/parsed",
-                    self.mqtt_config.namespace(),
-                    self.inverter.datalog()
-                ),
+                state_topic: &self.state_topic("input/warning_code/parsed"),
                 value_template: ValueTemplate::None,
                 icon: Some("mdi:alert-outline"),
                 ..base.clone()
@@ -608,6 +691,99 @@ impl Config {
                 unit_of_measurement: Some("s"),
                 ..base.clone()
             },
+            Entity {
+                key: "cell_voltage_delta",
+                name: "Cell Voltage Difference (BMS)",
+                state_topic: &self.state_topic("bms"),
+                ..voltage.clone()
+            },
+            // calculated by the bridge from the other inputs
+            Entity {
+                key: "p_load",
+                name: "House Load",
+                ..power.clone()
+            },
+            Entity {
+                key: "e_load_day",
+                name: "House Consumption (Today)",
+                ..energy.clone()
+            },
+            Entity {
+                key: "self_consumption",
+                name: "Self Consumption (Today)",
+                icon: Some("mdi:solar-power"),
+                ..percentage.clone()
+            },
+            Entity {
+                key: "self_sufficiency",
+                name: "Self Sufficiency (Today)",
+                icon: Some("mdi:home-lightning-bolt"),
+                ..percentage.clone()
+            },
+            Entity {
+                key: "battery_efficiency",
+                name: "Battery Round Trip Efficiency",
+                entity_category: Some("diagnostic"),
+                icon: Some("mdi:battery-sync"),
+                ..percentage.clone()
+            },
+            Entity {
+                key: "inverter_efficiency",
+                name: "Inverter Efficiency",
+                entity_category: Some("diagnostic"),
+                icon: Some("mdi:sine-wave"),
+                ..percentage.clone()
+            },
+            Entity {
+                key: "time_to_empty",
+                name: "Battery Time to Empty",
+                icon: Some("mdi:battery-arrow-down"),
+                ..minutes.clone()
+            },
+            Entity {
+                key: "time_to_full",
+                name: "Battery Time to Full",
+                icon: Some("mdi:battery-arrow-up"),
+                ..minutes.clone()
+            },
+            // derived in the bridge so they never go backwards across the inverter's
+            // midnight or a bad reading; these are the ones for the HA energy dashboard
+            Entity {
+                key: "e_pv_total",
+                name: "Solar Production (Total)",
+                state_topic: &self.state_topic("inputs/energy"),
+                ..energy.clone()
+            },
+            Entity {
+                key: "e_grid_import_total",
+                name: "Grid Import (Total)",
+                state_topic: &self.state_topic("inputs/energy"),
+                ..energy.clone()
+            },
+            Entity {
+                key: "e_grid_export_total",
+                name: "Grid Export (Total)",
+                state_topic: &self.state_topic("inputs/energy"),
+                ..energy.clone()
+            },
+            Entity {
+                key: "e_chg_total",
+                name: "Battery Charge (Total)",
+                state_topic: &self.state_topic("inputs/energy"),
+                ..energy.clone()
+            },
+            Entity {
+                key: "e_dischg_total",
+                name: "Battery Discharge (Total)",
+                state_topic: &self.state_topic("inputs/energy"),
+                ..energy.clone()
+            },
+            Entity {
+                key: "e_eps_total",
+                name: "EPS Output (Total)",
+                state_topic: &self.state_topic("inputs/energy"),
+                ..energy.clone()
+            },
         ];
 
         sensors
@@ -635,26 +811,43 @@ impl Config {
             self.switch("ac_charge", "AC Charge")?,
             self.switch("charge_priority", "Charge Priority")?,
             self.switch("forced_discharge", "Forced Discharge")?,
-            self.number_percent(Register::ChargePowerPercentCmd, "System Charge Rate (%)")?,
-            self.number_percent(Register::DischgPowerPercentCmd, "System Discharge Rate (%)")?,
-            self.number_percent(Register::AcChargePowerCmd, "AC Charge Rate (%)")?,
-            self.number_percent(Register::AcChargeSocLimit, "AC Charge Limit %")?,
-            self.number_percent(Register::ChargePriorityPowerCmd, "Charge Priority Rate (%)")?,
-            self.number_percent(Register::ChargePrioritySocLimit, "Charge Priority Limit %")?,
-            self.number_percent(Register::ForcedDischgSocLimit, "Forced Discharge Limit %")?,
-            self.number_percent(Register::DischgCutOffSocEod, "Discharge Cutoff %")?,
-            self.number_percent(
+            self.number(Register::ChargePowerPercentCmd, "System Charge Rate (%)")?,
+            self.number(Register::DischgPowerPercentCmd, "System Discharge Rate (%)")?,
+            self.number(Register::AcChargePowerCmd, "AC Charge Rate (%)")?,
+            self.number(Register::AcChargeSocLimit, "AC Charge Limit %")?,
+            self.number(Register::ChargePriorityPowerCmd, "Charge Priority Rate (%)")?,
+            self.number(Register::ChargePrioritySocLimit, "Charge Priority Limit %")?,
+            self.number(Register::ForcedDischgSocLimit, "Forced Discharge Limit %")?,
+            self.number(Register::DischgCutOffSocEod, "Discharge Cutoff %")?,
+            self.number(
                 Register::EpsDischgCutoffSocEod,
                 "Discharge Cutoff for EPS %",
             )?,
-            self.number_percent(
+            self.number(
                 Register::AcChargeStartSocLimit,
                 "Charge From AC Lower Limit %",
             )?,
-            self.number_percent(
+            self.number(
                 Register::AcChargeEndSocLimit,
                 "Charge From AC Upper Limit %",
             )?,
+            self.number(Register::ChargeCurrentLimit, "Charge Current Limit")?,
+            self.number(Register::DischgCurrentLimit, "Discharge Current Limit")?,
+            self.number(Register::AcChargeBatteryCurrent, "AC Charge Current Limit")?,
+            self.number(Register::FeedInGridPowerPercent, "Export Limit %")?,
+            self.number(
+                Register::AcChargeStartBatteryVoltage,
+                "Charge From AC Lower Limit (V)",
+            )?,
+            self.number(
+                Register::AcChargeEndBatteryVoltage,
+                "Charge From AC Upper Limit (V)",
+            )?,
+            self.number(Register::OnGridEodVoltage, "Discharge Cutoff (V)")?,
+            self.working_mode()?,
+            self.button("set/timesync", "Sync Time")?,
+            self.button("read/holdings", "Read Holdings")?,
+            self.button("reconnect", "Reconnect")?,
             self.time_range("ac_charge/1", "AC Charge Timeslot 1")?,
             self.time_range("ac_charge/2", "AC Charge Timeslot 2")?,
             self.time_range("ac_charge/3", "AC Charge Timeslot 3")?,
@@ -669,17 +862,38 @@ impl Config {
             self.time_range("forced_discharge/3", "Forced Discharge Timeslot 3")?,
         ];
 
+        // 145 and 146 mean something else on hybrids, so these only go to
+        // inverters we know are off-grid
+        let off_grid =
+            matches!(&self.device_info, Some(info) if info.family() == ModelFamily::OffGrid);
+        if off_grid {
+            r.push(self.select(Register::OutputConfiguration, "Output Priority")?);
+            r.push(self.select(Register::LineModeInput, "AC Input Range")?);
+        }
+
+        if self.generator {
+            r.append(&mut self.generator_entities()?);
+        }
+
         r.append(&mut self.sensors());
+        r.append(&mut self.bms_flags()?);
+        r.append(&mut self.code_flags()?);
+        r.push(self.datalogger()?);
 
         Ok(r)
     }
 
+    // there's nothing to control directly or connect to, so just the sensors
+    pub fn system(&self) -> Vec<mqtt::Message> {
+        self.sensors()
+    }
+
     fn ha_discovery_topic(&self, kind: &str, name: &str) -> String {
         format!(
             "{}/{}/lxp_{}/{}/config",
             self.mqtt_config.homeassistant().prefix(),
             kind,
-            self.inverter.datalog(),
+            self.datalog,
             // The forward slash is used in some names (e.g. ac_charge/1) but
             // has semantic meaning in MQTT, so must be changed
             name.replace('/', "_"),
@@ -689,21 +903,18 @@ impl Config {
     fn switch(&self, name: &str, label: &str) -> Result<mqtt::Message> {
         let config = Switch {
             value_template: format!("{{{{ value_json.{}_en }}}}", name),
-            state_topic: format!(
-                "{}/{}/hold/21/bits",
-                self.mqtt_config.namespace(),
-                self.inverter.datalog()
-            ),
+            state_topic: self.state_topic("hold/21/bits"),
             command_topic: format!(
                 "{}/cmd/{}/set/{}",
                 self.mqtt_config.namespace(),
-                self.inverter.datalog(),
+                self.datalog,
                 name
             ),
-            unique_id: format!("lxp_{}_{}", self.inverter.datalog(), name),
+            unique_id: format!("lxp_{}_{}", self.datalog, name),
             name: label.to_string(),
             device: self.device(),
             availability: self.availability(),
+            availability_mode: "all",
         };
 
         Ok(mqtt::Message {
@@ -713,29 +924,36 @@ impl Config {
         })
     }
 
-    fn number_percent(&self, register: Register, label: &str) -> Result<mqtt::Message> {
+    fn number(&self, register: Register, label: &str) -> Result<mqtt::Message> {
+        let range = register.range();
+
+        // scaled registers hold eg tenths of a volt; HA deals in the real value
+        let (value_template, command_template) = if range.scale == 1.0 {
+            ("{{ float(value) }}".to_string(), None)
+        } else {
+            (
+                format!("{{{{ float(value) / {} }}}}", range.scale),
+                Some(format!(
+                    "{{{{ (value | float * {}) | round | int }}}}",
+                    range.scale
+                )),
+            )
+        };
+
         let config = Number {
             name: label.to_string(),
-            state_topic: format!(
-                "{}/{}/hold/{}",
-                self.mqtt_config.namespace(),
-                self.inverter.datalog(),
-                register as u16,
-            ),
-            command_topic: format!(
-                "{}/cmd/{}/set/hold/{}",
-                self.mqtt_config.namespace(),
-                self.inverter.datalog(),
-                register as u16,
-            ),
-            value_template: "{{ float(value) }}".to_string(),
-            unique_id: format!("lxp_{}_number_{:?}", self.inverter.datalog(), register),
+            state_topic: self.state_topic(&format!("hold/{}", register as u16)),
+            command_topic: self.command_topic(&format!("set/hold/{}", register as u16)),
+            value_template,
+            command_template,
+            unique_id: format!("lxp_{}_number_{:?}", self.datalog, register),
             device: self.device(),
             availability: self.availability(),
-            min: 0.0,
-            max: 100.0,
-            step: 1.0,
-            unit_of_measurement: "%".to_string(),
+            availability_mode: "all",
+            min: range.min,
+            max: range.max,
+            step: range.step,
+            unit_of_measurement: range.unit.to_string(),
         };
 
         Ok(mqtt::Message {
@@ -745,27 +963,114 @@ impl Config {
         })
     }
 
+    // A select for a register holding an enumeration; HA shows the labels, the
+    // inverter gets the raw value.
+    fn select(&self, register: Register, label: &str) -> Result<mqtt::Message> {
+        let options = register.options();
+
+        let to_label = options
+            .iter()
+            .map(|(value, label)| format!("{}: '{}'", value, label))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let to_value = options
+            .iter()
+            .map(|(value, label)| format!("'{}': {}", label, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let config = Select {
+            name: label.to_string(),
+            state_topic: self.state_topic(&format!("hold/{}", register as u16)),
+            command_topic: self.command_topic(&format!("set/hold/{}", register as u16)),
+            value_template: format!("{{{{ {{{}}}[value | int] }}}}", to_label),
+            command_template: Some(format!("{{{{ {{{}}}[value] }}}}", to_value)),
+            options: options.iter().map(|(_, label)| label.to_string()).collect(),
+            unique_id: format!("lxp_{}_select_{:?}", self.datalog, register),
+            device: self.device(),
+            availability: self.availability(),
+            availability_mode: "all",
+        };
+
+        Ok(mqtt::Message {
+            topic: self.ha_discovery_topic("select", &format!("{:?}", register)),
+            retain: true,
+            payload: serde_json::to_string(&config)?,
+        })
+    }
+
+    // Presents the AC Charge / Charge Priority / Forced Discharge switches as
+    // one select, since only one of them should be on at a time.
+    fn working_mode(&self) -> Result<mqtt::Message> {
+        use lxp::packet::WorkingMode;
+
+        let config = Select {
+            name: "Working Mode".to_string(),
+            state_topic: self.state_topic("hold/21/bits"),
+            command_topic: self.command_topic("set/working_mode"),
+            value_template: format!(
+                "{{% if value_json.forced_discharge_en == 'ON' %}}{}\
+                 {{% elif value_json.ac_charge_en == 'ON' %}}{}\
+                 {{% elif value_json.charge_priority_en == 'ON' %}}{}\
+                 {{% else %}}{}{{% endif %}}",
+                WorkingMode::ForcedDischarge.label(),
+                WorkingMode::AcCharge.label(),
+                WorkingMode::ChargePriority.label(),
+                WorkingMode::SelfUse.label(),
+            ),
+            command_template: None,
+            options: WorkingMode::ALL
+                .iter()
+                .map(|mode| mode.label().to_string())
+                .collect(),
+            unique_id: self.unique_id("working_mode"),
+            device: self.device(),
+            availability: self.availability(),
+            availability_mode: "all",
+        };
+
+        Ok(mqtt::Message {
+            topic: self.ha_discovery_topic("select", "working_mode"),
+            retain: true,
+            payload: serde_json::to_string(&config)?,
+        })
+    }
+
+    fn button(&self, command: &str, label: &str) -> Result<mqtt::Message> {
+        let config = Button {
+            name: label.to_string(),
+            command_topic: self.command_topic(command),
+            payload_press: String::new(),
+            unique_id: self.unique_id(&format!("button_{}", command.replace('/', "_"))),
+            device: self.device(),
+            availability: self.availability(),
+            availability_mode: "all",
+        };
+
+        Ok(mqtt::Message {
+            topic: self.ha_discovery_topic("button", command),
+            retain: true,
+            payload: serde_json::to_string(&config)?,
+        })
+    }
+
     // Models a time range as an MQTT Text field taking values like: 00:00-23:59
     fn time_range(&self, name: &str, label: &str) -> Result<mqtt::Message> {
         let config = Text {
             name: label.to_string(),
-            state_topic: format!(
-                "{}/{}/{}",
-                self.mqtt_config.namespace(),
-                self.inverter.datalog(),
-                name,
-            ),
+            state_topic: self.state_topic(name),
             command_topic: format!(
                 "{}/cmd/{}/set/{}",
                 self.mqtt_config.namespace(),
-                self.inverter.datalog(),
+                self.datalog,
                 name,
             ),
             command_template: r#"{% set parts = value.split("-") %}{"start":"{{ parts[0] }}", "end":"{{ parts[1] }}"}"#.to_string(),
             value_template: r#"{{ value_json["start"] }}-{{ value_json["end"] }}"#.to_string(),
-            unique_id: format!("lxp_{}_text_{}", self.inverter.datalog(), name),
+            unique_id: format!("lxp_{}_text_{}", self.datalog, name),
             device: self.device(),
             availability: self.availability(),
+            availability_mode: "all",
             pattern: r"([01]?[0-9]|2[0-3]):[0-5][0-9]-([01]?[0-9]|2[0-3]):[0-5][0-9]".to_string(),
         };
 
@@ -776,21 +1081,235 @@ impl Config {
         })
     }
 
+    // topics we publish to are subject to the configured templates, so work them out
+    // the same way mqtt::Mqtt does
+    fn state_topic(&self, topic: &str) -> String {
+        mqtt::Message::publish_topic(&format!("{}/{}", self.datalog, topic), &self.mqtt_config)
+    }
+
+    fn command_topic(&self, command: &str) -> String {
+        format!(
+            "{}/cmd/{}/{}",
+            self.mqtt_config.namespace(),
+            self.datalog,
+            command
+        )
+    }
+
     fn unique_id(&self, name: &str) -> String {
-        format!("lxp_{}_{}", self.inverter.datalog(), name)
+        format!("lxp_{}_{}", self.datalog, name)
     }
 
     fn device(&self) -> Device {
+        let info = self.device_info.as_ref();
+
         Device {
-            identifiers: [format!("lxp_{}", self.inverter.datalog())],
+            identifiers: [format!("lxp_{}", self.datalog)],
             manufacturer: "LuxPower".to_owned(),
-            name: format!("lxp_{}", self.inverter.datalog()),
+            name: format!("lxp_{}", self.datalog),
+            model: info.map(|i| i.model_name()),
+            sw_version: info.map(|i| i.sw_version()),
+            hw_version: info.map(|i| i.hw_version()),
+            serial_number: info.map(|i| i.serial.to_owned()),
+            via_device: info.map(|_| self.datalogger_id()),
         }
     }
 
-    fn availability(&self) -> Availability {
-        Availability {
-            topic: format!("{}/LWT", self.mqtt_config.namespace()),
+    fn bms_flags(&self) -> Result<Vec<mqtt::Message>> {
+        [
+            ("cell_imbalance", "BMS Cell Imbalance"),
+            ("fault.cell_overvoltage", "BMS Cell Overvoltage"),
+            ("fault.cell_undervoltage", "BMS Cell Undervoltage"),
+            ("fault.overtemperature", "BMS Overtemperature"),
+            ("fault.undertemperature", "BMS Undertemperature"),
+            ("fault.discharge_overcurrent", "BMS Discharge Overcurrent"),
+            ("fault.charge_overcurrent", "BMS Charge Overcurrent"),
+            ("fault.system_error", "BMS System Error"),
+            ("warning.high_voltage", "BMS High Voltage Warning"),
+            ("warning.low_voltage", "BMS Low Voltage Warning"),
+            ("warning.high_temperature", "BMS High Temperature Warning"),
+            ("warning.low_temperature", "BMS Low Temperature Warning"),
+            (
+                "warning.discharge_high_current",
+                "BMS Discharge Current Warning",
+            ),
+            ("warning.charge_high_current", "BMS Charge Current Warning"),
+            ("warning.comms_error", "BMS Communication Error"),
+        ]
+        .iter()
+        .map(|(key, label)| self.bms_flag(key, label))
+        .collect()
+    }
+
+    // A BMS fault or warning flag from the <datalog>/bms payload, eg fault.cell_overvoltage
+    fn bms_flag(&self, key: &str, label: &str) -> Result<mqtt::Message> {
+        self.problem(
+            &format!("bms_{}", key.replace('.', "_")),
+            label,
+            "bms",
+            format!("{{{{ value_json.{} }}}}", key),
+        )
+    }
+
+    // settings, and whether it's supplying power from the <datalog>/generator
+    // payload; only for inverters configured with something on that port
+    fn generator_entities(&self) -> Result<Vec<mqtt::Message>> {
+        let supplying = BinarySensor {
+            name: "Generator Supplying Power".to_owned(),
+            state_topic: self.state_topic("generator"),
+            value_template: Some("{{ value_json.supplying }}".to_owned()),
+            payload_on: "ON".to_owned(),
+            payload_off: "OFF".to_owned(),
+            device_class: "power".to_owned(),
+            entity_category: "diagnostic".to_owned(),
+            unique_id: self.unique_id("generator_supplying"),
+            device: self.device(),
+            availability: self.availability(),
+            availability_mode: Some("all"),
+            json_attributes_topic: None,
+            json_attributes_template: None,
+        };
+
+        Ok(vec![
+            self.number(Register::GenRatedPower, "Generator Rated Power (kW)")?,
+            self.number(
+                Register::GenChargeStartVoltage,
+                "Generator Start Voltage (V)",
+            )?,
+            self.number(Register::GenChargeEndVoltage, "Generator Stop Voltage (V)")?,
+            self.number(Register::GenChargeStartSocLimit, "Generator Start SOC %")?,
+            self.number(Register::GenChargeEndSocLimit, "Generator Stop SOC %")?,
+            self.number(
+                Register::GenChargeBatteryCurrent,
+                "Generator Charge Current (A)",
+            )?,
+            mqtt::Message {
+                topic: self.ha_discovery_topic("binary_sensor", "generator_supplying"),
+                retain: true,
+                payload: serde_json::to_string(&supplying)?,
+            },
+        ])
+    }
+
+    // One binary sensor each for faults and warnings, on whether anything is
+    // in the <datalog>/faults (or warnings) array. What is, with severities,
+    // goes in the attributes.
+    fn code_flags(&self) -> Result<Vec<mqtt::Message>> {
+        [("faults", "Faults"), ("warnings", "Warnings")]
+            .into_iter()
+            .map(|(topic, label)| {
+                let config = BinarySensor {
+                    json_attributes_topic: Some(self.state_topic(topic)),
+                    json_attributes_template: Some("{{ {'active': value_json} | tojson }}"),
+                    ..self.problem_config(
+                        topic,
+                        label,
+                        topic,
+                        "{{ 'ON' if value_json | length > 0 else 'OFF' }}".to_owned(),
+                    )
+                };
+
+                Ok(mqtt::Message {
+                    topic: self.ha_discovery_topic("binary_sensor", topic),
+                    retain: true,
+                    payload: serde_json::to_string(&config)?,
+                })
+            })
+            .collect()
+    }
+
+    fn problem(
+        &self,
+        name: &str,
+        label: &str,
+        topic: &str,
+        value_template: String,
+    ) -> Result<mqtt::Message> {
+        let config = self.problem_config(name, label, topic, value_template);
+
+        Ok(mqtt::Message {
+            topic: self.ha_discovery_topic("binary_sensor", name),
+            retain: true,
+            payload: serde_json::to_string(&config)?,
+        })
+    }
+
+    fn problem_config(
+        &self,
+        name: &str,
+        label: &str,
+        topic: &str,
+        value_template: String,
+    ) -> BinarySensor {
+        BinarySensor {
+            name: label.to_owned(),
+            state_topic: self.state_topic(topic),
+            value_template: Some(value_template),
+            payload_on: "ON".to_owned(),
+            payload_off: "OFF".to_owned(),
+            device_class: "problem".to_owned(),
+            entity_category: "diagnostic".to_owned(),
+            unique_id: self.unique_id(name),
+            device: self.device(),
+            availability: self.availability(),
+            availability_mode: Some("all"),
+            json_attributes_topic: None,
+            json_attributes_template: None,
         }
     }
+
+    // The datalogger (dongle) the inverter is reached through, as its own device.
+    // Its only entity is whether we currently have a connection to it.
+    fn datalogger(&self) -> Result<mqtt::Message> {
+        let config = BinarySensor {
+            name: "Connection".to_owned(),
+            state_topic: self.state_topic("availability"),
+            value_template: None,
+            payload_on: "online".to_owned(),
+            payload_off: "offline".to_owned(),
+            device_class: "connectivity".to_owned(),
+            entity_category: "diagnostic".to_owned(),
+            unique_id: format!("{}_connection", self.datalogger_id()),
+            device: Device {
+                identifiers: [self.datalogger_id()],
+                manufacturer: "LuxPower".to_owned(),
+                name: self.datalogger_id(),
+                model: Some("Datalogger".to_owned()),
+                sw_version: None,
+                hw_version: None,
+                serial_number: Some(self.datalog.to_string()),
+                via_device: None,
+            },
+            // not the inverter availability, or we'd show unavailable rather than disconnected
+            availability: vec![Availability {
+                topic: format!("{}/LWT", self.mqtt_config.namespace()),
+            }],
+            availability_mode: None,
+            json_attributes_topic: None,
+            json_attributes_template: None,
+        };
+
+        Ok(mqtt::Message {
+            topic: self.ha_discovery_topic("binary_sensor", "connection"),
+            retain: true,
+            payload: serde_json::to_string(&config)?,
+        })
+    }
+
+    fn datalogger_id(&self) -> String {
+        format!("lxp_datalog_{}", self.datalog)
+    }
+
+    // entities are only available when both the bridge is connected to mqtt, and
+    // the bridge is connected to this inverter
+    fn availability(&self) -> Vec<Availability> {
+        vec![
+            Availability {
+                topic: format!("{}/LWT", self.mqtt_config.namespace()),
+            },
+            Availability {
+                topic: self.state_topic("availability"),
+            },
+        ]
+    }
 }
@@ -1,110 +1,109 @@
 use crate::prelude::*;
 
+use async_trait::async_trait;
 use chrono::TimeZone;
-use rinfluxdb::line_protocol::{r#async::Client, LineBuilder};
+use output::{Output, OutputData};
+use rinfluxdb::line_protocol::{r#async::Client, Line, LineBuilder};
 
 static INPUTS_MEASUREMENT: &str = "inputs";
 
-#[derive(Eq, PartialEq, Clone, Debug)]
-pub enum ChannelData {
-    InputData(serde_json::Value),
-    Shutdown,
-}
-
 pub struct Influx {
     config: ConfigWrapper,
-    channels: Channels,
 }
 
 impl Influx {
-    pub fn new(config: ConfigWrapper, channels: Channels) -> Self {
-        Self { config, channels }
+    pub fn new(config: ConfigWrapper) -> Self {
+        Self { config }
     }
 
-    pub async fn start(&self) -> Result<()> {
-        if !self.config.influx().enabled() {
-            info!("influx disabled, skipping");
-            return Ok(());
-        }
+    fn client(&self) -> Result<Client> {
+        let config = self.config.influx();
+        let url = reqwest::Url::parse(config.url())?;
+        let credentials = match (config.username(), config.password()) {
+            (Some(u), Some(p)) => Some((u, p)),
+            _ => None,
+        };
 
-        info!("initializing influx at {}", self.config.influx().url());
+        Ok(Client::new(url, credentials)?)
+    }
 
-        let client = {
-            let config = self.config.influx();
-            let url = reqwest::Url::parse(config.url())?;
-            let credentials = match (config.username(), config.password()) {
-                (Some(u), Some(p)) => Some((u, p)),
-                _ => None,
-            };
+    fn line(data: &serde_json::Value) -> Line {
+        let mut line = LineBuilder::new(INPUTS_MEASUREMENT);
 
-            Client::new(url, credentials)?
-        };
+        for (key, value) in data.as_object().unwrap() {
+            let key = key.to_string();
+
+            // derived values with nothing to show; influx has no null
+            if value.is_null() {
+                continue;
+            }
+
+            line = if key == "time" {
+                let value = value
+                    .as_i64()
+                    .unwrap_or_else(|| panic!("cannot represent {value} as i64 for {key}"));
+                line.set_timestamp(chrono::Utc.timestamp_opt(value, 0).unwrap())
+            } else if key == "datalog" {
+                let value = value
+                    .as_str()
+                    .unwrap_or_else(|| panic!("cannot represent {value} as str for {key}"));
+                line.insert_tag(key, value)
+            } else if value.is_f64() {
+                let value = value
+                    .as_f64()
+                    .unwrap_or_else(|| panic!("cannot represent {value} as f64 for {key}"));
+                line.insert_field(key, value)
+            } else {
+                // can't be anything other than int
+                let value = value
+                    .as_i64()
+                    .unwrap_or_else(|| panic!("cannot represent {value} as i64 for {key}"));
+                line.insert_field(key, value)
+            }
+        }
+
+        line.build()
+    }
 
-        futures::try_join!(self.sender(client))?;
+    fn database(&self) -> String {
+        self.config.influx().database().to_string()
+    }
+}
 
-        info!("influx loop exiting");
+#[async_trait(?Send)]
+impl Output for Influx {
+    fn name(&self) -> String {
+        format!("influx at {}", self.config.influx().url())
+    }
+
+    async fn connect(&self) -> Result<()> {
+        // nothing to connect to, but catches a bad url early
+        self.client()?;
 
         Ok(())
     }
 
-    pub fn stop(&self) {
-        let _ = self.channels.to_influx.send(ChannelData::Shutdown);
+    fn filter(&self, data: OutputData) -> Option<OutputData> {
+        match data {
+            OutputData::InputAll(_) => Some(data),
+            OutputData::Hold(..) | OutputData::Battery(_) => None,
+        }
     }
 
-    async fn sender(&self, client: Client) -> Result<()> {
-        use ChannelData::*;
-
-        let mut receiver = self.channels.to_influx.subscribe();
-
-        loop {
-            let mut line = LineBuilder::new(INPUTS_MEASUREMENT);
-
-            match receiver.recv().await? {
-                Shutdown => break,
-                InputData(data) => {
-                    for (key, value) in data.as_object().unwrap() {
-                        let key = key.to_string();
-
-                        line = if key == "time" {
-                            let value = value.as_i64().unwrap_or_else(|| {
-                                panic!("cannot represent {value} as i64 for {key}")
-                            });
-                            line.set_timestamp(chrono::Utc.timestamp_opt(value, 0).unwrap())
-                        } else if key == "datalog" {
-                            let value = value.as_str().unwrap_or_else(|| {
-                                panic!("cannot represent {value} as str for {key}")
-                            });
-                            line.insert_tag(key, value)
-                        } else if value.is_f64() {
-                            let value = value.as_f64().unwrap_or_else(|| {
-                                panic!("cannot represent {value} as f64 for {key}")
-                            });
-                            line.insert_field(key, value)
-                        } else {
-                            // can't be anything other than int
-                            let value = value.as_i64().unwrap_or_else(|| {
-                                panic!("cannot represent {value} as i64 for {key}")
-                            });
-                            line.insert_field(key, value)
-                        }
-                    }
-
-                    let lines = vec![line.build()];
-
-                    while let Err(err) = client.send(&self.database(), &lines).await {
-                        error!("push failed: {:?} - retrying in 10s", err);
-                        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-                    }
-                }
+    fn batch_size(&self) -> usize {
+        self.config.influx().batch_size()
+    }
+
+    async fn write(&self, batch: &[OutputData]) -> Result<()> {
+        let mut lines = Vec::new();
+        for data in batch {
+            if let OutputData::InputAll(input) = data {
+                lines.push(Self::line(&serde_json::to_value(input)?));
             }
         }
 
-        info!("sender loop exiting");
+        self.client()?.send(&self.database(), &lines).await?;
 
         Ok(())
     }
-
-    fn database(&self) -> String {
-        self.config.influx().database().to_string()
-    }
 }
@@ -1,18 +1,30 @@
+pub mod alerts;
+pub mod battery;
 pub mod channels;
 pub mod command;
 pub mod config;
 pub mod coordinator;
 pub mod database;
+pub mod derived;
+pub mod energy;
+pub mod hold_writes;
 pub mod home_assistant;
 pub mod influx;
 pub mod lxp;
 pub mod mqtt;
+pub mod optimiser;
 pub mod options;
+pub mod output;
 pub mod prelude;
+pub mod pvoutput;
 pub mod register_cache;
 pub mod scheduler;
+pub mod solar;
+pub mod system;
+pub mod tariff;
 pub mod unixtime;
 pub mod utils;
+pub mod webhook;
 
 const CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -47,9 +59,11 @@ pub async fn app() -> Result<()> {
 
     let scheduler = Scheduler::new(config.clone(), channels.clone());
     let mqtt = Mqtt::new(config.clone(), channels.clone());
-    let influx = Influx::new(config.clone(), channels.clone());
+    let outputs = Outputs::new(output::build(&config), channels.clone());
     let register_cache = RegisterCache::new(channels.clone());
     let coordinator = Coordinator::new(config.clone(), channels.clone());
+    let alerts = Alerts::new(config.clone(), channels.clone());
+    let optimiser = Optimiser::new(config.clone(), channels.clone());
 
     let inverters = config
         .enabled_inverters()
@@ -57,33 +71,20 @@ pub async fn app() -> Result<()> {
         .map(|inverter| Inverter::new(config.clone(), &inverter, channels.clone()))
         .collect();
 
-    let databases = config
-        .enabled_databases()
-        .into_iter()
-        .map(|database| Database::new(database, channels.clone()))
-        .collect();
-
     futures::try_join!(
-        start_databases(databases),
         start_inverters(inverters),
         scheduler.start(),
         mqtt.start(),
-        influx.start(),
+        outputs.start(),
         register_cache.start(),
-        coordinator.start()
+        coordinator.start(),
+        alerts.start(),
+        optimiser.start()
     )?;
 
     Ok(())
 }
 
-async fn start_databases(databases: Vec<Database>) -> Result<()> {
-    let futures = databases.iter().map(|d| d.start());
-
-    futures::future::join_all(futures).await;
-
-    Ok(())
-}
-
 async fn start_inverters(inverters: Vec<Inverter>) -> Result<()> {
     let futures = inverters.iter().map(|i| i.start());
 
@@ -269,7 +269,10 @@ impl Inverter {
                 Shutdown => break,
                 // this doesn't actually happen yet; (Dis)connect is never sent to this channel
                 Connected(_) => {}
-                Disconnect(_) => bail!("sender exiting due to ChannelData::Disconnect"),
+                Disconnect(datalog) if datalog == self.config().datalog() => {
+                    bail!("sender exiting due to ChannelData::Disconnect")
+                }
+                Disconnect(_) => {}
                 Packet(packet) => {
                     // this works, but needs more thought. because we only fix it here, immediately
                     // before transmission, calls to wait_for_reply with the original serials will
@@ -217,6 +217,25 @@ pub struct ReadInputAll {
 
     // EPS data; unsure what this is
 
+    // calculated by derived::apply rather than read from the inverter.
+    // None where there's nothing sensible to show, eg time_to_empty when charging
+    #[nom(Ignore)]
+    pub p_load: u16,
+    #[nom(Ignore)]
+    pub e_load_day: f64,
+    #[nom(Ignore)]
+    pub self_consumption: Option<f64>, // %, today
+    #[nom(Ignore)]
+    pub self_sufficiency: Option<f64>, // %, today
+    #[nom(Ignore)]
+    pub battery_efficiency: Option<f64>, // %, all time
+    #[nom(Ignore)]
+    pub inverter_efficiency: Option<f64>, // %, now
+    #[nom(Ignore)]
+    pub time_to_empty: Option<u32>, // minutes
+    #[nom(Ignore)]
+    pub time_to_full: Option<u32>, // minutes
+
     // following are for influx capability only
     #[nom(Parse = "Utils::current_time_for_nom")]
     pub time: UnixTime,
@@ -424,6 +443,43 @@ pub struct ReadInput3 {
     pub datalog: Serial,
 } // }}}
 
+// the battery half of a ReadInputAll
+impl From<&ReadInputAll> for ReadInput3 {
+    fn from(input: &ReadInputAll) -> Self {
+        Self {
+            max_chg_curr: input.max_chg_curr,
+            max_dischg_curr: input.max_dischg_curr,
+            charge_volt_ref: input.charge_volt_ref,
+            dischg_cut_volt: input.dischg_cut_volt,
+            bat_status_0: input.bat_status_0,
+            bat_status_1: input.bat_status_1,
+            bat_status_2: input.bat_status_2,
+            bat_status_3: input.bat_status_3,
+            bat_status_4: input.bat_status_4,
+            bat_status_5: input.bat_status_5,
+            bat_status_6: input.bat_status_6,
+            bat_status_7: input.bat_status_7,
+            bat_status_8: input.bat_status_8,
+            bat_status_9: input.bat_status_9,
+            bat_status_inv: input.bat_status_inv,
+            bat_count: input.bat_count,
+            bat_capacity: input.bat_capacity,
+            bat_current: input.bat_current,
+            bms_event_1: input.bms_event_1,
+            bms_event_2: input.bms_event_2,
+            max_cell_voltage: input.max_cell_voltage,
+            min_cell_voltage: input.min_cell_voltage,
+            max_cell_temp: input.max_cell_temp,
+            min_cell_temp: input.min_cell_temp,
+            bms_fw_update_state: input.bms_fw_update_state,
+            cycle_count: input.cycle_count,
+            vbat_inv: input.vbat_inv,
+            time: input.time.clone(),
+            datalog: input.datalog,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Nom)]
 #[nom(LittleEndian)]
 pub struct ReadInput4 {
@@ -489,7 +545,7 @@ impl ReadInputs {
             self.read_input_3.as_ref(),
             self.read_input_4.as_ref(),
         ) {
-            (Some(ri1), Some(ri2), Some(ri3), Some(ri4)) => Some(ReadInputAll {
+            (Some(ri1), Some(ri2), Some(ri3), Some(ri4)) => Some(derived::apply(ReadInputAll {
                 status: ri1.status,
                 v_pv_1: ri1.v_pv_1,
                 v_pv_2: ri1.v_pv_2,
@@ -594,9 +650,17 @@ impl ReadInputs {
                 e_eps_l2_day: ri4.e_eps_l2_day,
                 e_eps_l1_all: ri4.e_eps_l1_all,
                 e_eps_l2_all: ri4.e_eps_l2_all,
+                p_load: 0,
+                e_load_day: 0.0,
+                self_consumption: None,
+                self_sufficiency: None,
+                battery_efficiency: None,
+                inverter_efficiency: None,
+                time_to_empty: None,
+                time_to_full: None,
                 datalog: ri1.datalog,
                 time: ri1.time.clone(),
-            }),
+            })),
             _ => None,
         }
     }
@@ -632,20 +696,156 @@ pub enum DeviceFunction {
 #[derive(Clone, Copy, Debug, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u16)]
 pub enum Register {
-    Register21 = 21,             // not sure of a better name for this one..
-    ChargePowerPercentCmd = 64,  // System Charge Rate (%)
-    DischgPowerPercentCmd = 65,  // System Discharge Rate (%)
-    AcChargePowerCmd = 66,       // Grid Charge Power Rate (%)
-    AcChargeSocLimit = 67,       // AC Charge SOC Limit (%)
-    ChargePriorityPowerCmd = 74, // Charge Priority Charge Rate (%)
-    ChargePrioritySocLimit = 75, // Charge Priority SOC Limit (%)
-    ForcedDischgSocLimit = 83,   // Forced Discarge SOC Limit (%)
-    DischgCutOffSocEod = 105,    // Discharge cut-off SOC (%)
-    EpsDischgCutoffSocEod = 125, // EPS Discharge cut-off SOC (%)
-    AcChargeStartSocLimit = 160, // SOC at which AC charging will begin (%)
-    AcChargeEndSocLimit = 161,   // SOC at which AC charging will end (%)
+    Register21 = 21,                   // not sure of a better name for this one..
+    ChargePowerPercentCmd = 64,        // System Charge Rate (%)
+    DischgPowerPercentCmd = 65,        // System Discharge Rate (%)
+    AcChargePowerCmd = 66,             // Grid Charge Power Rate (%)
+    AcChargeSocLimit = 67,             // AC Charge SOC Limit (%)
+    ChargePriorityPowerCmd = 74,       // Charge Priority Charge Rate (%)
+    ChargePrioritySocLimit = 75,       // Charge Priority SOC Limit (%)
+    ForcedDischgSocLimit = 83,         // Forced Discarge SOC Limit (%)
+    ChargeCurrentLimit = 101,          // LEAD_ACID_CHARGE_RATE, used for lithium too (A)
+    DischgCurrentLimit = 102,          // LEAD_ACID_DISCHARGE_RATE, used for lithium too (A)
+    FeedInGridPowerPercent = 103,      // Export limit (%)
+    DischgCutOffSocEod = 105,          // Discharge cut-off SOC (%)
+    EpsDischgCutoffSocEod = 125,       // EPS Discharge cut-off SOC (%)
+    OutputConfiguration = 145,         // Output priority (off-grid models)
+    LineModeInput = 146,               // AC input range (off-grid models)
+    AcChargeStartBatteryVoltage = 158, // Battery voltage at which AC charging will begin (V/10)
+    AcChargeEndBatteryVoltage = 159,   // Battery voltage at which AC charging will end (V/10)
+    AcChargeStartSocLimit = 160,       // SOC at which AC charging will begin (%)
+    AcChargeEndSocLimit = 161,         // SOC at which AC charging will end (%)
+    AcChargeBatteryCurrent = 168,      // AC charge current limit (A)
+    OnGridEodVoltage = 169,            // On-grid discharge cut-off voltage (V/10)
+    GenRatedPower = 177,               // Generator rated power (kW/10)
+    GenChargeStartVoltage = 194,       // Battery voltage at which the generator will start (V/10)
+    GenChargeEndVoltage = 195,         // Battery voltage at which the generator will stop (V/10)
+    GenChargeStartSocLimit = 196,      // SOC at which the generator will start (%)
+    GenChargeEndSocLimit = 197,        // SOC at which the generator will stop (%)
+    GenChargeBatteryCurrent = 198,     // Generator charge current limit (A)
+}
+
+// what HA needs to know to present a numeric register sensibly
+pub struct RegisterRange {
+    pub unit: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+    pub scale: f64, // register value = displayed value * scale
 }
 
+impl Register {
+    pub fn range(&self) -> RegisterRange {
+        use Register::*;
+
+        let percent = RegisterRange {
+            unit: "%",
+            min: 0.0,
+            max: 100.0,
+            step: 1.0,
+            scale: 1.0,
+        };
+        let current = RegisterRange {
+            unit: "A",
+            min: 0.0,
+            max: 200.0,
+            step: 1.0,
+            scale: 1.0,
+        };
+        let voltage = RegisterRange {
+            unit: "V",
+            min: 40.0,
+            max: 59.0,
+            step: 0.1,
+            scale: 10.0,
+        };
+
+        match self {
+            ChargeCurrentLimit
+            | DischgCurrentLimit
+            | AcChargeBatteryCurrent
+            | GenChargeBatteryCurrent => current,
+            AcChargeStartBatteryVoltage
+            | AcChargeEndBatteryVoltage
+            | OnGridEodVoltage
+            | GenChargeStartVoltage
+            | GenChargeEndVoltage => voltage,
+            GenRatedPower => RegisterRange {
+                unit: "kW",
+                min: 0.0,
+                max: 50.0,
+                step: 0.1,
+                scale: 10.0,
+            },
+            _ => percent,
+        }
+    }
+
+    // (value, label) pairs for registers which hold an enumeration
+    pub fn options(&self) -> &'static [(u16, &'static str)] {
+        use Register::*;
+
+        match self {
+            OutputConfiguration => &[(0, "Battery First"), (1, "PV First"), (2, "AC First")],
+            LineModeInput => &[(0, "APL"), (1, "UPS"), (2, "GEN")],
+            _ => &[],
+        }
+    }
+}
+
+// WorkingMode {{{
+// The mutually exclusive uses of the charge/discharge bits in register 21,
+// as presented in the LuxPower app.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WorkingMode {
+    SelfUse,
+    AcCharge,
+    ChargePriority,
+    ForcedDischarge,
+}
+
+impl WorkingMode {
+    pub const ALL: [WorkingMode; 4] = [
+        Self::SelfUse,
+        Self::AcCharge,
+        Self::ChargePriority,
+        Self::ForcedDischarge,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::SelfUse => "Self Use",
+            Self::AcCharge => "AC Charge",
+            Self::ChargePriority => "Charge Priority",
+            Self::ForcedDischarge => "Forced Discharge",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.label().eq_ignore_ascii_case(label))
+            .ok_or_else(|| anyhow!("unknown working mode: {}", label))
+    }
+
+    pub fn bit(&self) -> Option<RegisterBit> {
+        match self {
+            Self::SelfUse => None,
+            Self::AcCharge => Some(RegisterBit::AcChargeEnable),
+            Self::ChargePriority => Some(RegisterBit::ChargePriorityEnable),
+            Self::ForcedDischarge => Some(RegisterBit::ForcedDischargeEnable),
+        }
+    }
+
+    // all the bits any mode might set, to clear before setting a new one
+    pub fn mask() -> u16 {
+        Self::ALL
+            .iter()
+            .filter_map(|mode| mode.bit())
+            .fold(0, |mask, bit| mask | u16::from(bit))
+    }
+} // }}}
+
 #[derive(Clone, Debug, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u16)]
 pub enum RegisterBit {
@@ -732,6 +932,345 @@ impl Register110Bits {
     }
 } // }}}
 
+// the BMS flags below are published as "ON"/"OFF", like the register bits above
+fn bms_flag(data: u16, bit: u16) -> String {
+    if (data & bit) == bit {
+        "ON".to_string()
+    } else {
+        "OFF".to_string()
+    }
+}
+
+// BmsFaultBits {{{
+// bms_event_1, the protection flags the BMS sends over CAN. The layout follows
+// the Pylontech CAN protocol, which the inverter passes through.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BmsFaultBits {
+    pub cell_overvoltage: String,
+    pub cell_undervoltage: String,
+    pub overtemperature: String,
+    pub undertemperature: String,
+    pub discharge_overcurrent: String,
+    pub charge_overcurrent: String,
+    pub system_error: String,
+}
+
+impl BmsFaultBits {
+    pub fn new(data: u16) -> Self {
+        Self {
+            cell_overvoltage: bms_flag(data, 1 << 1),
+            cell_undervoltage: bms_flag(data, 1 << 2),
+            overtemperature: bms_flag(data, 1 << 3),
+            undertemperature: bms_flag(data, 1 << 4),
+            discharge_overcurrent: bms_flag(data, 1 << 7),
+            charge_overcurrent: bms_flag(data, 1 << 8),
+            system_error: bms_flag(data, 1 << 11),
+        }
+    }
+} // }}}
+
+// BmsWarningBits {{{
+// bms_event_2, the alarm flags which precede the protections above
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BmsWarningBits {
+    pub high_voltage: String,
+    pub low_voltage: String,
+    pub high_temperature: String,
+    pub low_temperature: String,
+    pub discharge_high_current: String,
+    pub charge_high_current: String,
+    pub comms_error: String,
+}
+
+impl BmsWarningBits {
+    pub fn new(data: u16) -> Self {
+        Self {
+            high_voltage: bms_flag(data, 1 << 1),
+            low_voltage: bms_flag(data, 1 << 2),
+            high_temperature: bms_flag(data, 1 << 3),
+            low_temperature: bms_flag(data, 1 << 4),
+            discharge_high_current: bms_flag(data, 1 << 7),
+            charge_high_current: bms_flag(data, 1 << 8),
+            comms_error: bms_flag(data, 1 << 11),
+        }
+    }
+} // }}}
+
+// BmsRequestBits {{{
+// bat_status_5, the charge and discharge requests from the same Pylontech
+// protocol (frame 0x35C). The other bat_status words vary with battery brand.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BmsRequestBits {
+    pub charge_enable: String,
+    pub discharge_enable: String,
+    pub force_charge_1: String,
+    pub force_charge_2: String,
+    pub full_charge: String,
+}
+
+impl BmsRequestBits {
+    pub fn new(data: u16) -> Self {
+        Self {
+            charge_enable: bms_flag(data, 1 << 7),
+            discharge_enable: bms_flag(data, 1 << 6),
+            force_charge_1: bms_flag(data, 1 << 5),
+            force_charge_2: bms_flag(data, 1 << 4),
+            full_charge: bms_flag(data, 1 << 3),
+        }
+    }
+} // }}}
+
+// BmsStatus {{{
+// Everything the inverter tells us about the battery management system, decoded.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BmsStatus {
+    pub bat_count: u16,
+    pub bat_capacity: u16,
+    pub cycle_count: u16,
+    pub bat_status: [u16; 10],
+    pub bat_status_inv: u16,
+
+    pub max_cell_voltage: f64,
+    pub min_cell_voltage: f64,
+    pub cell_voltage_delta: f64,
+    pub max_cell_temp: f64,
+    pub min_cell_temp: f64,
+
+    pub cell_imbalance: String,
+    pub fault: BmsFaultBits,
+    pub warning: BmsWarningBits,
+    pub request: BmsRequestBits,
+}
+
+impl BmsStatus {
+    // spread between highest and lowest cell above which we call the pack imbalanced
+    const IMBALANCE_VOLTAGE: f64 = 0.1;
+
+    pub fn from_input_all(input: &ReadInputAll) -> Self {
+        Self::from_input_3(&ReadInput3::from(input))
+    }
+
+    pub fn from_input_3(input: &ReadInput3) -> Self {
+        Self {
+            bat_count: input.bat_count,
+            bat_capacity: input.bat_capacity,
+            cycle_count: input.cycle_count,
+            bat_status: [
+                input.bat_status_0,
+                input.bat_status_1,
+                input.bat_status_2,
+                input.bat_status_3,
+                input.bat_status_4,
+                input.bat_status_5,
+                input.bat_status_6,
+                input.bat_status_7,
+                input.bat_status_8,
+                input.bat_status_9,
+            ],
+            bat_status_inv: input.bat_status_inv,
+            max_cell_voltage: input.max_cell_voltage,
+            min_cell_voltage: input.min_cell_voltage,
+            cell_voltage_delta: 0.0,
+            max_cell_temp: input.max_cell_temp,
+            min_cell_temp: input.min_cell_temp,
+            cell_imbalance: String::new(),
+            fault: BmsFaultBits::new(input.bms_event_1),
+            warning: BmsWarningBits::new(input.bms_event_2),
+            request: BmsRequestBits::new(input.bat_status_5),
+        }
+        .with_cell_imbalance()
+    }
+
+    fn with_cell_imbalance(self) -> Self {
+        // no cell data (eg lead-acid, or no BMS comms) reads as zero
+        let cell_voltage_delta = if self.min_cell_voltage > 0.0 {
+            ((self.max_cell_voltage - self.min_cell_voltage) * 1000.0).round() / 1000.0
+        } else {
+            0.0
+        };
+
+        let cell_imbalance = if cell_voltage_delta > Self::IMBALANCE_VOLTAGE {
+            "ON"
+        } else {
+            "OFF"
+        };
+
+        Self {
+            cell_voltage_delta,
+            cell_imbalance: cell_imbalance.to_string(),
+            ..self
+        }
+    }
+} // }}}
+
+// GeneratorStatus {{{
+// The generator port readings, which come in ReadInput4 (or ReadInputAll).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GeneratorStatus {
+    pub v_gen: f64,
+    pub f_gen: f64,
+    pub p_gen: u16,
+    pub e_gen_day: f64,
+    pub e_gen_all: f64,
+    // power is coming in through the port. that's all the readings say; the
+    // port can also take AC coupled solar, and an idle generator reads 0
+    pub supplying: String,
+}
+
+impl GeneratorStatus {
+    pub fn from_input_all(input: &ReadInputAll) -> Self {
+        Self::new(
+            input.v_gen,
+            input.f_gen,
+            input.p_gen,
+            input.e_gen_day,
+            input.e_gen_all,
+        )
+    }
+
+    pub fn from_input_4(input: &ReadInput4) -> Self {
+        Self::new(
+            input.v_gen,
+            input.f_gen,
+            input.p_gen,
+            input.e_gen_day,
+            input.e_gen_all,
+        )
+    }
+
+    fn new(v_gen: f64, f_gen: f64, p_gen: u16, e_gen_day: f64, e_gen_all: f64) -> Self {
+        let supplying = if p_gen > 0 { "ON" } else { "OFF" };
+
+        Self {
+            v_gen,
+            f_gen,
+            p_gen,
+            e_gen_day,
+            e_gen_all,
+            supplying: supplying.to_string(),
+        }
+    }
+} // }}}
+
+// Model {{{
+// Holding registers 0-1, the model bitfield, as laid out in the LuxPower
+// protocol's Model fields. Only the ones we have a use for are decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Model {
+    pub battery_type: u8, // 0 bits 0-1; 0 lead-acid, 1 lithium
+    pub lithium_type: u8, // 0 bits 6-9, which BMS protocol
+    pub power_rating: u8, // 1 bits 0-4
+    pub us_version: bool, // 1 bit 8; split phase rather than single phase
+}
+
+impl Model {
+    pub fn new(registers: u32) -> Self {
+        let (low, high) = (registers & 0xffff, registers >> 16);
+
+        Self {
+            battery_type: (low & 0x3) as u8,
+            lithium_type: ((low >> 6) & 0xf) as u8,
+            power_rating: (high & 0x1f) as u8,
+            us_version: high & (1 << 8) != 0,
+        }
+    }
+} // }}}
+
+// ModelFamily {{{
+// The first letter of the firmware code says what kind of inverter it is.
+// Letters we haven't seen are Unknown, and get no family specific entities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelFamily {
+    OffGrid,
+    AcCoupled,
+    Hybrid,
+    Unknown,
+}
+
+impl ModelFamily {
+    pub fn from_fw_code(fw_code: &str) -> Self {
+        match fw_code.chars().next().map(|c| c.to_ascii_uppercase()) {
+            Some('A') => Self::OffGrid,
+            Some('B') => Self::AcCoupled,
+            Some('C' | 'E' | 'F') => Self::Hybrid,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::OffGrid => "Off-grid",
+            Self::AcCoupled => "AC Coupled",
+            Self::Hybrid => "Hybrid",
+            Self::Unknown => "Inverter",
+        }
+    }
+} // }}}
+
+// DeviceInfo {{{
+// Decoded from holding registers 0-10, which describe the hardware and firmware.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub model: Model,       // 0-1
+    pub serial: String,     // 2-6
+    pub fw_code: String,    // 7-8, eg FAAB
+    pub slave_version: u8,  // 9
+    pub master_version: u8, // 10
+}
+
+impl DeviceInfo {
+    pub const REGISTER_COUNT: u16 = 11;
+
+    pub fn from_hold(td: &TranslatedData) -> Result<Self> {
+        if td.register != 0 || td.values.len() < Self::REGISTER_COUNT as usize * 2 {
+            bail!(
+                "DeviceInfo needs holding registers 0-10, got register={} len={}",
+                td.register,
+                td.values.len()
+            );
+        }
+
+        let v = &td.values;
+
+        Ok(Self {
+            model: Model::new(Utils::u16ify(v, 0) as u32 | ((Utils::u16ify(v, 2) as u32) << 16)),
+            serial: Self::ascii(&v[4..14]),
+            fw_code: Self::ascii(&v[14..18]),
+            slave_version: v[18],
+            master_version: v[20],
+        })
+    }
+
+    pub fn family(&self) -> ModelFamily {
+        ModelFamily::from_fw_code(&self.fw_code)
+    }
+
+    // eg LuxPower Hybrid (US)
+    pub fn model_name(&self) -> String {
+        let region = if self.model.us_version { "US" } else { "EU" };
+        format!("LuxPower {} ({})", self.family().label(), region)
+    }
+
+    // formatted the same way as the LuxPower app, eg FAAB-2525
+    pub fn sw_version(&self) -> String {
+        format!(
+            "{}-{:02X}{:02X}",
+            self.fw_code, self.slave_version, self.master_version
+        )
+    }
+
+    // the power rating code; the app's kW figure for it varies by family
+    pub fn hw_version(&self) -> String {
+        format!("power rating {}", self.model.power_rating)
+    }
+
+    fn ascii(bytes: &[u8]) -> String {
+        String::from_utf8_lossy(bytes)
+            .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+            .to_owned()
+    }
+} // }}}
+
 #[enum_dispatch]
 pub trait PacketCommon {
     fn datalog(&self) -> Serial;
@@ -891,7 +1430,7 @@ impl TranslatedData {
                 r.e_pv_day = Utils::round(r.e_pv_day_1 + r.e_pv_day_2 + r.e_pv_day_3, 1);
                 r.e_pv_all = Utils::round(r.e_pv_all_1 + r.e_pv_all_2 + r.e_pv_all_3, 1);
                 r.datalog = self.datalog;
-                Ok(r)
+                Ok(derived::apply(r))
             }
             Err(_) => Err(anyhow!("meh")),
         }
@@ -1352,6 +1891,69 @@ impl StatusString {
     }
 }
 
+// unit of measurement for a key in the inputs JSON, if it has one
+pub struct UnitString;
+impl UnitString {
+    pub fn from_key(key: &str) -> Option<&'static str> {
+        match key {
+            "soc" | "soh" => Some("%"),
+            "runtime" => Some("s"),
+            "bat_capacity" => Some("Ah"),
+            "max_chg_curr" | "max_dischg_curr" | "bat_current" => Some("A"),
+            "charge_volt_ref" | "dischg_cut_volt" | "vbat_inv" => Some("V"),
+            "max_cell_voltage" | "min_cell_voltage" => Some("V"),
+            "max_cell_temp" | "min_cell_temp" => Some("°C"),
+            k if k.starts_with("v_") => Some("V"),
+            k if k.starts_with("p_") => Some("W"),
+            k if k.starts_with("s_") => Some("VA"),
+            k if k.starts_with("e_") => Some("kWh"),
+            k if k.starts_with("f_") => Some("Hz"),
+            k if k.starts_with("t_") => Some("°C"),
+            _ => None,
+        }
+    }
+}
+
+// ActiveCode {{{
+// One set bit of fault_code or warning_code, decoded
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ActiveCode {
+    pub bit: u8,
+    pub code: &'static str,        // eg E001
+    pub description: &'static str, // eg Model fault
+    pub severity: &'static str,    // critical, warning or info
+}
+
+impl ActiveCode {
+    fn new(bit: u8, text: &'static str, severity: &'static str) -> Self {
+        let (code, description) = text.split_once(": ").unwrap_or((text, ""));
+
+        Self {
+            bit,
+            code,
+            description,
+            severity,
+        }
+    }
+
+    fn all_set(
+        value: u32,
+        from_bit: fn(usize) -> &'static str,
+        severity: fn(usize) -> &'static str,
+    ) -> Vec<Self> {
+        (0..=31)
+            .filter(|i| value & (1 << i) > 0)
+            .map(|i| Self::new(i as u8, from_bit(i), severity(i)))
+            .collect()
+    }
+
+    // just the codes, eg ["E001","E003"], as stored in databases
+    pub fn codes_json(codes: &[Self]) -> String {
+        let codes: Vec<&str> = codes.iter().map(|c| c.code).collect();
+        serde_json::to_string(&codes).unwrap()
+    }
+} // }}}
+
 pub struct WarningCodeString;
 impl WarningCodeString {
     pub fn from_value(value: u32) -> &'static str {
@@ -1365,6 +1967,21 @@ impl WarningCodeString {
             .unwrap()
     }
 
+    // every warning set in value, rather than just the first
+    pub fn active(value: u32) -> Vec<ActiveCode> {
+        ActiveCode::all_set(value, Self::from_bit, Self::severity)
+    }
+
+    // critical where there's a risk to safety or the hardware, info for
+    // reserved bits and the grid being out of spec, which isn't ours to fix
+    fn severity(bit: usize) -> &'static str {
+        match bit {
+            2 | 13 | 15 | 20 | 21 | 23 | 25 | 27 | 28 => "critical",
+            6 | 7 | 10 | 14 | 17 | 18 | 19 | 24 => "info",
+            _ => "warning",
+        }
+    }
+
     fn from_bit(bit: usize) -> &'static str {
         match bit {
             0 => "W000: Battery communication failure",
@@ -1418,6 +2035,20 @@ impl FaultCodeString {
             .unwrap()
     }
 
+    // every fault set in value, rather than just the first
+    pub fn active(value: u32) -> Vec<ActiveCode> {
+        ActiveCode::all_set(value, Self::from_bit, Self::severity)
+    }
+
+    // any fault stops the inverter. reserved bits aren't documented, so we
+    // can't say that much about them
+    fn severity(bit: usize) -> &'static str {
+        match bit {
+            4..=7 | 28..=30 => "warning",
+            _ => "critical",
+        }
+    }
+
     fn from_bit(bit: usize) -> &'static str {
         match bit {
             0 => "E000: Internal communication fault 1",
@@ -1,6 +1,10 @@
 use crate::prelude::*;
 
-use rumqttc::{AsyncClient, Event, EventLoop, Incoming, LastWill, MqttOptions, Publish, QoS};
+use rumqttc::{
+    tokio_rustls::rustls::{client::WantsClientCert, ClientConfig, ConfigBuilder, RootCertStore},
+    v5::mqttbytes::v5::PublishProperties,
+    LastWill, MqttOptions, QoS, TlsConfiguration, Transport,
+};
 
 // Message {{{
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -10,6 +14,41 @@ pub struct Message {
     pub payload: String,
 }
 
+// which part of the topic hierarchy a message belongs to, so qos/retain/template
+// can be configured per class
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopicClass {
+    Inputs,
+    Input,
+    Hold,
+    Param,
+    Result,
+    Availability,
+    TimeRegister,
+    Other,
+}
+
+impl TopicClass {
+    pub fn from_topic(topic: &str) -> Self {
+        let mut parts = topic.split('/');
+
+        match (parts.next(), parts.next()) {
+            (Some("result"), _) => Self::Result,
+            (_, Some("inputs")) | (_, Some("bms")) | (_, Some("generator")) => Self::Inputs,
+            (_, Some("faults")) | (_, Some("warnings")) => Self::Inputs,
+            (_, Some("input")) => Self::Input,
+            (_, Some("hold")) => Self::Hold,
+            (_, Some("param")) => Self::Param,
+            (_, Some("availability")) => Self::Availability,
+            (_, Some("ac_charge" | "ac_first" | "charge_priority" | "forced_discharge")) => {
+                Self::TimeRegister
+            }
+            // time_drift, battery, hold_writes, alerts, schedule/* and so on
+            _ => Self::Other,
+        }
+    }
+}
+
 pub enum TargetInverter {
     Serial(Serial),
     All,
@@ -62,6 +101,48 @@ impl Message {
         Ok(r)
     }
 
+    // whether we currently have a TCP session to this inverter
+    pub fn for_availability(datalog: Serial, online: bool) -> Message {
+        mqtt::Message {
+            topic: format!("{}/availability", datalog),
+            retain: true,
+            payload: if online { "online" } else { "offline" }.to_owned(),
+        }
+    }
+
+    // seconds the inverter's clock was out by at the last timesync; positive is ahead
+    pub fn for_time_drift(datalog: Serial, drift: chrono::Duration) -> Message {
+        mqtt::Message {
+            topic: format!("{}/time_drift", datalog),
+            retain: false,
+            payload: drift.num_seconds().to_string(),
+        }
+    }
+
+    pub fn for_hold_writes(datalog: Serial, counts: &hold_writes::Counts) -> Result<Message> {
+        Ok(mqtt::Message {
+            topic: format!("{}/hold_writes", datalog),
+            retain: false,
+            payload: serde_json::to_string(counts)?,
+        })
+    }
+
+    pub fn for_battery(stats: &battery::Stats) -> Result<Message> {
+        Ok(mqtt::Message {
+            topic: format!("{}/battery", stats.datalog),
+            retain: true,
+            payload: serde_json::to_string(stats)?,
+        })
+    }
+
+    pub fn for_energy(datalog: Serial, totals: &energy::Totals) -> Result<Message> {
+        Ok(mqtt::Message {
+            topic: format!("{}/inputs/energy", datalog),
+            retain: false,
+            payload: serde_json::to_string(totals)?,
+        })
+    }
+
     pub fn for_input_all(
         inputs: &lxp::packet::ReadInputAll,
         datalog: lxp::inverter::Serial,
@@ -170,6 +251,97 @@ impl Message {
         Ok(r)
     }
 
+    // every active fault and warning as JSON arrays, from the inputs packets which carry them
+    pub fn for_codes(td: &lxp::packet::TranslatedData) -> Result<Vec<Message>> {
+        use lxp::packet::{FaultCodeString, ReadInput, WarningCodeString};
+
+        let (fault_code, warning_code) = match td.read_input() {
+            Ok(ReadInput::ReadInputAll(r_all)) => (r_all.fault_code, r_all.warning_code),
+            Ok(ReadInput::ReadInput2(r2)) => (r2.fault_code, r2.warning_code),
+            _ => return Ok(Vec::new()),
+        };
+
+        Ok(vec![
+            mqtt::Message {
+                topic: format!("{}/faults", td.datalog),
+                retain: false,
+                payload: serde_json::to_string(&FaultCodeString::active(fault_code))?,
+            },
+            mqtt::Message {
+                topic: format!("{}/warnings", td.datalog),
+                retain: false,
+                payload: serde_json::to_string(&WarningCodeString::active(warning_code))?,
+            },
+        ])
+    }
+
+    // decoded BMS status, from the inputs packets which carry it
+    pub fn for_bms(td: &lxp::packet::TranslatedData) -> Result<Option<Message>> {
+        use lxp::packet::{BmsStatus, ReadInput};
+
+        let status = match td.read_input() {
+            Ok(ReadInput::ReadInputAll(r_all)) => BmsStatus::from_input_all(&r_all),
+            Ok(ReadInput::ReadInput3(r3)) => BmsStatus::from_input_3(&r3),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(mqtt::Message {
+            topic: format!("{}/bms", td.datalog),
+            retain: false,
+            payload: serde_json::to_string(&status)?,
+        }))
+    }
+
+    // decoded generator port readings, from the inputs packets which carry them
+    pub fn for_generator(td: &lxp::packet::TranslatedData) -> Result<Option<Message>> {
+        use lxp::packet::{GeneratorStatus, ReadInput};
+
+        let status = match td.read_input() {
+            Ok(ReadInput::ReadInputAll(r_all)) => GeneratorStatus::from_input_all(&r_all),
+            Ok(ReadInput::ReadInput4(r4)) => GeneratorStatus::from_input_4(&r4),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(mqtt::Message {
+            topic: format!("{}/generator", td.datalog),
+            retain: false,
+            payload: serde_json::to_string(&status)?,
+        }))
+    }
+
+    // one message per decoded input, eg <datalog>/inputs/soc => 55, already scaled
+    pub fn for_input_keys(td: lxp::packet::TranslatedData) -> Result<Vec<Message>> {
+        use lxp::packet::ReadInput;
+
+        let value = match td.read_input() {
+            Ok(ReadInput::ReadInputAll(r_all)) => serde_json::to_value(&r_all)?,
+            Ok(ReadInput::ReadInput1(r1)) => serde_json::to_value(&r1)?,
+            Ok(ReadInput::ReadInput2(r2)) => serde_json::to_value(&r2)?,
+            Ok(ReadInput::ReadInput3(r3)) => serde_json::to_value(&r3)?,
+            Ok(ReadInput::ReadInput4(r4)) => serde_json::to_value(&r4)?,
+            Err(_) => return Ok(Vec::new()), // already warned about in for_input
+        };
+
+        let mut r = Vec::new();
+
+        if let serde_json::Value::Object(map) = value {
+            for (key, value) in map {
+                // these are only in the struct for influx's benefit
+                if key == "time" || key == "datalog" {
+                    continue;
+                }
+
+                r.push(mqtt::Message {
+                    topic: format!("{}/inputs/{}", td.datalog, key),
+                    retain: false,
+                    payload: value.to_string(),
+                });
+            }
+        }
+
+        Ok(r)
+    }
+
     pub fn to_command(&self, inverter: config::Inverter) -> Result<Command> {
         use Command::*;
 
@@ -220,6 +392,14 @@ impl Message {
                 DischargeCutoffSocLimit(inverter, self.payload_int()?)
             }
 
+            ["set", "working_mode"] => WorkingMode(
+                inverter,
+                lxp::packet::WorkingMode::from_label(&self.payload)?,
+            ),
+            ["set", "timesync"] => TimeSync(inverter),
+            ["read", "holdings"] => ReadHoldings(inverter),
+            ["reconnect"] => Reconnect(inverter),
+
             [..] => bail!("unhandled: {:?}", self),
         };
 
@@ -250,6 +430,89 @@ impl Message {
         }
     }
 
+    pub fn class(&self) -> TopicClass {
+        TopicClass::from_topic(&self.topic)
+    }
+
+    // turn an internal topic such as 2222222222/inputs/all into the full topic to publish on.
+    //
+    // without a template for its class this is just namespace/topic. templates can use
+    // {namespace}, {site}, {datalog}, {topic} (the whole internal topic) and {rest}
+    // (whatever follows the class, eg "all" or "0/parsed").
+    pub fn publish_topic(topic: &str, mqtt_config: &config::Mqtt) -> String {
+        let class = TopicClass::from_topic(topic);
+
+        let template = match mqtt_config.topics().for_class(class).template() {
+            Some(template) => template,
+            None => return format!("{}/{}", mqtt_config.namespace(), topic),
+        };
+
+        let parts: Vec<&str> = topic.split('/').collect();
+        let datalog = match class {
+            TopicClass::Result => parts.get(1),
+            _ => parts.first(),
+        };
+        // these have no class segment of their own to drop, so keep everything
+        // after the datalog, eg ac_charge/1 or battery
+        let rest = match class {
+            TopicClass::TimeRegister | TopicClass::Other => parts.get(1..),
+            _ => parts.get(2..),
+        }
+        .unwrap_or_default()
+        .join("/");
+
+        template
+            .replace("{namespace}", mqtt_config.namespace())
+            .replace("{site}", mqtt_config.site())
+            .replace("{datalog}", datalog.unwrap_or(&""))
+            .replace("{topic}", topic)
+            .replace("{rest}", &rest)
+    }
+
+    // the datalog this message relates to, if any.
+    //
+    // eg 2222222222/inputs/all or result/2222222222/set/ac_charge => 2222222222
+    pub fn datalog(&self) -> Option<Serial> {
+        self.topic
+            .split('/')
+            .take(2)
+            .find_map(|part| Serial::from_str(part).ok())
+    }
+
+    // input register readings, as opposed to config or command results
+    pub fn is_telemetry(&self) -> bool {
+        matches!(self.class(), TopicClass::Inputs | TopicClass::Input)
+    }
+
+    // for inputs/* messages, a JSON object mapping each key in the payload to its unit,
+    // or just the unit for a single named input such as inputs/v_bat
+    pub fn units(&self) -> Option<String> {
+        if self.class() != TopicClass::Inputs {
+            return None;
+        }
+
+        if let Some(unit) = self
+            .topic
+            .split('/')
+            .nth(2)
+            .and_then(lxp::packet::UnitString::from_key)
+        {
+            return Some(unit.to_owned());
+        }
+
+        let payload: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&self.payload).ok()?;
+
+        let units: serde_json::Map<String, serde_json::Value> = payload
+            .keys()
+            .filter_map(|key| {
+                lxp::packet::UnitString::from_key(key).map(|unit| (key.to_owned(), unit.into()))
+            })
+            .collect();
+
+        serde_json::to_string(&units).ok()
+    }
+
     // not entirely happy with this return type but it avoids needing to expose a struct for now
     fn payload_start_end_time(&self) -> Result<[u8; 4]> {
         use serde::Deserialize;
@@ -293,9 +556,21 @@ impl Message {
     }
 } // }}}
 
+// where to send the reply to a command, when the request asked for one (MQTT v5 only)
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct ResponseTarget {
+    pub topic: String,
+    pub correlation_data: Option<Vec<u8>>,
+}
+
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum ChannelData {
     Message(Message),
+    Internal(Message), // scheduler/optimiser->coordinator only, commands we send ourselves
+    Request(Message, ResponseTarget), // mqtt->coordinator only
+    Response(Message, ResponseTarget), // coordinator->mqtt only
+    HomeAssistant(Message), // coordinator->mqtt only, topic is published as-is
+    HomeAssistantOnline, // mqtt->coordinator only
     Shutdown,
 }
 
@@ -324,7 +599,52 @@ impl Mqtt {
             return Ok(());
         }
 
-        let mut options = MqttOptions::new("lxp-bridge", c.mqtt().host(), c.mqtt().port());
+        // websockets want a URL in place of the hostname
+        let host = if c.mqtt().websockets() {
+            let scheme = if c.mqtt().tls().enabled() {
+                "wss"
+            } else {
+                "ws"
+            };
+            format!(
+                "{}://{}:{}{}",
+                scheme,
+                c.mqtt().host(),
+                c.mqtt().port(),
+                c.mqtt().websocket_path()
+            )
+        } else {
+            c.mqtt().host().to_owned()
+        };
+
+        info!(
+            "initializing mqtt{} at {}:{} as {}",
+            if c.mqtt().v5() { " (v5)" } else { "" },
+            c.mqtt().host(),
+            c.mqtt().port(),
+            c.mqtt().client_id()
+        );
+
+        let (client, eventloop) = if c.mqtt().v5() {
+            self.v5_client(host)?
+        } else {
+            self.v4_client(host)?
+        };
+
+        futures::try_join!(
+            self.setup(client.clone()),
+            self.receiver(eventloop),
+            self.sender(client)
+        )?;
+
+        Ok(())
+    }
+
+    fn v4_client(&self, host: String) -> Result<(Client, EventLoop)> {
+        let c = &self.config;
+
+        let mut options = MqttOptions::new(c.mqtt().client_id(), host, c.mqtt().port());
+        options.set_transport(self.transport()?);
 
         let will = LastWill {
             topic: self.lwt_topic(),
@@ -339,21 +659,36 @@ impl Mqtt {
             options.set_credentials(u, p);
         }
 
-        info!(
-            "initializing mqtt at {}:{}",
-            c.mqtt().host(),
-            c.mqtt().port()
+        let (client, eventloop) = rumqttc::AsyncClient::new(options, 10);
+
+        Ok((Client::V4(client), EventLoop::V4(Box::new(eventloop))))
+    }
+
+    fn v5_client(&self, host: String) -> Result<(Client, EventLoop)> {
+        use rumqttc::v5::{self, mqttbytes};
+
+        let c = &self.config;
+
+        let mut options = v5::MqttOptions::new(c.mqtt().client_id(), host, c.mqtt().port());
+        options.set_transport(self.transport()?);
+
+        let will = mqttbytes::v5::LastWill::new(
+            self.lwt_topic(),
+            "offline",
+            mqttbytes::QoS::AtLeastOnce,
+            true,
+            None,
         );
+        options.set_last_will(will);
 
-        let (client, eventloop) = AsyncClient::new(options, 10);
+        options.set_keep_alive(std::time::Duration::from_secs(60));
+        if let (Some(u), Some(p)) = (c.mqtt().username(), c.mqtt().password()) {
+            options.set_credentials(u, p);
+        }
 
-        futures::try_join!(
-            self.setup(client.clone()),
-            self.receiver(eventloop),
-            self.sender(client)
-        )?;
+        let (client, eventloop) = v5::AsyncClient::new(options, 10);
 
-        Ok(())
+        Ok((Client::V5(client), EventLoop::V5(Box::new(eventloop))))
     }
 
     pub fn stop(&mut self) {
@@ -362,15 +697,17 @@ impl Mqtt {
         let _ = self.channels.from_mqtt.send(ChannelData::Shutdown);
     }
 
-    async fn setup(&self, client: AsyncClient) -> Result<()> {
+    async fn setup(&self, client: Client) -> Result<()> {
+        let command_qos = self.config.mqtt().topics().commands().qos().unwrap_or(0);
+
         client
-            .publish(self.lwt_topic(), QoS::AtLeastOnce, true, "online")
+            .publish(&self.lwt_topic(), 1, true, "online".to_owned(), None)
             .await?;
 
         client
             .subscribe(
                 format!("{}/cmd/all/#", self.config.mqtt().namespace()),
-                QoS::AtMostOnce,
+                command_qos,
             )
             .await?;
 
@@ -382,7 +719,7 @@ impl Mqtt {
                         self.config.mqtt().namespace(),
                         inverter.datalog()
                     ),
-                    QoS::AtMostOnce,
+                    command_qos,
                 )
                 .await?;
 
@@ -390,12 +727,39 @@ impl Mqtt {
                 let ha = home_assistant::Config::new(&inverter, &self.config.mqtt());
                 for msg in ha.all()?.into_iter() {
                     let _ = client
-                        .publish(&msg.topic, QoS::AtLeastOnce, msg.retain, msg.payload)
+                        .publish(&msg.topic, 1, msg.retain, msg.payload, None)
                         .await;
                 }
             }
         }
 
+        // commands to a system are fanned out to its inverters by the coordinator
+        for system in self.config.enabled_systems() {
+            client
+                .subscribe(
+                    format!(
+                        "{}/cmd/{}/#",
+                        self.config.mqtt().namespace(),
+                        system.datalog()
+                    ),
+                    command_qos,
+                )
+                .await?;
+        }
+
+        if self.config.mqtt().homeassistant().enabled() {
+            let prefix = self.config.mqtt().homeassistant().prefix().to_owned();
+
+            // birth message, so we know when to send discovery again
+            client.subscribe(format!("{}/status", prefix), 0).await?;
+
+            // retained discovery configs, so we can remove any left behind by inverters
+            // which are no longer in our config
+            client
+                .subscribe(format!("{}/+/+/+/config", prefix), 0)
+                .await?;
+        }
+
         Ok(())
     }
 
@@ -410,16 +774,16 @@ impl Mqtt {
                 tokio::time::timeout(std::time::Duration::from_secs(1), eventloop.poll()).await
             {
                 match event {
-                    Ok(Event::Incoming(Incoming::Publish(publish))) => {
+                    Ok(Some(publish)) => {
                         self.handle_message(publish)?;
                     }
+                    Ok(None) => {} // keepalives etc
                     Err(e) => {
                         // should automatically reconnect on next poll()..
                         error!("{}", e);
                         info!("reconnecting in 5s");
                         tokio::time::sleep(std::time::Duration::from_secs(5)).await;
                     }
-                    _ => {} // keepalives etc
                 }
             }
         }
@@ -429,7 +793,12 @@ impl Mqtt {
         Ok(())
     }
 
-    fn handle_message(&self, publish: Publish) -> Result<()> {
+    fn handle_message(&self, publish: IncomingPublish) -> Result<()> {
+        let ha_prefix = format!("{}/", self.config.mqtt().homeassistant().prefix());
+        if self.config.mqtt().homeassistant().enabled() && publish.topic.starts_with(&ha_prefix) {
+            return self.handle_home_assistant_message(publish);
+        }
+
         // remove the namespace, including the first /
         // doing it this way means we don't break if namespace happens to contain a /
         let topic = publish.topic[self.config.mqtt().namespace().len() + 1..].to_owned();
@@ -437,23 +806,105 @@ impl Mqtt {
         let message = Message {
             topic,
             retain: publish.retain,
-            payload: String::from_utf8(publish.payload.to_vec())?,
+            payload: String::from_utf8(publish.payload)?,
         };
         debug!("RX: {:?}", message);
+
+        let channel_data = match publish.response {
+            Some(target) => ChannelData::Request(message, target),
+            None => ChannelData::Message(message),
+        };
+        if self.channels.from_mqtt.send(channel_data).is_err() {
+            bail!("send(from_mqtt) failed - channel closed?");
+        }
+
+        Ok(())
+    }
+
+    fn handle_home_assistant_message(&self, publish: IncomingPublish) -> Result<()> {
+        let prefix = self.config.mqtt().homeassistant().prefix().to_owned();
+
+        if publish.topic == format!("{}/status", prefix) {
+            if publish.payload == b"online" {
+                info!("Home Assistant is online, sending discovery");
+                if self
+                    .channels
+                    .from_mqtt
+                    .send(ChannelData::HomeAssistantOnline)
+                    .is_err()
+                {
+                    bail!("send(from_mqtt) failed - channel closed?");
+                }
+            }
+
+            return Ok(());
+        }
+
+        // an empty payload is a config that has already been removed
+        if publish.payload.is_empty() {
+            return Ok(());
+        }
+
+        // <prefix>/<kind>/lxp_<datalog>/<name>/config
+        let datalog = publish.topic[prefix.len() + 1..]
+            .split('/')
+            .nth(1)
+            .and_then(|node_id| node_id.strip_prefix("lxp_"))
+            .and_then(|datalog| Serial::from_str(datalog).ok());
+        let datalog = match datalog {
+            Some(datalog) => datalog,
+            None => return Ok(()), // not one of ours
+        };
+
+        if self.config.enabled_inverter_with_datalog(datalog).is_some()
+            || self.config.enabled_system_with_datalog(datalog).is_some()
+        {
+            return Ok(());
+        }
+
+        // several bridges could share a broker, so only remove entities that were
+        // published with our namespace
+        let config: serde_json::Value = match serde_json::from_slice(&publish.payload) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!(
+                    "ignoring malformed Home Assistant config on {}: {}",
+                    publish.topic, err
+                );
+                return Ok(());
+            }
+        };
+        let namespace = format!("{}/", self.config.mqtt().namespace());
+        let availability = &config["availability"];
+        // older configs have a single availability rather than a list
+        match availability[0]["topic"]
+            .as_str()
+            .or(availability["topic"].as_str())
+        {
+            Some(topic) if topic.starts_with(&namespace) => {}
+            _ => return Ok(()),
+        }
+
+        info!("removing Home Assistant discovery for {}", publish.topic);
+        let message = Message {
+            topic: publish.topic,
+            retain: true,
+            payload: String::new(),
+        };
         if self
             .channels
-            .from_mqtt
-            .send(ChannelData::Message(message))
+            .to_mqtt
+            .send(ChannelData::HomeAssistant(message))
             .is_err()
         {
-            bail!("send(from_mqtt) failed - channel closed?");
+            bail!("send(to_mqtt) failed - channel closed?");
         }
 
         Ok(())
     }
 
     // coordinator -> mqtt
-    async fn sender(&self, client: AsyncClient) -> Result<()> {
+    async fn sender(&self, client: Client) -> Result<()> {
         use ChannelData::*;
 
         let mut receiver = self.channels.to_mqtt.subscribe();
@@ -461,14 +912,53 @@ impl Mqtt {
         loop {
             match receiver.recv().await? {
                 Shutdown => break,
-                Message(message) => {
-                    let topic = format!("{}/{}", self.config.mqtt().namespace(), message.topic);
+                Message(mut message) => {
+                    let (topic, qos) = {
+                        let mqtt_config = self.config.mqtt();
+                        let topic_config = mqtt_config.topics().for_class(message.class());
+                        if let Some(retain) = topic_config.retain() {
+                            message.retain = retain;
+                        }
+                        (
+                            mqtt::Message::publish_topic(&message.topic, &mqtt_config),
+                            topic_config.qos().unwrap_or(1),
+                        )
+                    };
+                    let properties = self.publish_properties(&message, None);
                     info!("publishing: {} = {}", topic, message.payload);
                     let _ = client
-                        .publish(&topic, QoS::AtLeastOnce, message.retain, message.payload)
+                        .publish(&topic, qos, message.retain, message.payload, properties)
                         .await
                         .map_err(|err| error!("publish {} failed: {:?} .. skipping", topic, err));
                 }
+                Response(message, target) => {
+                    // response topics are chosen by the requester, so no namespace here
+                    let qos = self
+                        .config
+                        .mqtt()
+                        .topics()
+                        .for_class(message.class())
+                        .qos()
+                        .unwrap_or(1);
+                    let properties = self.publish_properties(&message, Some(&target));
+                    info!("publishing: {} = {}", target.topic, message.payload);
+                    let _ = client
+                        .publish(&target.topic, qos, false, message.payload, properties)
+                        .await
+                        .map_err(|err| {
+                            error!("publish {} failed: {:?} .. skipping", target.topic, err)
+                        });
+                }
+                HomeAssistant(message) => {
+                    debug!("publishing: {} = {}", message.topic, message.payload);
+                    let _ = client
+                        .publish(&message.topic, 1, message.retain, message.payload, None)
+                        .await
+                        .map_err(|err| {
+                            error!("publish {} failed: {:?} .. skipping", message.topic, err)
+                        });
+                }
+                Internal(_) | Request(_, _) | HomeAssistantOnline => {} // never sent to this channel
             }
         }
 
@@ -477,7 +967,313 @@ impl Mqtt {
         Ok(())
     }
 
+    // extra metadata to attach to outgoing messages when speaking v5
+    fn publish_properties(
+        &self,
+        message: &Message,
+        response: Option<&ResponseTarget>,
+    ) -> Option<PublishProperties> {
+        if !self.config.mqtt().v5() {
+            return None;
+        }
+
+        let mut properties = PublishProperties::default();
+
+        // stale telemetry is no use to anyone, don't let the broker queue it up forever
+        if !message.retain && message.is_telemetry() {
+            properties.message_expiry_interval = Some(self.config.mqtt().message_expiry_interval());
+        }
+
+        if let Some(datalog) = message.datalog() {
+            properties
+                .user_properties
+                .push(("datalog".to_owned(), datalog.to_string()));
+
+            if let Some(inverter) = self.config.enabled_inverter_with_datalog(datalog) {
+                properties
+                    .user_properties
+                    .push(("serial".to_owned(), inverter.serial().to_string()));
+            }
+        }
+
+        if let Some(units) = message.units() {
+            properties.user_properties.push(("units".to_owned(), units));
+        }
+
+        if let Some(target) = response {
+            properties.correlation_data = target.correlation_data.clone().map(bytes::Bytes::from);
+        }
+
+        Some(properties)
+    }
+
+    fn transport(&self) -> Result<Transport> {
+        let mqtt = self.config.mqtt();
+        let tls = mqtt.tls();
+
+        if !tls.enabled() {
+            return Ok(if mqtt.websockets() {
+                Transport::Ws
+            } else {
+                Transport::Tcp
+            });
+        }
+
+        let client_auth = match (tls.client_cert(), tls.client_key()) {
+            (Some(cert), Some(key)) => Some((Self::read_file(cert)?, Self::read_file(key)?)),
+            (None, None) => None,
+            _ => bail!("mqtt.tls needs both client_cert and client_key, or neither"),
+        };
+
+        let tls_config = if tls.insecure_skip_verify() {
+            warn!("mqtt.tls.insecure_skip_verify is set, broker certificate will not be checked!");
+            TlsConfiguration::Rustls(std::sync::Arc::new(insecure::client_config(client_auth)?))
+        } else if let Some(ca) = tls.ca() {
+            TlsConfiguration::Simple {
+                ca: Self::read_file(ca)?,
+                alpn: None,
+                client_auth,
+            }
+        } else if let Some(client_auth) = client_auth {
+            // TlsConfiguration::default() can't take a client certificate, so
+            // build the same thing, system root certificates and all, ourselves
+            let mut roots = RootCertStore::empty();
+            for cert in rustls_native_certs::load_native_certs()? {
+                roots.add(cert)?;
+            }
+            let builder = ClientConfig::builder().with_root_certificates(roots);
+            TlsConfiguration::Rustls(std::sync::Arc::new(with_client_auth(
+                builder,
+                Some(client_auth),
+            )?))
+        } else {
+            // system root certificates
+            TlsConfiguration::default()
+        };
+
+        Ok(if mqtt.websockets() {
+            Transport::Wss(tls_config)
+        } else {
+            Transport::Tls(tls_config)
+        })
+    }
+
+    fn read_file(path: &str) -> Result<Vec<u8>> {
+        std::fs::read(path).map_err(|err| anyhow!("error reading {}: {}", path, err))
+    }
+
     fn lwt_topic(&self) -> String {
         format!("{}/LWT", self.config.mqtt().namespace())
     }
 }
+
+// Client {{{
+// thin wrappers so the rest of this file doesn't care which protocol version we're using
+#[derive(Clone)]
+enum Client {
+    V4(rumqttc::AsyncClient),
+    V5(rumqttc::v5::AsyncClient),
+}
+
+impl Client {
+    async fn subscribe(&self, topic: String, qos: u8) -> Result<()> {
+        match self {
+            Self::V4(client) => client.subscribe(topic, Self::qos_v4(qos)).await?,
+            Self::V5(client) => client.subscribe(topic, Self::qos_v5(qos)).await?,
+        }
+
+        Ok(())
+    }
+
+    // properties are silently dropped for v3.1.1
+    async fn publish(
+        &self,
+        topic: &str,
+        qos: u8,
+        retain: bool,
+        payload: String,
+        properties: Option<PublishProperties>,
+    ) -> Result<()> {
+        match (self, properties) {
+            (Self::V4(client), _) => {
+                client
+                    .publish(topic, Self::qos_v4(qos), retain, payload)
+                    .await?
+            }
+            (Self::V5(client), Some(properties)) => {
+                client
+                    .publish_with_properties(topic, Self::qos_v5(qos), retain, payload, properties)
+                    .await?
+            }
+            (Self::V5(client), None) => {
+                client
+                    .publish(topic, Self::qos_v5(qos), retain, payload)
+                    .await?
+            }
+        }
+
+        Ok(())
+    }
+
+    // config only allows 0-2
+    fn qos_v4(qos: u8) -> QoS {
+        match qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        }
+    }
+
+    fn qos_v5(qos: u8) -> rumqttc::v5::mqttbytes::QoS {
+        use rumqttc::v5::mqttbytes::QoS;
+
+        match qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        }
+    }
+}
+
+struct IncomingPublish {
+    topic: String,
+    retain: bool,
+    payload: Vec<u8>,
+    response: Option<ResponseTarget>,
+}
+
+// both are big enough that clippy wants them boxed
+enum EventLoop {
+    V4(Box<rumqttc::EventLoop>),
+    V5(Box<rumqttc::v5::EventLoop>),
+}
+
+impl EventLoop {
+    // returns incoming publishes; everything else (acks, pings etc) is None
+    async fn poll(&mut self) -> Result<Option<IncomingPublish>> {
+        let r = match self {
+            Self::V4(eventloop) => match eventloop.poll().await? {
+                rumqttc::Event::Incoming(rumqttc::Incoming::Publish(publish)) => {
+                    Some(IncomingPublish {
+                        topic: publish.topic,
+                        retain: publish.retain,
+                        payload: publish.payload.to_vec(),
+                        response: None,
+                    })
+                }
+                _ => None,
+            },
+            Self::V5(eventloop) => match eventloop.poll().await? {
+                rumqttc::v5::Event::Incoming(rumqttc::v5::Incoming::Publish(publish)) => {
+                    let response = publish.properties.and_then(|p| {
+                        let correlation_data = p.correlation_data.map(|c| c.to_vec());
+                        p.response_topic.map(|topic| ResponseTarget {
+                            topic,
+                            correlation_data,
+                        })
+                    });
+
+                    Some(IncomingPublish {
+                        topic: String::from_utf8(publish.topic.to_vec())?,
+                        retain: publish.retain,
+                        payload: publish.payload.to_vec(),
+                        response,
+                    })
+                }
+                _ => None,
+            },
+        };
+
+        Ok(r)
+    }
+} // }}}
+
+// adds our client certificate, if we have one, to a rustls config
+fn with_client_auth(
+    builder: ConfigBuilder<ClientConfig, WantsClientCert>,
+    client_auth: Option<(Vec<u8>, Vec<u8>)>,
+) -> Result<ClientConfig> {
+    let config = match client_auth {
+        Some((cert, key)) => {
+            let certs =
+                rustls_pemfile::certs(&mut cert.as_slice()).collect::<Result<Vec<_>, _>>()?;
+            let key = rustls_pemfile::private_key(&mut key.as_slice())?
+                .ok_or_else(|| anyhow!("no private key found in mqtt.tls.client_key"))?;
+            builder.with_client_auth_cert(certs, key)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(config)
+}
+
+// insecure {{{
+// Only intended for testing against brokers with self-signed certificates;
+// accepts whatever certificate the broker presents.
+mod insecure {
+    use crate::prelude::*;
+
+    use rumqttc::tokio_rustls::rustls::{
+        self,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        pki_types::{CertificateDer, ServerName},
+        ClientConfig, DigitallySignedStruct, SignatureScheme,
+    };
+
+    #[derive(Debug)]
+    struct NoVerifier;
+
+    impl ServerCertVerifier for NoVerifier {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: rustls::pki_types::UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            use SignatureScheme::*;
+            vec![
+                RSA_PKCS1_SHA256,
+                RSA_PKCS1_SHA384,
+                RSA_PKCS1_SHA512,
+                ECDSA_NISTP256_SHA256,
+                ECDSA_NISTP384_SHA384,
+                RSA_PSS_SHA256,
+                RSA_PSS_SHA384,
+                RSA_PSS_SHA512,
+                ED25519,
+            ]
+        }
+    }
+
+    pub fn client_config(client_auth: Option<(Vec<u8>, Vec<u8>)>) -> Result<ClientConfig> {
+        let builder = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(std::sync::Arc::new(NoVerifier));
+
+        super::with_client_auth(builder, client_auth)
+    }
+} // }}}
@@ -0,0 +1,303 @@
+use crate::prelude::*;
+
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use lxp::packet::{ReadInputAll, Register, RegisterBit};
+use output::OutputData;
+use std::collections::HashMap;
+
+// SOC has to climb this far back above reserve_soc before discharging again
+const SOC_HYSTERESIS: u16 = 2;
+
+// Adjustment {{{
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Adjustment {
+    ChargeRate(u16),    // percent
+    DischargeRate(u16), // percent
+    ForcedDischarge(bool),
+}
+
+impl Adjustment {
+    // each writes a different register, so is rate limited separately
+    fn register(&self) -> Register {
+        match self {
+            Self::ChargeRate(_) => Register::ChargePowerPercentCmd,
+            Self::DischargeRate(_) => Register::DischgPowerPercentCmd,
+            Self::ForcedDischarge(_) => Register::Register21,
+        }
+    }
+
+    // the coordinator command that makes this change, and its payload
+    fn command(&self) -> (&'static str, String) {
+        match self {
+            Self::ChargeRate(rate) => ("set/charge_rate_pct", rate.to_string()),
+            Self::DischargeRate(rate) => ("set/discharge_rate_pct", rate.to_string()),
+            Self::ForcedDischarge(enable) => (
+                "set/forced_discharge",
+                if *enable { "ON" } else { "OFF" }.to_owned(),
+            ),
+        }
+    }
+} // }}}
+
+// Controller {{{
+// Decides what to change from each set of inputs. Kept apart from any I/O so
+// it can be tested with plain packets.
+pub struct Controller {
+    config: config::Optimiser,
+    reserve_until: Option<NaiveTime>,
+    // what we last asked for or saw in the holding registers; None until
+    // the first hold read, as a guess would throw the control loop off
+    charge_rate: Option<u16>,
+    discharge_rate: Option<u16>,
+    // None until we've asked for it or seen register 21
+    forced_discharge: Option<bool>,
+    last_writes: HashMap<u16, NaiveDateTime>,
+}
+
+impl Controller {
+    pub fn new(config: config::Optimiser) -> Result<Self> {
+        let reserve_until = match config.reserve_until() {
+            Some(time) => Some(
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .map_err(|err| anyhow!("bad reserve_until {}: {}", time, err))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            config,
+            reserve_until,
+            charge_rate: None,
+            discharge_rate: None,
+            forced_discharge: None,
+            last_writes: HashMap::new(),
+        })
+    }
+
+    // at is the inverter's local time
+    pub fn update(&mut self, input: &ReadInputAll, at: NaiveDateTime) -> Vec<Adjustment> {
+        let mut wanted = Vec::new();
+
+        if let Some(limit) = self.config.export_limit() {
+            wanted.extend(self.export(input, limit));
+        }
+
+        if let Some(reserve) = self.config.reserve_soc() {
+            wanted.extend(self.reserve(input, reserve, at));
+        }
+
+        let mut unique: Vec<Adjustment> = Vec::new();
+        for adjustment in wanted {
+            if !unique.contains(&adjustment) {
+                unique.push(adjustment);
+            }
+        }
+
+        unique
+            .into_iter()
+            .filter(|adjustment| self.permitted(*adjustment, at))
+            .collect()
+    }
+
+    // keeps the charge rate and forced discharge in step with the inverter,
+    // so changes made elsewhere aren't undone or repeated
+    pub fn observe_hold(&mut self, pairs: &[(u16, u16)]) {
+        for (register, value) in pairs {
+            match *register {
+                r if r == Register::ChargePowerPercentCmd as u16 => self.charge_rate = Some(*value),
+                r if r == Register::DischgPowerPercentCmd as u16 => {
+                    self.discharge_rate = Some(*value)
+                }
+                r if r == Register::Register21 as u16 => {
+                    let bit = RegisterBit::ForcedDischargeEnable as u16;
+                    self.forced_discharge = Some(value & bit == bit);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // steer the charge rate to hold export at the limit: over it, charge
+    // harder to soak up the excess; under it, charge less so solar goes to
+    // the house and grid instead
+    fn export(&self, input: &ReadInputAll, limit: u32) -> Vec<Adjustment> {
+        let hysteresis = self.config.hysteresis() as i32;
+        // positive means exporting too much
+        let error = input.p_to_grid as i32 - input.p_to_user as i32 - limit as i32;
+
+        if error.abs() <= hysteresis {
+            return Vec::new();
+        }
+
+        let mut r = Vec::new();
+
+        // nothing to step from until we've read the register
+        if let Some(charge_rate) = self.charge_rate {
+            let step = error * 100 / self.config.battery_power() as i32;
+            let rate = (charge_rate as i32 + step).clamp(0, 100) as u16;
+            r.push(Adjustment::ChargeRate(rate));
+        }
+
+        // forced discharge is what's pushing us over
+        if error > hysteresis && input.p_discharge as i32 > hysteresis {
+            r.push(Adjustment::ForcedDischarge(false));
+        }
+
+        r
+    }
+
+    // stop discharging at reserve_soc until reserve_until
+    fn reserve(&self, input: &ReadInputAll, reserve: u16, at: NaiveDateTime) -> Vec<Adjustment> {
+        let soc = input.soc.max(0) as u16;
+        let holding = match self.reserve_until {
+            Some(until) => at.time() < until,
+            None => true,
+        };
+
+        if !holding || soc >= reserve + SOC_HYSTERESIS {
+            return vec![Adjustment::DischargeRate(100)];
+        }
+
+        if soc > reserve {
+            return Vec::new();
+        }
+
+        let mut r = vec![Adjustment::DischargeRate(0)];
+        if input.p_discharge as u32 > self.config.hysteresis() {
+            r.push(Adjustment::ForcedDischarge(false));
+        }
+
+        r
+    }
+
+    // skips writes that change nothing or come too soon after the last one,
+    // and records those that pass
+    fn permitted(&mut self, adjustment: Adjustment, at: NaiveDateTime) -> bool {
+        let min_interval = Duration::seconds(self.config.min_write_interval() as i64);
+        let register = u16::from(adjustment.register());
+
+        let unchanged = match adjustment {
+            Adjustment::ChargeRate(rate) => self.charge_rate == Some(rate),
+            Adjustment::DischargeRate(rate) => self.discharge_rate == Some(rate),
+            Adjustment::ForcedDischarge(enable) => self.forced_discharge == Some(enable),
+        };
+        if unchanged {
+            return false;
+        }
+
+        if matches!(self.last_writes.get(&register), Some(last) if at - *last < min_interval) {
+            return false;
+        }
+
+        match adjustment {
+            Adjustment::ChargeRate(rate) => self.charge_rate = Some(rate),
+            Adjustment::DischargeRate(rate) => self.discharge_rate = Some(rate),
+            Adjustment::ForcedDischarge(enable) => self.forced_discharge = Some(enable),
+        }
+        self.last_writes.insert(register, at);
+
+        true
+    }
+} // }}}
+
+// Optimiser {{{
+// Runs the controller on each set of inputs from one inverter, taking them
+// from to_outputs so it gets the same complete ReadInputAll packets the
+// outputs do. Changes are sent to the coordinator as commands, so they go
+// through the same hold write checks as any other.
+pub struct Optimiser {
+    config: ConfigWrapper,
+    channels: Channels,
+}
+
+impl Optimiser {
+    pub fn new(config: ConfigWrapper, channels: Channels) -> Self {
+        Self { config, channels }
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        let config = match &*self.config.optimiser() {
+            Some(optimiser) if optimiser.enabled() => optimiser.clone(),
+            _ => {
+                info!("optimiser disabled, skipping");
+                return Ok(());
+            }
+        };
+
+        let inverter = match self.config.enabled_inverter_with_datalog(config.datalog()) {
+            Some(inverter) => inverter,
+            None => {
+                warn!(
+                    "optimiser: no enabled inverter with datalog {}",
+                    config.datalog()
+                );
+                return Ok(());
+            }
+        };
+
+        let controller = match Controller::new(config) {
+            Ok(controller) => controller,
+            Err(err) => {
+                error!("optimiser: {}", err);
+                return Ok(());
+            }
+        };
+
+        self.receiver(inverter, controller).await?;
+
+        info!("optimiser loop exiting");
+
+        Ok(())
+    }
+
+    async fn receiver(&self, inverter: config::Inverter, mut controller: Controller) -> Result<()> {
+        use output::ChannelData::*;
+        use tokio::sync::broadcast::error::RecvError;
+
+        let mut receiver = self.channels.to_outputs.subscribe();
+
+        loop {
+            match receiver.recv().await {
+                Ok(Shutdown) | Err(RecvError::Closed) => break,
+                // the inputs will have moved on, so nothing to catch up on
+                Err(RecvError::Lagged(n)) => {
+                    warn!("optimiser: fell behind, dropped {} input(s)", n)
+                }
+                Ok(Data(OutputData::Hold(datalog, pairs))) if datalog == inverter.datalog() => {
+                    controller.observe_hold(&pairs)
+                }
+                Ok(Data(OutputData::InputAll(input))) if input.datalog == inverter.datalog() => {
+                    let at = inverter.local_time(input.time.0);
+                    for adjustment in controller.update(&input, at) {
+                        self.apply(&inverter, adjustment)?;
+                    }
+                }
+                Ok(Data(_)) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply(&self, inverter: &config::Inverter, adjustment: Adjustment) -> Result<()> {
+        info!(
+            "optimiser: inverter {}: {:?}",
+            inverter.datalog(),
+            adjustment
+        );
+
+        let (command, payload) = adjustment.command();
+        let message = mqtt::Message {
+            topic: format!("cmd/{}/{}", inverter.datalog(), command),
+            retain: false,
+            payload,
+        };
+
+        let channel_data = mqtt::ChannelData::Internal(message);
+        if self.channels.from_mqtt.send(channel_data).is_err() {
+            bail!("send(from_mqtt) failed - channel closed?");
+        }
+
+        Ok(())
+    }
+} // }}}
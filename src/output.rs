@@ -0,0 +1,210 @@
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use tokio::sync::broadcast::error::RecvError;
+
+// longest we'll wait between retries of one failed write
+const MAX_BACKOFF_SECS: u64 = 60;
+// how often a part-filled batch is written anyway
+const FLUSH_INTERVAL_SECS: u64 = 10;
+
+// OutputData {{{
+// What the coordinator hands to outputs. Each output picks what it wants with
+// Output::filter.
+#[derive(PartialEq, Clone, Debug)]
+pub enum OutputData {
+    InputAll(Box<lxp::packet::ReadInputAll>),
+    Hold(Serial, Vec<(u16, u16)>), // datalog, (register, value) pairs
+    Battery(battery::Stats),
+}
+
+impl OutputData {
+    pub fn datalog(&self) -> Serial {
+        match self {
+            Self::InputAll(input) => input.datalog,
+            Self::Hold(datalog, _) => *datalog,
+            Self::Battery(stats) => stats.datalog,
+        }
+    }
+} // }}}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum ChannelData {
+    Data(OutputData),
+    Shutdown,
+}
+
+pub type Sender = broadcast::Sender<ChannelData>;
+
+// Output {{{
+// A data sink. The runner in Outputs takes care of receiving, batching and
+// retrying, so implementations only need to say what they want and how to
+// write it.
+#[async_trait(?Send)]
+pub trait Output {
+    // used in logs
+    fn name(&self) -> String;
+
+    // one-off setup before the first write, eg connecting to a database.
+    // if this fails the output is not started.
+    async fn connect(&self) -> Result<()> {
+        Ok(())
+    }
+
+    // return None to skip data, or trim it down to what we care about
+    fn filter(&self, data: OutputData) -> Option<OutputData> {
+        Some(data)
+    }
+
+    // how many items to collect before writing. part-filled batches are
+    // written every FLUSH_INTERVAL_SECS, and on shutdown.
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    // how many times to retry a failed write before giving up on it.
+    // None retries forever.
+    fn retries(&self) -> Option<u32> {
+        None
+    }
+
+    async fn write(&self, batch: &[OutputData]) -> Result<()>;
+
+    // called when a batch is dropped after running out of retries
+    fn failed(&self, batch: &[OutputData], err: &Error) {
+        error!(
+            "{}: dropping {} item(s) after retries: {}",
+            self.name(),
+            batch.len(),
+            err
+        );
+    }
+} // }}}
+
+pub fn build(config: &ConfigWrapper) -> Vec<Box<dyn Output>> {
+    let mut r: Vec<Box<dyn Output>> = Vec::new();
+
+    if config.influx().enabled() {
+        r.push(Box::new(Influx::new(config.clone())));
+    }
+
+    for database in config.enabled_databases() {
+        r.push(Box::new(Database::new(database)));
+    }
+
+    for webhook in config.enabled_webhooks() {
+        r.push(Box::new(Webhook::new(webhook)));
+    }
+
+    if let Some(pvoutput) = config.pvoutput().clone() {
+        if pvoutput.enabled() {
+            match config.enabled_inverter_with_datalog(pvoutput.datalog()) {
+                Some(inverter) => r.push(Box::new(PvOutput::new(pvoutput, inverter))),
+                None => warn!(
+                    "pvoutput: no enabled inverter with datalog {}",
+                    pvoutput.datalog()
+                ),
+            }
+        }
+    }
+
+    r
+}
+
+pub struct Outputs {
+    outputs: Vec<Box<dyn Output>>,
+    channels: Channels,
+}
+
+impl Outputs {
+    pub fn new(outputs: Vec<Box<dyn Output>>, channels: Channels) -> Self {
+        Self { outputs, channels }
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        let futures = self.outputs.iter().map(|o| self.runner(o.as_ref()));
+
+        futures::future::join_all(futures).await;
+
+        info!("outputs loop exiting");
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        let _ = self.channels.to_outputs.send(ChannelData::Shutdown);
+    }
+
+    async fn runner(&self, output: &dyn Output) {
+        // subscribe first so nothing is missed while connecting
+        let mut receiver = self.channels.to_outputs.subscribe();
+
+        info!("initializing {}", output.name());
+
+        if let Err(err) = output.connect().await {
+            error!("{}: {:?}", output.name(), err);
+            return;
+        }
+
+        let mut batch = Vec::new();
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(FLUSH_INTERVAL_SECS));
+
+        loop {
+            tokio::select! {
+                data = receiver.recv() => match data {
+                    Ok(ChannelData::Shutdown) | Err(RecvError::Closed) => break,
+                    // a slow output drops the oldest data rather than holding up everything else
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("{}: fell behind, dropped {} item(s)", output.name(), n)
+                    }
+                    Ok(ChannelData::Data(data)) => {
+                        if let Some(data) = output.filter(data) {
+                            batch.push(data);
+                            if batch.len() >= output.batch_size() {
+                                Self::flush(output, &mut batch).await;
+                            }
+                        }
+                    }
+                },
+                _ = interval.tick() => Self::flush(output, &mut batch).await,
+            }
+        }
+
+        Self::flush(output, &mut batch).await;
+
+        info!("{} loop exiting", output.name());
+    }
+
+    async fn flush(output: &dyn Output, batch: &mut Vec<OutputData>) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let batch = std::mem::take(batch);
+        let mut backoff = 1;
+        let mut attempt = 0;
+
+        loop {
+            let err = match output.write(&batch).await {
+                Ok(()) => return,
+                Err(err) => err,
+            };
+
+            if matches!(output.retries(), Some(retries) if attempt >= retries) {
+                output.failed(&batch, &err);
+                return;
+            }
+
+            warn!(
+                "{}: write failed: {:?} - retrying in {}s",
+                output.name(),
+                err,
+                backoff
+            );
+            tokio::time::sleep(std::time::Duration::from_secs(backoff)).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+            attempt += 1;
+        }
+    }
+}
@@ -13,12 +13,14 @@ pub use {
 };
 
 pub use crate::{
+    alerts::{self, Alerts},
+    battery,
     channels::Channels,
     command::Command,
     config::{self, Config, ConfigWrapper},
     coordinator::{self, Coordinator},
     database::{self, Database},
-    home_assistant,
+    derived, energy, hold_writes, home_assistant,
     influx::{self, Influx},
     lxp::{
         self,
@@ -26,9 +28,14 @@ pub use crate::{
         packet::{Packet, PacketCommon},
     },
     mqtt::{self, Mqtt},
+    optimiser::{self, Optimiser},
     options::Options,
+    output::{self, Output, Outputs},
+    pvoutput::{self, PvOutput},
     register_cache::{self, RegisterCache},
     scheduler::Scheduler,
+    solar, system, tariff,
     unixtime::UnixTime,
     utils::Utils,
+    webhook::{self, Webhook},
 };
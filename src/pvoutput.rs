@@ -0,0 +1,291 @@
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use lxp::packet::ReadInputAll;
+use output::{Output, OutputData};
+use std::collections::VecDeque;
+
+// https://pvoutput.org/help/api_specification.html
+static ADD_STATUS_PATH: &str = "/service/r2/addstatus.jsp";
+static ADD_OUTPUT_PATH: &str = "/service/r2/addoutput.jsp";
+
+// a day of 5 minute statuses; past this the oldest unaccepted ones are dropped
+const MAX_READY: usize = 288;
+
+// Upload {{{
+#[derive(Clone, Debug, PartialEq)]
+pub enum Upload {
+    Status(Vec<(&'static str, String)>),
+    Output(Vec<(&'static str, String)>),
+}
+
+impl Upload {
+    pub fn path(&self) -> &'static str {
+        match self {
+            Self::Status(_) => ADD_STATUS_PATH,
+            Self::Output(_) => ADD_OUTPUT_PATH,
+        }
+    }
+
+    pub fn params(&self) -> &Vec<(&'static str, String)> {
+        match self {
+            Self::Status(params) | Self::Output(params) => params,
+        }
+    }
+} // }}}
+
+fn wh(kwh: f64) -> String {
+    format!("{}", (kwh * 1000.0).round())
+}
+
+// Window {{{
+// samples for one status interval
+struct Window {
+    start: NaiveDateTime,
+    last: NaiveDateTime,
+    samples: Vec<ReadInputAll>,
+}
+
+impl Window {
+    fn average(&self, f: impl Fn(&ReadInputAll) -> f64) -> f64 {
+        self.samples.iter().map(f).sum::<f64>() / self.samples.len() as f64
+    }
+
+    fn status(&self) -> Upload {
+        // energy is cumulative for the day, so the latest reading is what we want
+        let last = self.samples.last().expect("empty window");
+
+        Upload::Status(vec![
+            ("d", self.last.format("%Y%m%d").to_string()),
+            ("t", self.last.format("%H:%M").to_string()),
+            ("v1", wh(last.e_pv_day)),
+            ("v2", format!("{}", self.average(|i| i.p_pv as f64).round())),
+            ("v3", wh(derived::load_energy_day(last))),
+            (
+                "v4",
+                format!(
+                    "{}",
+                    self.average(|i| derived::load_power(i) as f64).round()
+                ),
+            ),
+            ("v5", format!("{:.1}", self.average(|i| i.t_inner as f64))),
+            ("v6", format!("{:.1}", self.average(|i| i.v_ac_r))),
+            ("v7", format!("{}", last.soc)),
+            (
+                "v8",
+                format!("{}", self.average(|i| i.p_battery as f64).round()),
+            ),
+            (
+                "v9",
+                format!("{}", self.average(|i| i.p_grid as f64).round()),
+            ),
+            ("v10", format!("{:.1}", self.average(|i| i.v_bat))),
+            ("v11", format!("{}", last.t_bat)),
+            (
+                "v12",
+                format!("{}", self.average(|i| i.p_eps as f64).round()),
+            ),
+        ])
+    }
+} // }}}
+
+// Day {{{
+struct Day {
+    date: NaiveDate,
+    peak: (u16, NaiveTime),
+    last: ReadInputAll,
+}
+
+impl Day {
+    fn output(&self) -> Upload {
+        let last = &self.last;
+
+        // pvoutput splits imports by tariff; we don't know the tariffs so it all goes in peak
+        Upload::Output(vec![
+            ("d", self.date.format("%Y%m%d").to_string()),
+            ("g", wh(last.e_pv_day)),
+            ("e", wh(last.e_to_grid_day)),
+            ("pp", format!("{}", self.peak.0)),
+            ("pt", self.peak.1.format("%H:%M").to_string()),
+            ("ip", wh(last.e_to_user_day)),
+            ("c", wh(derived::load_energy_day(last))),
+        ])
+    }
+} // }}}
+
+// Aggregator {{{
+// Collects inputs into a status update per interval, and an output per day.
+// Each is only complete once a sample from the next one arrives, and is then
+// kept until pvoutput accepts it.
+pub struct Aggregator {
+    interval: u32,
+    end_of_day: bool,
+    window: Option<Window>,
+    day: Option<Day>,
+    ready: VecDeque<Upload>,
+}
+
+impl Aggregator {
+    pub fn new(interval: u32, end_of_day: bool) -> Self {
+        Self {
+            interval,
+            end_of_day,
+            window: None,
+            day: None,
+            ready: VecDeque::new(),
+        }
+    }
+
+    // at is the local time of the sample, as pvoutput wants local times
+    pub fn add(&mut self, input: &ReadInputAll, at: NaiveDateTime) {
+        let start = self.window_start(at);
+        if matches!(&self.window, Some(window) if window.start != start) {
+            let window = self.window.take().unwrap();
+            self.push(window.status());
+        }
+
+        if matches!(&self.day, Some(day) if day.date != at.date()) {
+            let day = self.day.take().unwrap();
+            if self.end_of_day {
+                self.push(day.output());
+            }
+        }
+
+        let window = self.window.get_or_insert_with(|| Window {
+            start,
+            last: at,
+            samples: Vec::new(),
+        });
+        window.last = at;
+        window.samples.push(input.clone());
+
+        let day = self.day.get_or_insert_with(|| Day {
+            date: at.date(),
+            peak: (input.p_pv, at.time()),
+            last: input.clone(),
+        });
+        if input.p_pv > day.peak.0 {
+            day.peak = (input.p_pv, at.time());
+        }
+        day.last = input.clone();
+    }
+
+    // completed uploads pvoutput hasn't accepted yet, oldest first
+    pub fn ready(&self) -> Vec<Upload> {
+        self.ready.iter().cloned().collect()
+    }
+
+    pub fn accepted(&mut self, upload: &Upload) {
+        if let Some(pos) = self.ready.iter().position(|u| u == upload) {
+            self.ready.remove(pos);
+        }
+    }
+
+    fn push(&mut self, upload: Upload) {
+        if self.ready.len() >= MAX_READY {
+            if let Some(dropped) = self.ready.pop_front() {
+                warn!("pvoutput: dropping {:?}, never accepted", dropped);
+            }
+        }
+        self.ready.push_back(upload);
+    }
+
+    fn window_start(&self, at: NaiveDateTime) -> NaiveDateTime {
+        let minute = at.minute() / self.interval * self.interval;
+        at.date()
+            .and_hms_opt(at.hour(), minute, 0)
+            .expect("valid time")
+    }
+} // }}}
+
+pub struct PvOutput {
+    config: config::PvOutput,
+    inverter: config::Inverter,
+    client: reqwest::Client,
+    aggregator: RefCell<Aggregator>,
+}
+
+impl PvOutput {
+    pub fn new(config: config::PvOutput, inverter: config::Inverter) -> Self {
+        let aggregator = Aggregator::new(config.interval(), config.end_of_day());
+
+        Self {
+            config,
+            inverter,
+            client: reqwest::Client::new(),
+            aggregator: RefCell::new(aggregator),
+        }
+    }
+
+    async fn post(&self, upload: &Upload) -> Result<()> {
+        debug!("pvoutput {} {:?}", upload.path(), upload.params());
+
+        self.client
+            .post(format!(
+                "{}{}",
+                self.config.url().trim_end_matches('/'),
+                upload.path()
+            ))
+            .header("X-Pvoutput-Apikey", self.config.api_key())
+            .header("X-Pvoutput-SystemId", self.config.system_id())
+            .form(upload.params())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl Output for PvOutput {
+    fn name(&self) -> String {
+        format!("pvoutput for {}", self.config.datalog())
+    }
+
+    // samples are aggregated here, as each arrives, so that write only has
+    // to post whatever that completed and can't count a sample twice
+    fn filter(&self, data: OutputData) -> Option<OutputData> {
+        match data {
+            OutputData::InputAll(ref input) if input.datalog == self.config.datalog() => {
+                let at = self.inverter.local_time(input.time.0);
+                self.aggregator.borrow_mut().add(input, at);
+                Some(data)
+            }
+            _ => None,
+        }
+    }
+
+    // pvoutput rate limits us, so don't retry; anything not accepted is
+    // posted again with the next sample
+    fn retries(&self) -> Option<u32> {
+        Some(0)
+    }
+
+    async fn write(&self, _batch: &[OutputData]) -> Result<()> {
+        let mut result = Ok(());
+
+        let ready = self.aggregator.borrow().ready();
+        for upload in ready {
+            match self.post(&upload).await {
+                Ok(()) => self.aggregator.borrow_mut().accepted(&upload),
+                Err(err) => {
+                    warn!("pvoutput {}: {}", upload.path(), err);
+                    result = Err(err);
+                }
+            }
+        }
+
+        result
+    }
+
+    // nothing is dropped; write already said what failed
+    fn failed(&self, _batch: &[OutputData], _err: &Error) {
+        info!(
+            "{}: {} upload(s) not accepted yet, will try again with the next sample",
+            self.name(),
+            self.aggregator.borrow().ready().len()
+        );
+    }
+}
@@ -1,23 +1,23 @@
 use crate::prelude::*;
 
-// this just needs to be bigger than the max register we'll see
-const REGISTER_COUNT: usize = 256;
+use std::collections::HashMap;
 
 #[derive(Clone, Debug)]
 pub enum ChannelData {
-    ReadRegister(u16, Rc<RefCell<oneshot::Sender<u16>>>),
-    RegisterData(u16, u16),
+    ReadRegister(Serial, u16, Rc<RefCell<oneshot::Sender<Option<u16>>>>),
+    RegisterData(Serial, u16, u16),
     Shutdown,
 }
 
+// Holding registers, by inverter datalog, as last read from or written to it
 pub struct RegisterCache {
     channels: Channels,
-    register_data: Rc<RefCell<[u16; REGISTER_COUNT]>>,
+    register_data: Rc<RefCell<HashMap<(Serial, u16), u16>>>,
 }
 
 impl RegisterCache {
     pub fn new(channels: Channels) -> Self {
-        let register_data = Rc::new(RefCell::new([0; REGISTER_COUNT]));
+        let register_data = Rc::new(RefCell::new(HashMap::new()));
 
         Self {
             channels,
@@ -33,14 +33,14 @@ impl RegisterCache {
 
     // external helper method to simplify access to the cache, use like so:
     //
-    //   RegisterCache::get(&self.channels, 1);
+    //   RegisterCache::get(&self.channels, datalog, 1);
     //
-    pub async fn get(channels: &Channels, register: u16) -> u16 {
+    // None if we've not seen that register yet, or the cache isn't running
+    pub async fn get(channels: &Channels, datalog: Serial, register: u16) -> Option<u16> {
         let (tx, rx) = oneshot::channel();
-        let channel_data = ChannelData::ReadRegister(register, Rc::new(RefCell::new(tx)));
+        let channel_data = ChannelData::ReadRegister(datalog, register, Rc::new(RefCell::new(tx)));
         let _ = channels.read_register_cache.send(channel_data);
-        rx.await
-            .expect("unexpected error reading from register cache")
+        rx.await.ok().flatten()
     }
 
     async fn cache_getter(&self) -> Result<()> {
@@ -48,19 +48,15 @@ impl RegisterCache {
 
         info!("register_cache getter starting");
 
-        while let ChannelData::ReadRegister(register, reply_tx) = receiver.recv().await? {
-            if register < REGISTER_COUNT as u16 {
-                let register_data = self.register_data.borrow();
-                let value = register_data[register as usize];
-
-                let reply_tx = Rc::try_unwrap(reply_tx).unwrap();
-                let _ = reply_tx.into_inner().send(value);
-            } else {
-                warn!(
-                    "cannot cache register {}, increase REGISTER_COUNT!",
-                    register
-                );
-            }
+        while let ChannelData::ReadRegister(datalog, register, reply_tx) = receiver.recv().await? {
+            let value = self
+                .register_data
+                .borrow()
+                .get(&(datalog, register))
+                .copied();
+
+            let reply_tx = Rc::try_unwrap(reply_tx).unwrap();
+            let _ = reply_tx.into_inner().send(value);
         }
 
         info!("register_cache getter exiting");
@@ -73,16 +69,10 @@ impl RegisterCache {
 
         info!("register_cache setter starting");
 
-        while let ChannelData::RegisterData(register, value) = receiver.recv().await? {
-            if register < REGISTER_COUNT as u16 {
-                let mut register_data = self.register_data.borrow_mut();
-                register_data[register as usize] = value;
-            } else {
-                warn!(
-                    "cannot cache register {}, increase REGISTER_COUNT!",
-                    register
-                );
-            }
+        while let ChannelData::RegisterData(datalog, register, value) = receiver.recv().await? {
+            self.register_data
+                .borrow_mut()
+                .insert((datalog, register), value);
         }
 
         info!("register_cache setter exiting");
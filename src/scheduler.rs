@@ -2,7 +2,7 @@ use crate::prelude::*;
 
 use cron_parser::parse;
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
 
 pub struct Scheduler {
     config: ConfigWrapper,
@@ -29,38 +29,439 @@ impl Scheduler {
 
         info!("scheduler starting");
 
+        // one loop per job and inverter, as each runs on its own inverter's clock
+        let jobs: Vec<_> = Self::jobs(&scheduler)
+            .into_iter()
+            .flat_map(|job| {
+                self.target_inverters(&job)
+                    .into_iter()
+                    .map(move |inverter| {
+                        let job = config::Job {
+                            inverters: vec![inverter.datalog()],
+                            ..job.clone()
+                        };
+                        (job, inverter)
+                    })
+            })
+            .collect();
+        let job_loops = futures::future::try_join_all(
+            jobs.iter()
+                .map(|(job, inverter)| self.job_loop(&scheduler, job, inverter)),
+        );
+
+        futures::try_join!(
+            job_loops,
+            self.tariff_loop(&scheduler),
+            self.dst_loop(&scheduler)
+        )?;
+
+        info!("scheduler exiting");
+
+        Ok(())
+    }
+
+    // timesync_cron predates jobs, and is just shorthand for one
+    fn jobs(scheduler: &config::Scheduler) -> Vec<config::Job> {
+        let mut r = scheduler.jobs().clone();
+
         if let Some(timesync_cron) = scheduler.timesync_cron() {
-            // sticking to Utc here avoids some "invalid date" panics around DST changes
-            while let Ok(next) = parse(timesync_cron, &Utils::utc()) {
-                let sleep = next - Utils::utc();
+            r.push(config::Job {
+                name: "timesync".to_owned(),
+                cron: Some(timesync_cron.to_owned()),
+                solar: None,
+                command: "set/timesync".to_owned(),
+                payload: String::new(),
+                inverters: Vec::new(),
+            });
+        }
+
+        r
+    }
+
+    async fn job_loop(
+        &self,
+        scheduler: &config::Scheduler,
+        job: &config::Job,
+        inverter: &config::Inverter,
+    ) -> Result<()> {
+        loop {
+            let next = match Self::next_run(scheduler, job, inverter, Utils::utc()) {
+                Ok(next) => next,
+                Err(err) => {
+                    warn!("job {}: {}, not running it", job.name(), err);
+                    return Ok(());
+                }
+            };
+            let sleep = next - Utils::utc();
+
+            info!(
+                "job {}: next run on inverter {} at {}, sleeping for {}",
+                job.name(),
+                inverter.datalog(),
+                inverter.local_time(next),
+                sleep
+            );
+            self.publish_next_run(job, Self::display_time(inverter, next))?;
 
-                // localtime is only used for display
-                let local_next: DateTime<Local> = DateTime::from(next);
-                info!("next timesync at {}, sleeping for {}", local_next, sleep);
+            tokio::time::sleep(sleep.to_std()?).await;
+            self.run_job(job)?;
+        }
+    }
 
-                tokio::time::sleep(sleep.to_std()?).await;
-                self.timesync().await?;
+    pub fn next_run(
+        scheduler: &config::Scheduler,
+        job: &config::Job,
+        inverter: &config::Inverter,
+        now: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>> {
+        match (job.cron(), job.solar()) {
+            (Some(cron), None) => Self::next_cron(cron, inverter, now),
+            (None, Some(solar)) => {
+                let trigger: solar::Trigger = solar.parse()?;
+                let (latitude, longitude) = scheduler
+                    .location()
+                    .ok_or_else(|| anyhow!("solar jobs need scheduler latitude and longitude"))?;
+
+                trigger
+                    .next_after(now, latitude, longitude)
+                    .ok_or_else(|| anyhow!("no {} in the next year", solar))
             }
-        } else {
-            info!("timesync_cron config not found, skipping");
+            _ => bail!("needs exactly one of cron or solar"),
         }
+    }
 
-        info!("scheduler exiting");
+    // cron times are on the inverter's clock. cron_parser is given that as a
+    // naive time dressed up as UTC, so it never sees a time which doesn't
+    // exist; Inverter::utc_time then deals with those.
+    pub fn next_cron(
+        cron: &str,
+        inverter: &config::Inverter,
+        now: DateTime<Utc>,
+    ) -> Result<DateTime<Utc>> {
+        // cron_parser panics on too few fields
+        if cron.split_whitespace().count() != 5 {
+            bail!("cannot parse cron {}: needs 5 fields", cron);
+        }
+
+        let local_now = Utc.from_utc_datetime(&inverter.local_time(now));
+        let next = parse(cron, &local_now)
+            .map_err(|err| anyhow!("cannot parse cron {}: {:?}", cron, err))?;
+
+        Ok(inverter.utc_time(next.naive_utc()))
+    }
+
+    // what we publish as the next run, with the inverter's UTC offset
+    fn display_time(inverter: &config::Inverter, at: DateTime<Utc>) -> String {
+        match inverter.timezone() {
+            Some(tz) => at.with_timezone(&tz).to_rfc3339(),
+            None => at.with_timezone(&Local).to_rfc3339(),
+        }
+    }
+
+    // clocks going forward or back leave the inverter an hour out until the
+    // next timesync, so do one straight after
+    async fn dst_loop(&self, scheduler: &config::Scheduler) -> Result<()> {
+        if !scheduler.timesync_on_dst_change() {
+            return Ok(());
+        }
+
+        let inverters = self.config.enabled_inverters();
+        let loops = inverters
+            .iter()
+            .map(|inverter| self.inverter_dst_loop(inverter));
+
+        futures::future::try_join_all(loops).await?;
+
+        Ok(())
+    }
+
+    async fn inverter_dst_loop(&self, inverter: &config::Inverter) -> Result<()> {
+        let job = config::Job {
+            name: "dst_timesync".to_owned(),
+            cron: None,
+            solar: None,
+            command: "set/timesync".to_owned(),
+            payload: String::new(),
+            inverters: vec![inverter.datalog()],
+        };
+
+        while let Some(change) = Self::next_offset_change(inverter, Utils::utc()) {
+            let next = change + Duration::minutes(1);
+            let sleep = next - Utils::utc();
+            info!(
+                "inverter {}: next clock change at {}, timesync then",
+                inverter.datalog(),
+                change
+            );
+
+            tokio::time::sleep(sleep.to_std()?).await;
+            self.run_job(&job)?;
+        }
+
+        Ok(())
+    }
+
+    // when the inverter's timezone next changes its UTC offset, if within a year
+    pub fn next_offset_change(
+        inverter: &config::Inverter,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let offset = |at: DateTime<Utc>| inverter.local_time(at) - at.naive_utc();
+        let current = offset(now);
+
+        // hourly is fine enough to find it, then narrow it down to the minute
+        let mut after = (1..=366 * 24)
+            .map(|hours| now + Duration::hours(hours))
+            .find(|at| offset(*at) != current)?;
+        let mut before = after - Duration::hours(1);
+        while after - before > Duration::minutes(1) {
+            let middle = before + (after - before) / 2;
+            if offset(middle) == current {
+                before = middle;
+            } else {
+                after = middle;
+            }
+        }
+
+        Some(after)
+    }
+
+    // hands the command to the coordinator exactly as if it had come in over
+    // MQTT, so it gets the same parsing and result/ replies
+    pub fn run_job(&self, job: &config::Job) -> Result<()> {
+        info!(
+            "job {}: running {} {}",
+            job.name(),
+            job.command(),
+            job.payload()
+        );
+
+        for datalog in self.targets(job) {
+            self.send_command(datalog, job.command(), job.payload().to_owned())?;
+        }
+
+        Ok(())
+    }
+
+    fn send_command(&self, datalog: Serial, command: &str, payload: String) -> Result<()> {
+        let message = mqtt::Message {
+            topic: format!("cmd/{}/{}", datalog, command),
+            retain: false,
+            payload,
+        };
+
+        let channel_data = mqtt::ChannelData::Internal(message);
+        if self.channels.from_mqtt.send(channel_data).is_err() {
+            bail!("send(from_mqtt) failed - channel closed?");
+        }
+
+        Ok(())
+    }
+
+    pub fn publish_next_run(&self, job: &config::Job, next: String) -> Result<()> {
+        if !self.config.mqtt().enabled() {
+            return Ok(());
+        }
+
+        for datalog in self.targets(job) {
+            let message = mqtt::Message {
+                topic: format!("{}/schedule/{}", datalog, job.name()),
+                retain: true,
+                payload: next.clone(),
+            };
+
+            let channel_data = mqtt::ChannelData::Message(message);
+            if self.channels.to_mqtt.send(channel_data).is_err() {
+                bail!("send(to_mqtt) failed - channel closed?");
+            }
+        }
 
         Ok(())
     }
 
-    async fn timesync(&self) -> Result<()> {
-        info!("timesync starting");
+    fn targets(&self, job: &config::Job) -> Vec<Serial> {
+        self.target_inverters(job)
+            .iter()
+            .map(|inverter| inverter.datalog())
+            .collect()
+    }
+
+    fn target_inverters(&self, job: &config::Job) -> Vec<config::Inverter> {
+        self.config
+            .enabled_inverters()
+            .into_iter()
+            .filter(|inverter| {
+                job.inverters().is_empty() || job.inverters().contains(&inverter.datalog())
+            })
+            .collect()
+    }
+
+    async fn tariff_loop(&self, scheduler: &config::Scheduler) -> Result<()> {
+        let tariff = match scheduler.tariff() {
+            Some(tariff) if tariff.enabled() => tariff,
+            _ => {
+                info!("tariff config not found, skipping");
+                return Ok(());
+            }
+        };
+
+        // like jobs, plan_cron is on each inverter's own clock
+        let inverters = self.config.enabled_inverters();
+        let loops = inverters
+            .iter()
+            .map(|inverter| self.inverter_tariff_loop(tariff, inverter));
+
+        futures::future::try_join_all(loops).await?;
+
+        Ok(())
+    }
+
+    async fn inverter_tariff_loop(
+        &self,
+        tariff: &config::Tariff,
+        inverter: &config::Inverter,
+    ) -> Result<()> {
+        loop {
+            let next = match Self::next_cron(tariff.plan_cron(), inverter, Utils::utc()) {
+                Ok(next) => next,
+                Err(err) => {
+                    warn!("tariff: {}, not planning", err);
+                    return Ok(());
+                }
+            };
+            let sleep = next - Utils::utc();
+
+            info!(
+                "inverter {}: next tariff plan at {}, sleeping for {}",
+                inverter.datalog(),
+                inverter.local_time(next),
+                sleep
+            );
+
+            tokio::time::sleep(sleep.to_std()?).await;
+            // prices not being available yet shouldn't stop everything else
+            if let Err(err) = self.plan_tariff(tariff, inverter).await {
+                error!(
+                    "inverter {}: tariff plan failed: {:?}",
+                    inverter.datalog(),
+                    err
+                );
+            }
+        }
+    }
+
+    async fn plan_tariff(
+        &self,
+        tariff: &config::Tariff,
+        inverter: &config::Inverter,
+    ) -> Result<()> {
+        let now = inverter.local_time(Utils::utc());
+
+        let slots = match tariff.prices() {
+            Some(source) => {
+                let prices = tariff::load_prices(source).await?;
+                tariff::slots_from_agile(&prices, inverter)?
+            }
+            None => tariff::slots_from_bands(tariff.bands(), now)?,
+        };
+
+        let plan = tariff::Plan::new(
+            &slots,
+            now,
+            tariff::slots_for(tariff.charge_minutes()),
+            tariff::slots_for(tariff.discharge_minutes().unwrap_or(0)),
+        );
 
-        for inverter in self.config.enabled_inverters() {
-            coordinator::commands::timesync::TimeSync::new(self.channels.clone(), inverter)
-                .run()
-                .await?;
+        let describe = |windows: &Vec<tariff::Window>| {
+            windows
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        info!(
+            "inverter {}: tariff plan: charge {} to {}%, discharge {}",
+            inverter.datalog(),
+            describe(&plan.charge),
+            tariff.target_soc(),
+            describe(&plan.discharge)
+        );
+
+        self.program_charge(inverter, &plan.charge, tariff.target_soc())?;
+        if tariff.discharge_minutes().is_some() {
+            self.program_discharge(inverter, &plan.discharge)?;
         }
 
-        info!("timesync complete");
+        Ok(())
+    }
+
+    // these are sent as commands too, so the coordinator can skip registers
+    // which already hold what we want and count the rest against the budget
+    fn program_charge(
+        &self,
+        inverter: &config::Inverter,
+        windows: &[tariff::Window],
+        target_soc: u16,
+    ) -> Result<()> {
+        let datalog = inverter.datalog();
+
+        self.program_windows(datalog, windows, "ac_charge")?;
+        self.send_command(
+            datalog,
+            "set/ac_charge_soc_limit_pct",
+            target_soc.to_string(),
+        )?;
+        self.send_command(datalog, "set/ac_charge", Self::on_off(!windows.is_empty()))?;
 
         Ok(())
     }
+
+    fn program_discharge(
+        &self,
+        inverter: &config::Inverter,
+        windows: &[tariff::Window],
+    ) -> Result<()> {
+        let datalog = inverter.datalog();
+
+        self.program_windows(datalog, windows, "forced_discharge")?;
+        self.send_command(
+            datalog,
+            "set/forced_discharge",
+            Self::on_off(!windows.is_empty()),
+        )?;
+
+        Ok(())
+    }
+
+    // fills all three time registers, switching off any we don't need
+    fn program_windows(
+        &self,
+        datalog: Serial,
+        windows: &[tariff::Window],
+        command: &str,
+    ) -> Result<()> {
+        for num in 1..=3 {
+            let window = windows
+                .get(num - 1)
+                .copied()
+                .unwrap_or_else(tariff::Window::off);
+
+            let payload = serde_json::json!({
+                "start": window.start.format("%H:%M").to_string(),
+                "end": window.end.format("%H:%M").to_string(),
+            });
+            self.send_command(
+                datalog,
+                &format!("set/{}/{}", command, num),
+                payload.to_string(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn on_off(enable: bool) -> String {
+        if enable { "ON" } else { "OFF" }.to_owned()
+    }
 }
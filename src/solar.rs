@@ -0,0 +1,154 @@
+use crate::prelude::*;
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+
+// Julian date of 2000-01-01 12:00 UTC, and of the unix epoch
+const J2000: f64 = 2451545.0;
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+// refraction and the sun's radius mean it's visible a little below the horizon
+const HORIZON_DEGREES: f64 = -0.833;
+const EARTH_TILT_DEGREES: f64 = 23.4397;
+// far enough ahead to get past polar night
+const SEARCH_DAYS: i64 = 370;
+
+// Event {{{
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Sunrise,
+    Noon,
+    Sunset,
+}
+
+impl FromStr for Event {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input {
+            "sunrise" => Ok(Self::Sunrise),
+            "noon" | "solar_noon" => Ok(Self::Noon),
+            "sunset" => Ok(Self::Sunset),
+            _ => bail!("unknown solar event {}", input),
+        }
+    }
+} // }}}
+
+// Trigger {{{
+// A solar event with an offset, parsed from eg "sunrise", "sunset-30m" or "noon+1h30m"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trigger {
+    pub event: Event,
+    pub offset: Duration,
+}
+
+impl FromStr for Trigger {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.trim();
+
+        let (event, offset) = match input.find(['+', '-']) {
+            Some(i) => (&input[..i], Some(&input[i..])),
+            None => (input, None),
+        };
+
+        let offset = match offset {
+            Some(offset) => Self::parse_offset(offset)
+                .ok_or_else(|| anyhow!("bad offset in solar trigger {}", input))?,
+            None => Duration::zero(),
+        };
+
+        Ok(Self {
+            event: event.trim().parse()?,
+            offset,
+        })
+    }
+}
+
+impl Trigger {
+    // "+1h", "-30m", "+1h30m"
+    fn parse_offset(input: &str) -> Option<Duration> {
+        let (sign, mut rest) = match input.split_at(1) {
+            ("+", rest) => (1, rest),
+            ("-", rest) => (-1, rest),
+            _ => return None,
+        };
+
+        let mut minutes = 0;
+        while !rest.is_empty() {
+            let i = rest.find(|c: char| !c.is_ascii_digit())?;
+            let value: i64 = rest[..i].parse().ok()?;
+            let unit = rest[i..].chars().next()?;
+            minutes += match unit {
+                'h' => value * 60,
+                'm' => value,
+                _ => return None,
+            };
+            rest = &rest[i + unit.len_utf8()..];
+        }
+
+        Some(Duration::minutes(sign * minutes))
+    }
+
+    // The first time this triggers after now, or None if the sun doesn't rise
+    // or set at all for the next year (only likely at the poles).
+    pub fn next_after(
+        &self,
+        now: DateTime<Utc>,
+        latitude: f64,
+        longitude: f64,
+    ) -> Option<DateTime<Utc>> {
+        // start a day early as a big negative offset can pull tomorrow's event into today
+        let today = now.date_naive();
+
+        (-1..SEARCH_DAYS)
+            .map(|days| today + Duration::days(days))
+            .filter_map(|date| event_time(self.event, date, latitude, longitude))
+            .map(|time| time + self.offset)
+            .find(|time| *time > now)
+    }
+} // }}}
+
+// When event happens on the solar day nearest date, using the sunrise equation
+// (https://en.wikipedia.org/wiki/Sunrise_equation). Good to a minute or two,
+// which is plenty for switching things on and off.
+//
+// None if the sun doesn't cross the horizon that day.
+pub fn event_time(
+    event: Event,
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+) -> Option<DateTime<Utc>> {
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    let days = (date - epoch).num_days() as f64;
+
+    // mean solar noon, in days since J2000
+    let mean_noon = days + 0.0009 - longitude / 360.0;
+
+    let anomaly = (357.5291 + 0.98560028 * mean_noon)
+        .rem_euclid(360.0)
+        .to_radians();
+    let centre =
+        1.9148 * anomaly.sin() + 0.0200 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + centre + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+
+    let transit =
+        J2000 + mean_noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * EARTH_TILT_DEGREES.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = (HORIZON_DEGREES.to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+
+    let julian = match event {
+        Event::Noon => transit,
+        _ if !(-1.0..=1.0).contains(&cos_hour_angle) => return None,
+        Event::Sunrise => transit - cos_hour_angle.acos().to_degrees() / 360.0,
+        Event::Sunset => transit + cos_hour_angle.acos().to_degrees() / 360.0,
+    };
+
+    let secs = ((julian - UNIX_EPOCH_JD) * 86400.0).round() as i64;
+    Utc.timestamp_opt(secs, 0).single()
+}
@@ -0,0 +1,172 @@
+use crate::prelude::*;
+
+use lxp::packet::ReadInputAll;
+use std::collections::{HashMap, HashSet};
+
+// Aggregator {{{
+// Combines the inputs of a system's inverters. Each inverter reports on its
+// own schedule, so a new total is only produced once all of them have
+// reported again since the last one.
+pub struct Aggregator {
+    system: config::System,
+    latest: HashMap<Serial, ReadInputAll>,
+    fresh: HashSet<Serial>,
+    online: bool,
+}
+
+impl Aggregator {
+    pub fn new(system: config::System) -> Self {
+        Self {
+            system,
+            latest: HashMap::new(),
+            fresh: HashSet::new(),
+            online: false,
+        }
+    }
+
+    pub fn system(&self) -> &config::System {
+        &self.system
+    }
+
+    pub fn contains(&self, datalog: Serial) -> bool {
+        self.system.inverters().contains(&datalog)
+    }
+
+    // whether the last add produced a total, and nobody has gone offline since
+    pub fn online(&self) -> bool {
+        self.online
+    }
+
+    pub fn add(&mut self, input: &ReadInputAll) -> Option<ReadInputAll> {
+        if !self.contains(input.datalog) {
+            return None;
+        }
+
+        self.latest.insert(input.datalog, input.clone());
+        self.fresh.insert(input.datalog);
+
+        if !self
+            .system
+            .inverters()
+            .iter()
+            .all(|d| self.fresh.contains(d))
+        {
+            return None;
+        }
+
+        self.fresh.clear();
+        self.online = true;
+
+        let members: Vec<&ReadInputAll> = self
+            .system
+            .inverters()
+            .iter()
+            .filter_map(|datalog| self.latest.get(datalog))
+            .collect();
+
+        Some(aggregate(&members, self.system.datalog()))
+    }
+
+    // its old inputs can't count towards the next total
+    pub fn disconnected(&mut self, datalog: Serial) {
+        if self.contains(datalog) {
+            self.latest.remove(&datalog);
+            self.fresh.remove(&datalog);
+            self.online = false;
+        }
+    }
+} // }}}
+
+// Powers and energies are summed, SOC and battery voltage averaged, and
+// temperatures take the hottest. Anything else (grid voltage and frequency,
+// battery limits and so on) is shared, so comes from the first inverter.
+pub fn aggregate(members: &[&ReadInputAll], datalog: Serial) -> ReadInputAll {
+    let first = members.first().expect("system with no inverters");
+    let mut r = (*first).clone();
+
+    let count = members.len() as f64;
+    let sum_u16 = |f: fn(&ReadInputAll) -> u16| {
+        members
+            .iter()
+            .fold(0_u16, |acc, i| acc.saturating_add(f(i)))
+    };
+    let sum_i32 = |f: fn(&ReadInputAll) -> i32| members.iter().map(|i| f(i)).sum::<i32>();
+    let sum_f64 = |f: fn(&ReadInputAll) -> f64| members.iter().map(|i| f(i)).sum::<f64>();
+    let max_u16 = |f: fn(&ReadInputAll) -> u16| members.iter().map(|i| f(i)).max().unwrap_or(0);
+    let avg_f64 = |f: fn(&ReadInputAll) -> f64| sum_f64(f) / count;
+    // energies are 0.1kWh resolution; don't let float sums add noise to that
+    let sum_kwh = |f: fn(&ReadInputAll) -> f64| (sum_f64(f) * 10.0).round() / 10.0;
+
+    r.p_pv = sum_u16(|i| i.p_pv);
+    r.p_pv_1 = sum_u16(|i| i.p_pv_1);
+    r.p_pv_2 = sum_u16(|i| i.p_pv_2);
+    r.p_pv_3 = sum_u16(|i| i.p_pv_3);
+    r.p_battery = sum_i32(|i| i.p_battery);
+    r.p_charge = sum_u16(|i| i.p_charge);
+    r.p_discharge = sum_u16(|i| i.p_discharge);
+    r.p_inv = sum_u16(|i| i.p_inv);
+    r.p_rec = sum_u16(|i| i.p_rec);
+    r.p_eps = sum_u16(|i| i.p_eps);
+    r.s_eps = sum_u16(|i| i.s_eps);
+    r.p_grid = sum_i32(|i| i.p_grid);
+    r.p_to_grid = sum_u16(|i| i.p_to_grid);
+    r.p_to_user = sum_u16(|i| i.p_to_user);
+    r.p_gen = sum_u16(|i| i.p_gen);
+    r.p_eps_l1 = sum_u16(|i| i.p_eps_l1);
+    r.p_eps_l2 = sum_u16(|i| i.p_eps_l2);
+    r.s_eps_l1 = sum_u16(|i| i.s_eps_l1);
+    r.s_eps_l2 = sum_u16(|i| i.s_eps_l2);
+    r.bat_current = sum_f64(|i| i.bat_current);
+
+    r.e_pv_day = sum_kwh(|i| i.e_pv_day);
+    r.e_pv_day_1 = sum_kwh(|i| i.e_pv_day_1);
+    r.e_pv_day_2 = sum_kwh(|i| i.e_pv_day_2);
+    r.e_pv_day_3 = sum_kwh(|i| i.e_pv_day_3);
+    r.e_inv_day = sum_kwh(|i| i.e_inv_day);
+    r.e_rec_day = sum_kwh(|i| i.e_rec_day);
+    r.e_chg_day = sum_kwh(|i| i.e_chg_day);
+    r.e_dischg_day = sum_kwh(|i| i.e_dischg_day);
+    r.e_eps_day = sum_kwh(|i| i.e_eps_day);
+    r.e_to_grid_day = sum_kwh(|i| i.e_to_grid_day);
+    r.e_to_user_day = sum_kwh(|i| i.e_to_user_day);
+    r.e_gen_day = sum_kwh(|i| i.e_gen_day);
+    r.e_eps_l1_day = sum_kwh(|i| i.e_eps_l1_day);
+    r.e_eps_l2_day = sum_kwh(|i| i.e_eps_l2_day);
+
+    r.e_pv_all = sum_kwh(|i| i.e_pv_all);
+    r.e_pv_all_1 = sum_kwh(|i| i.e_pv_all_1);
+    r.e_pv_all_2 = sum_kwh(|i| i.e_pv_all_2);
+    r.e_pv_all_3 = sum_kwh(|i| i.e_pv_all_3);
+    r.e_inv_all = sum_kwh(|i| i.e_inv_all);
+    r.e_rec_all = sum_kwh(|i| i.e_rec_all);
+    r.e_chg_all = sum_kwh(|i| i.e_chg_all);
+    r.e_dischg_all = sum_kwh(|i| i.e_dischg_all);
+    r.e_eps_all = sum_kwh(|i| i.e_eps_all);
+    r.e_to_grid_all = sum_kwh(|i| i.e_to_grid_all);
+    r.e_to_user_all = sum_kwh(|i| i.e_to_user_all);
+    r.e_gen_all = sum_kwh(|i| i.e_gen_all);
+    r.e_eps_l1_all = sum_kwh(|i| i.e_eps_l1_all);
+    r.e_eps_l2_all = sum_kwh(|i| i.e_eps_l2_all);
+
+    r.soc = avg_f64(|i| i.soc as f64).round() as i8;
+    r.soh = avg_f64(|i| i.soh as f64).round() as i8;
+    r.v_bat = (avg_f64(|i| i.v_bat) * 10.0).round() / 10.0;
+
+    r.t_inner = max_u16(|i| i.t_inner);
+    r.t_rad_1 = max_u16(|i| i.t_rad_1);
+    r.t_rad_2 = max_u16(|i| i.t_rad_2);
+    r.t_bat = max_u16(|i| i.t_bat);
+
+    r.fault_code = members.iter().fold(0, |acc, i| acc | i.fault_code);
+    r.warning_code = members.iter().fold(0, |acc, i| acc | i.warning_code);
+
+    r.time = members
+        .iter()
+        .map(|i| i.time.clone())
+        .max_by_key(|time| time.0)
+        .unwrap_or_else(|| first.time.clone());
+    r.datalog = datalog;
+
+    // percentages and times don't add up, so work them out again from the totals
+    derived::apply(r)
+}
@@ -0,0 +1,249 @@
+use crate::prelude::*;
+
+use chrono::{Duration, NaiveDateTime, NaiveTime, Timelike};
+use serde::Deserialize;
+
+// the inverter has three of each kind of time register
+const MAX_WINDOWS: usize = 3;
+const SLOT_MINUTES: i64 = 30;
+
+// Slot {{{
+// A period at one price. Times are the inverter's local time, as that's what
+// it runs on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slot {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub price: f64,
+}
+
+fn parse_time(input: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(input, "%H:%M").map_err(|err| anyhow!("bad time {}: {}", input, err))
+}
+
+// Expands time-of-use bands into half-hour slots for the day following from.
+// Times not covered by any band are left out, so never get picked.
+pub fn slots_from_bands(bands: &[config::TariffBand], from: NaiveDateTime) -> Result<Vec<Slot>> {
+    let bands = bands
+        .iter()
+        .map(|band| Ok((parse_time(&band.start)?, parse_time(&band.end)?, band.price)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let minute = from.minute() as i64 / SLOT_MINUTES * SLOT_MINUTES;
+    let mut start = from.date().and_hms_opt(from.hour(), 0, 0).unwrap() + Duration::minutes(minute);
+
+    let mut r = Vec::new();
+    for _ in 0..(24 * 60 / SLOT_MINUTES) {
+        let end = start + Duration::minutes(SLOT_MINUTES);
+        let time = start.time();
+
+        let band = bands.iter().find(|(band_start, band_end, _)| {
+            if band_start <= band_end {
+                time >= *band_start && time < *band_end
+            } else {
+                time >= *band_start || time < *band_end
+            }
+        });
+        if let Some((_, _, price)) = band {
+            r.push(Slot {
+                start,
+                end,
+                price: *price,
+            });
+        }
+
+        start = end;
+    }
+
+    Ok(r)
+}
+
+#[derive(Deserialize)]
+struct AgileRates {
+    results: Vec<AgileRate>,
+}
+
+#[derive(Deserialize)]
+struct AgileRate {
+    value_inc_vat: f64,
+    valid_from: String,
+    valid_to: String,
+}
+
+// The format of Octopus' standard-unit-rates API; newest first, times in UTC.
+// Slots are put on the inverter's clock, as that's what the windows run on.
+pub fn slots_from_agile(json: &str, inverter: &config::Inverter) -> Result<Vec<Slot>> {
+    let rates: AgileRates = serde_json::from_str(json)?;
+
+    let local = |time: &str| -> Result<NaiveDateTime> {
+        let time = chrono::DateTime::parse_from_rfc3339(time)
+            .map_err(|err| anyhow!("bad time {}: {}", time, err))?;
+        Ok(inverter.local_time(time.with_timezone(&chrono::Utc)))
+    };
+
+    let mut r = rates
+        .results
+        .iter()
+        .map(|rate| {
+            Ok(Slot {
+                start: local(&rate.valid_from)?,
+                end: local(&rate.valid_to)?,
+                price: rate.value_inc_vat,
+            })
+        })
+        .collect::<Result<Vec<Slot>>>()?;
+    r.sort_by_key(|slot| slot.start);
+
+    Ok(r)
+}
+
+pub async fn load_prices(source: &str) -> Result<String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        Ok(reqwest::get(source)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    } else {
+        Ok(std::fs::read_to_string(source)?)
+    }
+} // }}}
+
+// Window {{{
+// One time register's worth: a daily period the inverter repeats
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Window {
+    pub fn off() -> Self {
+        let midnight = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
+        Self {
+            start: midnight,
+            end: midnight,
+        }
+    }
+
+    pub fn values(&self) -> [u8; 4] {
+        [
+            self.start.hour() as u8,
+            self.start.minute() as u8,
+            self.end.hour() as u8,
+            self.end.minute() as u8,
+        ]
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+} // }}}
+
+// Plan {{{
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    pub charge: Vec<Window>,
+    pub discharge: Vec<Window>,
+}
+
+impl Plan {
+    // Charge in the cheapest slots and discharge in the dearest, over the day
+    // following from.
+    pub fn new(
+        slots: &[Slot],
+        from: NaiveDateTime,
+        charge_slots: usize,
+        discharge_slots: usize,
+    ) -> Self {
+        let until = from + Duration::hours(24);
+        let mut slots: Vec<&Slot> = slots
+            .iter()
+            .filter(|slot| slot.start >= from && slot.start < until)
+            .collect();
+
+        slots.sort_by(|a, b| a.price.total_cmp(&b.price));
+        let charge: Vec<&Slot> = slots.iter().take(charge_slots).cloned().collect();
+
+        // don't let them overlap if there aren't many slots to go round
+        let dearest = slots
+            .len()
+            .saturating_sub(charge.len())
+            .min(discharge_slots);
+        let discharge: Vec<&Slot> = slots.iter().rev().take(dearest).cloned().collect();
+
+        Self {
+            charge: Self::windows(charge),
+            discharge: Self::windows(discharge),
+        }
+    }
+
+    fn windows(mut slots: Vec<&Slot>) -> Vec<Window> {
+        slots.sort_by_key(|slot| slot.start);
+
+        // contiguous runs of slots
+        let mut runs: Vec<(NaiveDateTime, NaiveDateTime)> = Vec::new();
+        for slot in slots {
+            match runs.last_mut() {
+                Some(run) if run.1 == slot.start => run.1 = slot.end,
+                _ => runs.push((slot.start, slot.end)),
+            }
+        }
+
+        // registers can't span midnight, so split runs that do
+        let mut r: Vec<Window> = Vec::new();
+        for (start, end) in runs {
+            let midnight = start
+                .date()
+                .succ_opt()
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap();
+            if end > midnight {
+                r.push(Self::window(start, midnight));
+                r.push(Self::window(midnight, end));
+            } else {
+                r.push(Self::window(start, end));
+            }
+        }
+        r.sort_by_key(|window| window.start);
+
+        // too many; fill in the smallest gaps until they fit
+        while r.len() > MAX_WINDOWS {
+            let i = (0..r.len() - 1)
+                .min_by_key(|&i| r[i + 1].start - r[i].end)
+                .unwrap();
+            r[i].end = r[i].end.max(r[i + 1].end);
+            r.remove(i + 1);
+        }
+
+        r
+    }
+
+    fn window(start: NaiveDateTime, end: NaiveDateTime) -> Window {
+        // the registers can't say 24:00, so stop a minute short
+        let end = if end.time() == Window::off().end && end > start {
+            NaiveTime::from_hms_opt(23, 59, 0).unwrap()
+        } else {
+            end.time()
+        };
+
+        Window {
+            start: start.time(),
+            end,
+        }
+    }
+} // }}}
+
+// minutes to whole slots, rounding up
+pub fn slots_for(minutes: u32) -> usize {
+    let slot_minutes = SLOT_MINUTES as u32;
+    minutes.div_ceil(slot_minutes) as usize
+}
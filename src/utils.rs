@@ -33,6 +33,17 @@ impl Utils {
         Ok((input, num as f64 / 10.0))
     }
 
+    // best-effort hostname lookup without pulling in another crate. falls back to
+    // our pid, which is at least unlikely to collide with another bridge.
+    pub fn hostname() -> String {
+        std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| std::process::id().to_string())
+    }
+
     pub fn current_time_for_nom(input: &[u8]) -> nom::IResult<&[u8], UnixTime> {
         Ok((input, UnixTime::now()))
     }
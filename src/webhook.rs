@@ -0,0 +1,157 @@
+use crate::prelude::*;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use output::{Output, OutputData};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+pub struct Webhook {
+    config: config::Webhook,
+    client: reqwest::Client,
+    // last value we posted for each register, so we only post changes
+    holds: RefCell<HashMap<(Serial, u16), u16>>,
+    last_inputs: RefCell<HashMap<Serial, Instant>>,
+}
+
+impl Webhook {
+    // like databases, webhooks are fixed once running so don't need a ConfigWrapper
+    pub fn new(config: config::Webhook) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            holds: RefCell::new(HashMap::new()),
+            last_inputs: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn body(data: &OutputData) -> serde_json::Value {
+        match data {
+            OutputData::InputAll(input) => serde_json::json!({
+                "type": "inputs",
+                "datalog": input.datalog,
+                "data": input,
+            }),
+            OutputData::Hold(datalog, pairs) => serde_json::json!({
+                "type": "hold",
+                "datalog": datalog,
+                "registers": pairs.iter().cloned().collect::<BTreeMap<u16, u16>>(),
+            }),
+            OutputData::Battery(stats) => serde_json::json!({
+                "type": "battery",
+                "datalog": stats.datalog,
+                "data": stats,
+            }),
+        }
+    }
+
+    // only inputs are rate limited; holding changes are rare and we'd lose them
+    fn rate_limited(&self, datalog: Serial) -> bool {
+        let min_interval = Duration::from_secs(self.config.min_interval());
+        let now = Instant::now();
+
+        let mut last_inputs = self.last_inputs.borrow_mut();
+        match last_inputs.get(&datalog) {
+            Some(last) if now.duration_since(*last) < min_interval => true,
+            _ => {
+                last_inputs.insert(datalog, now);
+                false
+            }
+        }
+    }
+
+    fn changed_holds(&self, datalog: Serial, pairs: Vec<(u16, u16)>) -> Vec<(u16, u16)> {
+        let holds = self.holds.borrow();
+
+        pairs
+            .into_iter()
+            .filter(|(register, value)| holds.get(&(datalog, *register)) != Some(value))
+            .collect()
+    }
+
+    // only once they've been posted, so a failed post is tried again next read
+    fn posted_holds(&self, datalog: Serial, pairs: &[(u16, u16)]) {
+        let mut holds = self.holds.borrow_mut();
+
+        for (register, value) in pairs {
+            holds.insert((datalog, *register), *value);
+        }
+    }
+
+    async fn post(&self, body: &str) -> Result<()> {
+        let mut request = self
+            .client
+            .post(self.config.url())
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+
+        for (key, value) in self.config.headers() {
+            request = request.header(key, value);
+        }
+
+        if let Some(secret) = self.config.secret() {
+            request = request.header("X-Signature-256", Self::signature(secret, body)?);
+        }
+
+        request
+            .body(body.to_owned())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    // HMAC-SHA256 of the body, in the same form GitHub uses
+    pub fn signature(secret: &str, body: &str) -> Result<String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .map_err(|err| anyhow!("bad webhook secret: {}", err))?;
+        mac.update(body.as_bytes());
+
+        Ok(format!(
+            "sha256={}",
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+}
+
+#[async_trait(?Send)]
+impl Output for Webhook {
+    fn name(&self) -> String {
+        format!("webhook to {}", self.config.url())
+    }
+
+    fn filter(&self, data: OutputData) -> Option<OutputData> {
+        match data {
+            OutputData::InputAll(ref input) => {
+                if self.rate_limited(input.datalog) {
+                    debug!("{}: dropping inputs, too soon", self.name());
+                    return None;
+                }
+                Some(data)
+            }
+            OutputData::Hold(datalog, pairs) => {
+                let pairs = self.changed_holds(datalog, pairs);
+                (!pairs.is_empty()).then_some(OutputData::Hold(datalog, pairs))
+            }
+            // daily at most, so never rate limited
+            OutputData::Battery(_) => Some(data),
+        }
+    }
+
+    fn retries(&self) -> Option<u32> {
+        Some(self.config.retries())
+    }
+
+    async fn write(&self, batch: &[OutputData]) -> Result<()> {
+        for data in batch {
+            self.post(&Self::body(data).to_string()).await?;
+
+            if let OutputData::Hold(datalog, pairs) = data {
+                self.posted_holds(*datalog, pairs);
+            }
+        }
+
+        Ok(())
+    }
+}
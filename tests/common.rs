@@ -24,6 +24,10 @@ impl Factory {
             heartbeats: None,
             publish_holdings_on_connect: None,
             read_timeout: None,
+            timezone: None,
+            timesync_on_connect: None,
+            timesync_tolerance: None,
+            generator: None,
         }
     }
 
@@ -228,6 +232,29 @@ impl Factory {
             bms_fw_update_state: 2,
             cycle_count: 200,
             vbat_inv: 5.4,
+            v_gen: 0.0,
+            f_gen: 0.0,
+            p_gen: 0,
+            e_gen_day: 0.0,
+            e_gen_all: 0.0,
+            v_eps_l1: 0.0,
+            v_eps_l2: 0.0,
+            p_eps_l1: 0,
+            p_eps_l2: 0,
+            s_eps_l1: 0,
+            s_eps_l2: 0,
+            e_eps_l1_day: 0.0,
+            e_eps_l2_day: 0.0,
+            e_eps_l1_all: 0.0,
+            e_eps_l2_all: 0.0,
+            p_load: 722,
+            e_load_day: 6.7,
+            self_consumption: None,
+            self_sufficiency: Some(52.2),
+            battery_efficiency: Some(93.2),
+            inverter_efficiency: Some(90.0),
+            time_to_empty: None,
+            time_to_full: None,
             time: UnixTime::now(),
             datalog: Serial::from_str("1234567890").unwrap(),
         }
@@ -245,17 +272,8 @@ pub fn unwrap_inverter_channeldata_packet(i: lxp::inverter::ChannelData) -> lxp:
     panic!()
 }
 
-pub fn unwrap_influx_channeldata_input_data(i: influx::ChannelData) -> serde_json::Value {
-    if let influx::ChannelData::InputData(i) = i {
-        return i;
-    }
-    panic!()
-}
-
-pub fn unwrap_database_channeldata_read_input_all(
-    i: database::ChannelData,
-) -> lxp::packet::ReadInputAll {
-    if let database::ChannelData::ReadInputAll(i) = i {
+pub fn unwrap_output_channeldata_input_all(i: output::ChannelData) -> lxp::packet::ReadInputAll {
+    if let output::ChannelData::Data(output::OutputData::InputAll(i)) = i {
         return *i;
     }
     panic!()
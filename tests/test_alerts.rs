@@ -0,0 +1,143 @@
+mod common;
+use common::*;
+
+use alerts::{AlertState, Condition, Rules};
+use chrono::{Duration, TimeZone, Utc};
+
+fn config() -> config::Alerts {
+    config::Alerts {
+        enabled: true,
+        debounce: Some(60),
+        offline_minutes: Some(10),
+        soc_below: Some(20),
+        faults: None,
+        warnings: None,
+        cell_imbalance: None,
+        grid_loss: None,
+        capacity_fade: None,
+        sinks: Vec::new(),
+    }
+}
+
+#[test]
+fn debounces_and_resolves() {
+    common_setup();
+
+    let datalog = Factory::inverter().datalog();
+    let t0 = Utc.timestamp_opt(1646370367, 0).unwrap();
+    let mut rules = Rules::new(config());
+
+    rules.observe(datalog, Condition::LowSoc, true, t0);
+    assert!(rules.evaluate(t0 + Duration::seconds(30)).is_empty());
+
+    let alerts = rules.evaluate(t0 + Duration::seconds(60));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].condition, "low_soc");
+    assert_eq!(alerts[0].state, AlertState::Firing);
+    assert_eq!(alerts[0].message, "Battery SOC low");
+
+    // flapping back and forth inside the debounce time says nothing
+    let t1 = t0 + Duration::seconds(120);
+    rules.observe(datalog, Condition::LowSoc, false, t1);
+    rules.observe(datalog, Condition::LowSoc, true, t1 + Duration::seconds(10));
+    assert!(rules.evaluate(t1 + Duration::seconds(70)).is_empty());
+
+    let t2 = t1 + Duration::seconds(100);
+    rules.observe(datalog, Condition::LowSoc, false, t2);
+    let alerts = rules.evaluate(t2 + Duration::seconds(60));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].state, AlertState::Resolved);
+}
+
+#[test]
+fn offline_after_minutes() {
+    common_setup();
+
+    let datalog = Factory::inverter().datalog();
+    let t0 = Utc.timestamp_opt(1646370367, 0).unwrap();
+    let mut rules = Rules::new(config());
+
+    rules.disconnected(datalog, t0);
+    rules.disconnected(datalog, t0 + Duration::minutes(5)); // reconnect attempt failed
+    assert!(rules.evaluate(t0 + Duration::minutes(5)).is_empty());
+
+    let alerts = rules.evaluate(t0 + Duration::minutes(10));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].condition, "offline");
+    assert_eq!(alerts[0].state, AlertState::Firing);
+
+    let t1 = t0 + Duration::minutes(20);
+    rules.connected(datalog, t1);
+    let alerts = rules.evaluate(t1 + Duration::seconds(60));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].state, AlertState::Resolved);
+}
+
+#[test]
+fn offline_if_never_connected() {
+    common_setup();
+
+    let datalog = Factory::inverter().datalog();
+    let t0 = Utc.timestamp_opt(1646370367, 0).unwrap();
+    let mut rules = Rules::new(config());
+
+    rules.expect([datalog], t0);
+    assert!(rules.evaluate(t0 + Duration::minutes(5)).is_empty());
+
+    let alerts = rules.evaluate(t0 + Duration::minutes(10));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].condition, "offline");
+
+    // whereas one that connects in time says nothing
+    let mut rules = Rules::new(config());
+    rules.expect([datalog], t0);
+    rules.connected(datalog, t0 + Duration::minutes(1));
+    assert!(rules.evaluate(t0 + Duration::minutes(10)).is_empty());
+}
+
+#[test]
+fn disabled_conditions() {
+    common_setup();
+
+    let datalog = Factory::inverter().datalog();
+    let t0 = Utc.timestamp_opt(1646370367, 0).unwrap();
+    let mut rules = Rules::new(config::Alerts {
+        faults: Some(false),
+        ..config()
+    });
+
+    rules.observe(datalog, Condition::Fault(1), true, t0);
+    rules.observe(datalog, Condition::Warning(16), true, t0);
+
+    let alerts = rules.evaluate(t0 + Duration::seconds(60));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].condition, "warning_16");
+    assert_eq!(alerts[0].message, "W016: Grid power outage");
+}
+
+#[test]
+fn capacity_fade() {
+    common_setup();
+
+    let datalog = Factory::inverter().datalog();
+    let t0 = Utc.timestamp_opt(1646370367, 0).unwrap();
+    let mut rules = Rules::new(config());
+
+    let stats = battery::Stats {
+        datalog,
+        soh: 100,
+        cycle_count: 200,
+        equivalent_cycles: 195.6,
+        rated_capacity: 5.12,
+        expected_capacity: 5.09,
+        estimated_capacity: Some(4.27),
+        fading: true,
+        time: UnixTime(t0),
+    };
+    rules.observe_battery(&stats, t0);
+
+    let alerts = rules.evaluate(t0 + Duration::seconds(60));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].condition, "capacity_fade");
+    assert_eq!(alerts[0].state, AlertState::Firing);
+}
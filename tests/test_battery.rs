@@ -0,0 +1,119 @@
+mod common;
+use common::*;
+
+use battery::Tracker;
+use chrono::{Duration, TimeZone, Utc};
+
+fn config() -> config::Battery {
+    config::Battery {
+        enabled: true,
+        nominal_voltage: None,
+        full_soc: None,
+        empty_soc: None,
+        expected_fade: None,
+        fade_tolerance: None,
+    }
+}
+
+// where it's 2022-03-04 00:06:07 at t0
+fn inverter() -> config::Inverter {
+    config::Inverter {
+        timezone: Some(chrono_tz::America::New_York),
+        ..Factory::inverter()
+    }
+}
+
+// 100Ah at 51.2V is a 5.12kWh battery
+fn input(soc: i8, e_chg_all: f64, e_dischg_all: f64, minutes: i64) -> lxp::packet::ReadInputAll {
+    let t0 = Utc.timestamp_opt(1646370367, 0).unwrap(); // 2022-03-04 05:06:07
+    lxp::packet::ReadInputAll {
+        soc,
+        e_chg_all,
+        e_dischg_all,
+        bat_capacity: 100,
+        time: UnixTime(t0 + Duration::minutes(minutes)),
+        ..Factory::read_input_all()
+    }
+}
+
+#[test]
+fn daily_stats() {
+    common_setup();
+
+    let mut tracker = Tracker::default();
+
+    let stats = tracker
+        .update(&config(), &inverter(), &input(50, 1024.0, 1024.0, 0))
+        .unwrap();
+    assert_eq!(stats.rated_capacity, 5.12);
+    assert_eq!(stats.equivalent_cycles, 200.0);
+    // 3% per 1000 cycles
+    assert_eq!(stats.expected_capacity, 5.09);
+    assert_eq!(stats.estimated_capacity, None);
+    assert!(!stats.fading);
+
+    // once a day is plenty
+    assert!(tracker
+        .update(&config(), &inverter(), &input(50, 1024.0, 1024.0, 60))
+        .is_none());
+    assert!(tracker
+        .update(&config(), &inverter(), &input(50, 1024.0, 1024.0, 24 * 60))
+        .is_some());
+
+    // the day is the inverter's, not UTC's
+    let mut tracker = Tracker::default();
+    assert!(tracker
+        .update(&config(), &inverter(), &input(50, 1024.0, 1024.0, -10))
+        .is_some());
+    assert!(tracker
+        .update(&config(), &inverter(), &input(50, 1024.0, 1024.0, 0))
+        .is_some());
+
+    // no BMS, no rated capacity
+    let mut tracker = Tracker::default();
+    let no_bms = lxp::packet::ReadInputAll {
+        bat_capacity: 0,
+        ..input(50, 1024.0, 1024.0, 0)
+    };
+    assert!(tracker.update(&config(), &inverter(), &no_bms).is_none());
+}
+
+#[test]
+fn estimates_capacity() {
+    common_setup();
+
+    let mut tracker = Tracker::default();
+    tracker.update(&config(), &inverter(), &input(100, 1000.0, 1000.0, 0));
+    // the run starts from the last time it was full
+    tracker.update(&config(), &inverter(), &input(96, 1000.0, 1000.2, 10));
+    assert!(tracker
+        .update(&config(), &inverter(), &input(60, 1000.0, 1001.8, 20))
+        .is_none());
+
+    // 4.4kWh out, 0.4kWh of it from solar topping it up along the way, for 80%
+    let stats = tracker
+        .update(&config(), &inverter(), &input(16, 1000.4, 1004.6, 30))
+        .unwrap();
+    assert_eq!(stats.estimated_capacity, Some(5.0));
+    assert!(!stats.fading);
+
+    // a second run without getting full again doesn't count
+    assert!(tracker
+        .update(&config(), &inverter(), &input(10, 1000.4, 1005.0, 40))
+        .is_none());
+}
+
+#[test]
+fn fading() {
+    common_setup();
+
+    let mut tracker = Tracker::default();
+    tracker.update(&config(), &inverter(), &input(95, 1000.0, 1000.0, 0));
+
+    // 3.2kWh for 75% is 4.27kWh, well under the expected 5.09kWh
+    let stats = tracker
+        .update(&config(), &inverter(), &input(20, 1000.0, 1003.2, 30))
+        .unwrap();
+    assert_eq!(stats.estimated_capacity, Some(4.27));
+    assert!(stats.fading);
+}
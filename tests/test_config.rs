@@ -63,6 +63,109 @@ fn mqtt_defaults() {
     assert!(mqtt.enabled());
     assert_eq!(mqtt.port(), 1883);
     assert_eq!(mqtt.namespace(), "lxp");
+    assert!(mqtt.client_id().starts_with("lxp-bridge-"));
+    assert_eq!(mqtt.websockets(), false);
+    assert_eq!(mqtt.websocket_path(), "/mqtt");
+    assert_eq!(mqtt.tls().enabled(), false);
+    assert_eq!(mqtt.v5(), false);
+    assert_eq!(mqtt.message_expiry_interval(), 300);
+    assert_eq!(mqtt.publish_named_inputs(), false);
+}
+
+#[test]
+fn mqtt_client_id() {
+    let input = json!({ "host": "host", "client_id": "bridge-2" });
+    let mqtt: config::Mqtt = serde_json::from_value(input).unwrap();
+    assert_eq!(mqtt.client_id(), "bridge-2");
+}
+
+#[test]
+fn mqtt_tls() {
+    let input = json!({ "host": "host", "tls": { "ca": "/ca.pem", "client_cert": "/cert.pem", "client_key": "/key.pem" } });
+    let mqtt: config::Mqtt = serde_json::from_value(input).unwrap();
+    assert!(mqtt.tls().enabled());
+    assert_eq!(mqtt.tls().ca(), &Some("/ca.pem".to_string()));
+    assert_eq!(mqtt.tls().client_cert(), &Some("/cert.pem".to_string()));
+    assert_eq!(mqtt.tls().client_key(), &Some("/key.pem".to_string()));
+    assert_eq!(mqtt.tls().insecure_skip_verify(), false);
+
+    let input =
+        json!({ "host": "host", "tls": { "enabled": false, "insecure_skip_verify": true } });
+    let mqtt: config::Mqtt = serde_json::from_value(input).unwrap();
+    assert_eq!(mqtt.tls().enabled(), false);
+    assert!(mqtt.tls().insecure_skip_verify());
+}
+
+#[test]
+fn mqtt_v5() {
+    let input = json!({ "host": "host", "v5": true, "message_expiry_interval": 60 });
+    let mqtt: config::Mqtt = serde_json::from_value(input).unwrap();
+    assert!(mqtt.v5());
+    assert_eq!(mqtt.message_expiry_interval(), 60);
+}
+
+#[test]
+fn mqtt_topics() {
+    let input = json!({ "host": "host" });
+    let mqtt: config::Mqtt = serde_json::from_value(input).unwrap();
+    assert_eq!(mqtt.site(), "default");
+    assert_eq!(mqtt.topics().commands().qos(), None);
+    assert_eq!(
+        mqtt.topics().for_class(mqtt::TopicClass::Inputs).retain(),
+        None
+    );
+    assert_eq!(
+        mqtt.topics().for_class(mqtt::TopicClass::Inputs).template(),
+        None
+    );
+
+    let input = json!({ "host": "host", "site": "home", "topics": {
+        "inputs": { "qos": 0, "retain": true, "template": "{namespace}/{site}/{datalog}/inputs/{rest}" },
+        "commands": { "qos": 2 }
+    }});
+    let mqtt: config::Mqtt = serde_json::from_value(input).unwrap();
+    assert_eq!(mqtt.site(), "home");
+    assert_eq!(mqtt.topics().commands().qos(), Some(2));
+    let inputs = mqtt.topics().for_class(mqtt::TopicClass::Inputs);
+    assert_eq!(inputs.qos(), Some(0));
+    assert_eq!(inputs.retain(), Some(true));
+    assert_eq!(
+        inputs.template(),
+        Some("{namespace}/{site}/{datalog}/inputs/{rest}")
+    );
+
+    let input = json!({ "host": "host", "topics": { "hold": { "qos": 3 } } });
+    assert!(serde_json::from_value::<config::Mqtt>(input).is_err());
+}
+
+#[test]
+fn alerts() {
+    let input = json!({});
+    let alerts: config::Alerts = serde_json::from_value(input).unwrap();
+    assert!(alerts.enabled());
+    assert_eq!(alerts.debounce(), 60);
+    assert_eq!(alerts.offline_minutes(), 10);
+    assert_eq!(alerts.soc_below(), None);
+    assert!(alerts.faults());
+    assert!(alerts.sinks().is_empty());
+
+    let input = json!({ "soc_below": 20, "faults": false, "sinks": [
+        { "type": "mqtt" },
+        { "type": "ntfy", "url": "https://ntfy.sh/lxp" }
+    ]});
+    let alerts: config::Alerts = serde_json::from_value(input).unwrap();
+    assert_eq!(alerts.soc_below(), Some(20));
+    assert!(!alerts.faults());
+    assert_eq!(
+        alerts.sinks(),
+        &vec![
+            config::AlertSink::Mqtt,
+            config::AlertSink::Ntfy {
+                url: "https://ntfy.sh/lxp".to_owned(),
+                token: None
+            }
+        ]
+    );
 }
 
 #[test]
@@ -87,6 +190,10 @@ fn enabled_inverters() {
             heartbeats: None,
             publish_holdings_on_connect: None,
             read_timeout: None,
+            timezone: None,
+            timesync_on_connect: None,
+            timesync_tolerance: None,
+            generator: None,
         },
         config::Inverter {
             enabled: true,
@@ -97,6 +204,10 @@ fn enabled_inverters() {
             heartbeats: None,
             publish_holdings_on_connect: None,
             read_timeout: None,
+            timezone: None,
+            timesync_on_connect: None,
+            timesync_tolerance: None,
+            generator: None,
         },
     ]);
 
@@ -117,6 +228,10 @@ fn inverters_for_message() {
             heartbeats: None,
             publish_holdings_on_connect: None,
             read_timeout: None,
+            timezone: None,
+            timesync_on_connect: None,
+            timesync_tolerance: None,
+            generator: None,
         },
         config::Inverter {
             enabled: false,
@@ -127,6 +242,10 @@ fn inverters_for_message() {
             heartbeats: None,
             publish_holdings_on_connect: None,
             read_timeout: None,
+            timezone: None,
+            timesync_on_connect: None,
+            timesync_tolerance: None,
+            generator: None,
         },
     ]);
 
@@ -158,6 +277,43 @@ fn inverters_for_message() {
     assert_eq!(r.len(), 1);
 }
 
+#[test]
+fn inverters_for_message_to_system() {
+    let config = Factory::example_config_wrapped();
+
+    let other = config::Inverter {
+        datalog: Serial::from_str("3333333333").unwrap(),
+        ..Factory::inverter()
+    };
+    config.set_inverters(vec![Factory::inverter(), other.clone()]);
+    config.set_systems(vec![config::System {
+        enabled: true,
+        name: "garage".to_owned(),
+        datalog: Serial::from_str("SYSTEM0001").unwrap(),
+        inverters: vec![Factory::inverter().datalog(), other.datalog()],
+    }]);
+
+    let message = mqtt::Message {
+        topic: "cmd/SYSTEM0001/foo".to_string(),
+        retain: false,
+        payload: "foo".to_string(),
+    };
+
+    let r = config.inverters_for_message(&message).unwrap();
+    assert_eq!(r.len(), 2);
+
+    // members can still be addressed individually
+    let message = mqtt::Message {
+        topic: "cmd/3333333333/foo".to_string(),
+        retain: false,
+        payload: "foo".to_string(),
+    };
+
+    let r = config.inverters_for_message(&message).unwrap();
+    assert_eq!(r.len(), 1);
+    assert_eq!(r[0].datalog(), other.datalog());
+}
+
 #[test]
 fn enabled_databases() {
     let config = Factory::example_config_wrapped();
@@ -175,3 +331,34 @@ fn enabled_databases() {
 
     assert_eq!(config.enabled_databases().len(), 1);
 }
+
+#[test]
+fn scheduler_jobs() {
+    let input = json!({
+        "enabled": true,
+        "jobs": [
+            { "name": "discharge", "cron": "0 17 * * *", "command": "set/forced_discharge", "payload": "ON", "inverters": ["2222222222"] },
+            { "name": "timesync", "cron": "0 0 * * *", "command": "set/timesync" }
+        ]
+    });
+    let scheduler: config::Scheduler = serde_json::from_value(input).unwrap();
+
+    let jobs = scheduler.jobs();
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(jobs[0].payload(), "ON");
+    assert_eq!(jobs[0].inverters(), &vec![Factory::inverter().datalog()]);
+    assert_eq!(jobs[1].payload(), "");
+    assert!(jobs[1].inverters().is_empty());
+}
+
+#[test]
+fn inverter_timezone() {
+    let input = json!({ "host": "host", "port": 8000, "serial": "TESTSERIAL", "datalog": "TESTDATALO", "timezone": "Europe/London" });
+    let inverter: config::Inverter = serde_json::from_value(input).unwrap();
+    assert_eq!(inverter.timezone(), Some(chrono_tz::Europe::London));
+    assert!(inverter.timesync_on_connect());
+    assert_eq!(inverter.timesync_tolerance(), 120);
+
+    let input = json!({ "host": "host", "port": 8000, "serial": "TESTSERIAL", "datalog": "TESTDATALO", "timezone": "Mars/Olympus_Mons" });
+    assert!(serde_json::from_value::<config::Inverter>(input).is_err());
+}
@@ -17,9 +17,8 @@ async fn publishes_read_hold_mqtt() {
     let coordinator = Coordinator::new(config, channels.clone());
 
     let tf = async {
-        let mut to_influx = channels.to_influx.subscribe();
+        let mut to_outputs = channels.to_outputs.subscribe();
         let mut to_mqtt = channels.to_mqtt.subscribe();
-        let mut to_db = channels.to_database.subscribe();
         let mut to_register_cache = channels.to_register_cache.subscribe();
 
         // simulate ReadHold in from inverter
@@ -35,10 +34,12 @@ async fn publishes_read_hold_mqtt() {
             .send(lxp::inverter::ChannelData::Packet(packet.clone()))?;
 
         // verify register_cache is set
-        let register_cache::ChannelData::RegisterData(a, b) = to_register_cache.recv().await?
+        let register_cache::ChannelData::RegisterData(datalog, a, b) =
+            to_register_cache.recv().await?
         else {
             unreachable!()
         };
+        assert_eq!(datalog, inverter.datalog());
         assert_eq!(a, 12);
         assert_eq!(b, 1558);
 
@@ -52,8 +53,7 @@ async fn publishes_read_hold_mqtt() {
             })
         );
         // verify nothing sent to influx or database
-        assert_eq!(to_influx.try_recv(), Err(TryRecvError::Empty));
-        assert_eq!(to_db.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(to_outputs.try_recv(), Err(TryRecvError::Empty));
 
         coordinator.stop();
 
@@ -78,9 +78,8 @@ async fn handles_read_input_all() {
     let coordinator = Coordinator::new(config, channels.clone());
 
     let tf = async {
-        let mut to_influx = channels.to_influx.subscribe();
+        let mut to_outputs = channels.to_outputs.subscribe();
         let mut to_mqtt = channels.to_mqtt.subscribe();
-        let mut to_database = channels.to_database.subscribe();
 
         // simulate ReadHold in from inverter
         let packet = Packet::TranslatedData(lxp::packet::TranslatedData {
@@ -105,10 +104,7 @@ async fn handles_read_input_all() {
         );
 
         // verify influx and database output
-        let d = unwrap_influx_channeldata_input_data(to_influx.recv().await?);
-        assert_eq!(d["soc"], 1);
-        assert_eq!(d["v_pv_1"], 25.7);
-        let d = unwrap_database_channeldata_read_input_all(to_database.recv().await?);
+        let d = unwrap_output_channeldata_input_all(to_outputs.recv().await?);
         assert_eq!(d.soc, 1);
         assert_eq!(d.v_pv_1, 25.7);
 
@@ -120,6 +116,73 @@ async fn handles_read_input_all() {
     futures::try_join!(coordinator.start(), tf).unwrap();
 }
 
+#[tokio::test]
+async fn sends_battery_stats_to_alerts_without_outputs() {
+    common_setup();
+
+    // no outputs, just alerts watching the battery
+    let config = Factory::example_config_wrapped();
+    config.influx_mut().enabled = false;
+    config.databases_mut()[0].enabled = false;
+    *config.battery_mut() = Some(config::Battery {
+        enabled: true,
+        nominal_voltage: None,
+        full_soc: None,
+        empty_soc: None,
+        expected_fade: None,
+        fade_tolerance: None,
+    });
+    *config.alerts_mut() = Some(config::Alerts {
+        enabled: true,
+        debounce: None,
+        offline_minutes: None,
+        soc_below: None,
+        faults: None,
+        warnings: None,
+        cell_imbalance: None,
+        grid_loss: None,
+        capacity_fade: Some(true),
+        sinks: Vec::new(),
+    });
+    let inverter = config.inverters()[0].clone();
+
+    let channels = Channels::new();
+
+    let coordinator = Coordinator::new(config, channels.clone());
+
+    let tf = async {
+        let mut to_outputs = channels.to_outputs.subscribe();
+        let _to_mqtt = channels.to_mqtt.subscribe();
+
+        for register in [0, 40, 80, 120] {
+            let packet = Packet::TranslatedData(lxp::packet::TranslatedData {
+                datalog: inverter.datalog(),
+                device_function: lxp::packet::DeviceFunction::ReadInput,
+                inverter: inverter.serial(),
+                register,
+                values: vec![1; 80],
+            });
+            channels
+                .from_inverter
+                .send(lxp::inverter::ChannelData::Packet(packet))?;
+        }
+
+        // the inputs themselves have nowhere to go, but the battery stats do
+        match to_outputs.recv().await? {
+            output::ChannelData::Data(output::OutputData::Battery(stats)) => {
+                assert_eq!(stats.datalog, inverter.datalog())
+            }
+            other => panic!("expected battery stats, got {:?}", other),
+        }
+
+        coordinator.stop();
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), tf).unwrap();
+}
+
 #[tokio::test]
 async fn complete_path_read_hold_command() {
     common_setup();
@@ -194,10 +257,12 @@ async fn complete_path_read_hold_command() {
         );
 
         // verify register_cache is set
-        let register_cache::ChannelData::RegisterData(a, b) = to_register_cache.recv().await?
+        let register_cache::ChannelData::RegisterData(datalog, a, b) =
+            to_register_cache.recv().await?
         else {
             unreachable!()
         };
+        assert_eq!(datalog, inverter.datalog());
         assert_eq!(a, 12);
         assert_eq!(b, 1558);
 
@@ -208,3 +273,384 @@ async fn complete_path_read_hold_command() {
 
     futures::try_join!(coordinator.start(), tf).unwrap();
 }
+
+#[tokio::test]
+async fn command_with_response_target() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+
+    let inverter = config.inverters()[0].clone();
+
+    let channels = Channels::new();
+
+    let coordinator = Coordinator::new(config, channels.clone());
+
+    let tf = async {
+        let mut to_inverter = channels.to_inverter.subscribe();
+        let mut to_mqtt = channels.to_mqtt.subscribe();
+        // nothing reads the cache here, but the coordinator needs a receiver to send to
+        let _to_register_cache = channels.to_register_cache.subscribe();
+
+        // simulate a v5 request which asked for a response
+        let message = mqtt::Message {
+            topic: "cmd/all/read/hold/12".to_owned(),
+            retain: false,
+            payload: "".to_owned(),
+        };
+        let target = mqtt::ResponseTarget {
+            topic: "client/1/reply".to_owned(),
+            correlation_data: Some(vec![1, 2, 3]),
+        };
+        channels
+            .from_mqtt
+            .send(mqtt::ChannelData::Request(message, target.clone()))
+            .unwrap();
+
+        to_inverter.recv().await?;
+        let reply = Packet::TranslatedData(lxp::packet::TranslatedData {
+            datalog: inverter.datalog(),
+            device_function: lxp::packet::DeviceFunction::ReadHold,
+            inverter: inverter.serial(),
+            register: 12,
+            values: vec![22, 6],
+        });
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Packet(reply))
+            .unwrap();
+
+        let result = mqtt::Message {
+            topic: "result/2222222222/read/hold/12".to_owned(),
+            retain: false,
+            payload: "OK".to_owned(),
+        };
+
+        // skip the hold/12 message
+        to_mqtt.recv().await?;
+        assert_eq!(
+            to_mqtt.recv().await?,
+            mqtt::ChannelData::Response(result.clone(), target)
+        );
+        assert_eq!(to_mqtt.recv().await?, mqtt::ChannelData::Message(result));
+
+        coordinator.stop();
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), tf).unwrap();
+}
+
+#[tokio::test]
+#[cfg_attr(not(feature = "mocks"), ignore)]
+async fn home_assistant_online_sends_discovery() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+
+    let channels = Channels::new();
+
+    let coordinator = Coordinator::new(config, channels.clone());
+
+    let tf = async {
+        let mut to_mqtt = channels.to_mqtt.subscribe();
+        let mut to_inverter = channels.to_inverter.subscribe();
+
+        channels
+            .from_mqtt
+            .send(mqtt::ChannelData::HomeAssistantOnline)
+            .unwrap();
+
+        // first discovery message is for the enabled inverter
+        let mqtt::ChannelData::HomeAssistant(message) = to_mqtt.recv().await? else {
+            unreachable!()
+        };
+        assert_eq!(
+            message.topic,
+            "homeassistant/switch/lxp_2222222222/ac_charge/config"
+        );
+        assert!(message.retain);
+
+        // and inputs are read so HA has current state
+        let packet = unwrap_inverter_channeldata_packet(to_inverter.recv().await?);
+        assert_eq!(packet.datalog(), Serial::from_str("2222222222").unwrap());
+
+        coordinator.stop();
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), tf).unwrap();
+}
+
+#[tokio::test]
+async fn publishes_inverter_availability() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+    let inverter = config.inverters()[0].clone();
+
+    let channels = Channels::new();
+
+    let coordinator = Coordinator::new(config, channels.clone());
+
+    let tf = async {
+        let mut to_mqtt = channels.to_mqtt.subscribe();
+
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Connected(inverter.datalog()))?;
+        assert_eq!(
+            to_mqtt.recv().await?,
+            mqtt::ChannelData::Message(mqtt::Message {
+                topic: "2222222222/availability".to_owned(),
+                retain: true,
+                payload: "online".to_owned()
+            })
+        );
+
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Disconnect(inverter.datalog()))?;
+        assert_eq!(
+            to_mqtt.recv().await?,
+            mqtt::ChannelData::Message(mqtt::Message {
+                topic: "2222222222/availability".to_owned(),
+                retain: true,
+                payload: "offline".to_owned()
+            })
+        );
+
+        coordinator.stop();
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), tf).unwrap();
+}
+
+#[tokio::test]
+async fn skips_unchanged_hold_writes() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+    let inverter = config.inverters()[0].clone();
+
+    let channels = Channels::new();
+
+    let coordinator = Coordinator::new(config, channels.clone());
+    let register_cache = RegisterCache::new(channels.clone());
+
+    let tf = async {
+        let mut to_inverter = channels.to_inverter.subscribe();
+        let mut to_mqtt = channels.to_mqtt.subscribe();
+        let _to_register_cache = channels.to_register_cache.subscribe();
+
+        // we learn what register 64 holds
+        let packet = Packet::TranslatedData(lxp::packet::TranslatedData {
+            datalog: inverter.datalog(),
+            device_function: lxp::packet::DeviceFunction::ReadHold,
+            inverter: inverter.serial(),
+            register: 64,
+            values: vec![80, 0],
+        });
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Packet(packet))?;
+        loop {
+            if let mqtt::ChannelData::Message(message) = to_mqtt.recv().await? {
+                if message.topic == "2222222222/hold/64" {
+                    break;
+                }
+            }
+        }
+
+        let message = mqtt::Message {
+            topic: "cmd/2222222222/set/hold/64".to_owned(),
+            retain: false,
+            payload: "80".to_owned(),
+        };
+        channels
+            .from_mqtt
+            .send(mqtt::ChannelData::Message(message))?;
+
+        assert_eq!(
+            to_mqtt.recv().await?,
+            mqtt::ChannelData::Message(mqtt::Message {
+                topic: "result/2222222222/set/hold/64".to_owned(),
+                retain: false,
+                payload: "OK".to_owned(),
+            })
+        );
+        // and nothing was written
+        assert!(to_inverter.try_recv().is_err());
+
+        coordinator.stop();
+        let _ = channels
+            .read_register_cache
+            .send(register_cache::ChannelData::Shutdown);
+        let _ = channels
+            .to_register_cache
+            .send(register_cache::ChannelData::Shutdown);
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), register_cache.start(), tf).unwrap();
+}
+
+#[tokio::test]
+async fn debounced_write_replies_once_written() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+    config.hold_writes_mut().debounce = Some(1);
+    let inverter = config.inverters()[0].clone();
+
+    let channels = Channels::new();
+
+    let coordinator = Coordinator::new(config, channels.clone());
+
+    let tf = async {
+        let mut to_inverter = channels.to_inverter.subscribe();
+        let mut to_mqtt = channels.to_mqtt.subscribe();
+        let _to_register_cache = channels.to_register_cache.subscribe();
+
+        let message = mqtt::Message {
+            topic: "cmd/2222222222/set/hold/64".to_owned(),
+            retain: false,
+            payload: "50".to_owned(),
+        };
+        channels
+            .from_mqtt
+            .send(mqtt::ChannelData::Message(message))?;
+
+        // no result until the write is actually made
+        let packet = unwrap_inverter_channeldata_packet(to_inverter.recv().await?);
+        assert_eq!(packet.register(), 64);
+        while let Ok(data) = to_mqtt.try_recv() {
+            if let mqtt::ChannelData::Message(message) = data {
+                assert!(!message.topic.starts_with("result/"));
+            }
+        }
+
+        let reply = Packet::TranslatedData(lxp::packet::TranslatedData {
+            datalog: inverter.datalog(),
+            device_function: lxp::packet::DeviceFunction::WriteSingle,
+            inverter: inverter.serial(),
+            register: 64,
+            values: vec![50, 0],
+        });
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Packet(reply))?;
+
+        loop {
+            if let mqtt::ChannelData::Message(message) = to_mqtt.recv().await? {
+                if message.topic == "result/2222222222/set/hold/64" {
+                    assert_eq!(message.payload, "OK");
+                    break;
+                }
+            }
+        }
+
+        coordinator.stop();
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), tf).unwrap();
+}
+
+#[tokio::test]
+async fn internal_writes_are_not_debounced() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+    config.hold_writes_mut().debounce = Some(60);
+    let inverter = config.inverters()[0].clone();
+
+    let channels = Channels::new();
+
+    let coordinator = Coordinator::new(config, channels.clone());
+
+    let tf = async {
+        let mut to_inverter = channels.to_inverter.subscribe();
+        let mut to_mqtt = channels.to_mqtt.subscribe();
+        let _to_register_cache = channels.to_register_cache.subscribe();
+
+        // as the scheduler or optimiser would send it
+        let message = mqtt::Message {
+            topic: "cmd/2222222222/set/hold/64".to_owned(),
+            retain: false,
+            payload: "50".to_owned(),
+        };
+        channels
+            .from_mqtt
+            .send(mqtt::ChannelData::Internal(message))?;
+
+        // written straight away rather than after the debounce
+        let data =
+            tokio::time::timeout(std::time::Duration::from_secs(5), to_inverter.recv()).await??;
+        let packet = unwrap_inverter_channeldata_packet(data);
+        assert_eq!(packet.register(), 64);
+
+        let reply = Packet::TranslatedData(lxp::packet::TranslatedData {
+            datalog: inverter.datalog(),
+            device_function: lxp::packet::DeviceFunction::WriteSingle,
+            inverter: inverter.serial(),
+            register: 64,
+            values: vec![50, 0],
+        });
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Packet(reply))?;
+
+        loop {
+            if let mqtt::ChannelData::Message(message) = to_mqtt.recv().await? {
+                if message.topic == "result/2222222222/set/hold/64" {
+                    assert_eq!(message.payload, "OK");
+                    break;
+                }
+            }
+        }
+
+        coordinator.stop();
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), tf).unwrap();
+}
+
+#[tokio::test]
+async fn publishes_offline_at_startup() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+
+    let channels = Channels::new();
+    let mut to_mqtt = channels.to_mqtt.subscribe();
+
+    let coordinator = Coordinator::new(config.clone(), channels.clone());
+
+    let tf = async {
+        for inverter in config.enabled_inverters() {
+            assert_eq!(
+                to_mqtt.recv().await?,
+                mqtt::ChannelData::Message(mqtt::Message {
+                    topic: format!("{}/availability", inverter.datalog()),
+                    retain: true,
+                    payload: "offline".to_owned(),
+                })
+            );
+        }
+
+        coordinator.stop();
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(coordinator.start(), tf).unwrap();
+}
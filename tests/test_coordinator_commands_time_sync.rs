@@ -118,3 +118,67 @@ async fn time_already_correct() {
 
     futures::try_join!(tf, sf).unwrap();
 }
+
+#[tokio::test]
+#[cfg_attr(not(feature = "mocks"), ignore)]
+async fn uses_inverter_timezone() {
+    common_setup();
+
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::Asia::Tokyo),
+        ..Factory::inverter()
+    };
+    let channels = Channels::new();
+
+    let subject =
+        coordinator::commands::timesync::TimeSync::new(channels.clone(), inverter.clone());
+
+    let sf = async {
+        // hardcoded test time is 05:06:07 UTC, 14:06:07 in Tokyo
+        assert_eq!(subject.run().await?, chrono::Duration::hours(-9));
+        Ok(())
+    };
+
+    let tf = async {
+        let mut to_inverter = channels.to_inverter.subscribe();
+        to_inverter.recv().await?;
+
+        // inverter still thinks it's in UTC
+        let inverter_time_packet = Packet::TranslatedData(lxp::packet::TranslatedData {
+            datalog: inverter.datalog(),
+            device_function: lxp::packet::DeviceFunction::ReadHold,
+            inverter: inverter.serial(),
+            register: 12,
+            values: vec![22, 3, 4, 5, 6, 7],
+        });
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Packet(inverter_time_packet))?;
+
+        assert_eq!(
+            unwrap_inverter_channeldata_packet(to_inverter.recv().await?),
+            Packet::TranslatedData(lxp::packet::TranslatedData {
+                datalog: inverter.datalog(),
+                device_function: lxp::packet::DeviceFunction::WriteMulti,
+                inverter: inverter.serial(),
+                register: 12,
+                values: vec![22, 3, 4, 14, 6, 7]
+            })
+        );
+
+        let inverter_ok_packet = Packet::TranslatedData(lxp::packet::TranslatedData {
+            datalog: inverter.datalog(),
+            device_function: lxp::packet::DeviceFunction::WriteMulti,
+            inverter: inverter.serial(),
+            register: 12,
+            values: vec![3, 0],
+        });
+        channels
+            .from_inverter
+            .send(lxp::inverter::ChannelData::Packet(inverter_ok_packet))?;
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    futures::try_join!(tf, sf).unwrap();
+}
@@ -28,24 +28,13 @@ async fn sqlite_insertion() {
         enabled: true,
         url: "sqlite::memory:".to_string(),
     };
-    let channels = Channels::new();
-
-    let database = Database::new(config, channels.clone());
+    let database = Database::new(config);
 
     let tf = async {
-        let channel_data = database::ChannelData::ReadInputAll(Box::new(Factory::read_input_all()));
-
-        let mut retries = 0;
-        // wait up to 5 seconds for database to be ready and accepting messages
-        while channels.to_database.send(channel_data.clone()).is_err() {
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-            retries = retries + 1;
-            if retries > 50 {
-                panic!("database not ready for messages");
-            }
-        }
+        database.connect().await?;
 
-        database.stop();
+        let data = output::OutputData::InputAll(Box::new(Factory::read_input_all()));
+        database.write(&[data]).await?;
 
         let mut conn = database.connection().await?;
 
@@ -67,6 +56,8 @@ async fn sqlite_insertion() {
                 assert_u16_eq(row.get("bms_fw_update_state"), ria.bms_fw_update_state);
                 assert_u16_eq(row.get("cycle_count"), ria.cycle_count);
                 assert_f64_eq(row.get("vbat_inv"), ria.vbat_inv);
+                assert_str_eq(row.get("faults"), r#"["E000","E002"]"#); // fault_code 5
+                assert_str_eq(row.get("warnings"), r#"["W000","W001"]"#); // warning_code 3
                 assert_str_eq(row.get("datalog"), "1234567890");
                 break;
             }
@@ -82,5 +73,47 @@ async fn sqlite_insertion() {
         Ok::<(), anyhow::Error>(())
     };
 
-    futures::try_join!(database.start(), tf).unwrap();
+    tf.await.unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_battery_history() {
+    common_setup();
+
+    let config = config::Database {
+        enabled: true,
+        url: "sqlite::memory:".to_string(),
+    };
+    let database = Database::new(config);
+
+    let tf = async {
+        database.connect().await?;
+
+        let stats = battery::Stats {
+            datalog: Factory::inverter().datalog(),
+            soh: 98,
+            cycle_count: 200,
+            equivalent_cycles: 195.6,
+            rated_capacity: 5.12,
+            expected_capacity: 5.09,
+            estimated_capacity: None,
+            fading: false,
+            time: UnixTime::now(),
+        };
+        database
+            .write(&[output::OutputData::Battery(stats)])
+            .await?;
+
+        let mut conn = database.connection().await?;
+
+        let mut rows = sqlx::query("SELECT * FROM battery_history").fetch(&mut conn);
+        let row = rows.try_next().await?.expect("row not inserted");
+        assert_i16_eq(row.get("soh"), 98);
+        assert_f64_eq(row.get("equivalent_cycles"), 195.6);
+        assert_str_eq(row.get("datalog"), "2222222222");
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    tf.await.unwrap();
 }
@@ -0,0 +1,75 @@
+mod common;
+use common::*;
+
+#[test]
+fn factory_input() {
+    common_setup();
+
+    let input = Factory::read_input_all();
+    assert_eq!(derived::apply(input.clone()), input);
+}
+
+#[test]
+fn solar_day() {
+    common_setup();
+
+    let input = derived::apply(lxp::packet::ReadInputAll {
+        p_pv: 3000,
+        p_inv: 1800,
+        p_charge: 1000,
+        p_discharge: 0,
+        p_to_grid: 300,
+        p_to_user: 0,
+        p_eps: 0,
+        p_rec: 0,
+        e_pv_day: 10.0,
+        e_inv_day: 8.0,
+        e_rec_day: 0.0,
+        e_eps_day: 0.0,
+        e_to_grid_day: 2.5,
+        e_to_user_day: 1.5,
+        soc: 60,
+        v_bat: 50.0,
+        bat_capacity: 100,
+        ..Factory::read_input_all()
+    });
+
+    assert_eq!(input.p_load, 1500);
+    assert_eq!(input.e_load_day, 7.0);
+    assert_eq!(input.self_consumption, Some(75.0));
+    assert_eq!(input.self_sufficiency, Some(78.6));
+    assert_eq!(input.inverter_efficiency, Some(93.3));
+    // 5kWh battery, 2kWh to go at 1kW
+    assert_eq!(input.time_to_full, Some(120));
+    assert_eq!(input.time_to_empty, None);
+}
+
+#[test]
+fn nothing_to_show() {
+    common_setup();
+
+    let input = derived::apply(lxp::packet::ReadInputAll {
+        p_pv: 0,
+        p_inv: 40,
+        p_charge: 0,
+        p_discharge: 60,
+        p_rec: 0,
+        e_pv_day: 0.0,
+        e_inv_day: 0.0,
+        e_rec_day: 0.0,
+        e_eps_day: 0.0,
+        e_to_grid_day: 0.0,
+        e_to_user_day: 0.0,
+        e_chg_all: 5.0,
+        bat_capacity: 0,
+        ..Factory::read_input_all()
+    });
+
+    assert_eq!(input.self_consumption, None);
+    assert_eq!(input.self_sufficiency, None);
+    assert_eq!(input.battery_efficiency, None);
+    // too little going through it to say
+    assert_eq!(input.inverter_efficiency, None);
+    // no BMS to give us a capacity
+    assert_eq!(input.time_to_empty, None);
+}
@@ -0,0 +1,143 @@
+mod common;
+use common::*;
+
+#[test]
+fn totals_need_seeding() {
+    common_setup();
+
+    let mut meter = energy::Meter::default();
+    meter.update_input_1(&Factory::read_input_1());
+    assert_eq!(meter.totals(), None);
+
+    meter.update_input_2(&Factory::read_input_2());
+    assert_eq!(
+        meter.totals(),
+        Some(energy::Totals {
+            e_pv_total: 4215.8,
+            e_grid_import_total: 5889.8,
+            e_grid_export_total: 979.6,
+            e_chg_total: 4392.6,
+            e_dischg_total: 4092.7,
+            e_eps_total: 0.0,
+        })
+    );
+}
+
+#[test]
+fn totals_follow_daily_counters() {
+    common_setup();
+
+    let mut meter = energy::Meter::default();
+    let mut input = Factory::read_input_all();
+    meter.update_input_all(&input);
+
+    input.e_to_user_day = 3.7;
+    meter.update_input_all(&input);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.3);
+
+    // inverter midnight; daily counter resets but the total carries on
+    input.e_to_user_day = 0.1;
+    meter.update_input_all(&input);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.4);
+}
+
+#[test]
+fn totals_ignore_garbage() {
+    common_setup();
+
+    let mut meter = energy::Meter::default();
+    let mut input = Factory::read_input_all();
+    meter.update_input_all(&input);
+
+    // a corrupt reading, then back to normal
+    input.e_to_user_day = 6553.5;
+    meter.update_input_all(&input);
+    input.e_to_user_day = 3.3;
+    meter.update_input_all(&input);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5889.9);
+
+    // lifetime counter behind what we've counted; don't go backwards
+    input.e_to_user_all = 5889.8;
+    meter.update_input_all(&input);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5889.9);
+}
+
+#[test]
+fn totals_count_energy_once() {
+    common_setup();
+
+    let mut meter = energy::Meter::default();
+    let mut input = Factory::read_input_all();
+    meter.update_input_all(&input);
+
+    // both counters see the same 0.5kWh
+    input.e_to_user_all = 5890.3;
+    input.e_to_user_day = 3.7;
+    meter.update_input_all(&input);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.3);
+
+    // same again, but with the lifetime counter in its own packet first
+    let mut input_2 = Factory::read_input_2();
+    input_2.e_to_user_all = 5890.5;
+    meter.update_input_2(&input_2);
+    let mut input_1 = Factory::read_input_1();
+    input_1.e_to_user_day = 3.9;
+    meter.update_input_1(&input_1);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.5);
+
+    input_1.e_to_user_day = 4.0;
+    meter.update_input_1(&input_1);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.6);
+}
+
+#[test]
+fn totals_resync_after_real_jumps() {
+    common_setup();
+
+    let mut meter = energy::Meter::default();
+    meter.update_input_all(&Factory::read_input_all());
+
+    // we missed a lot; too much to believe from one reading
+    let mut input_1 = Factory::read_input_1();
+    input_1.e_to_user_day = 40.0;
+    meter.update_input_1(&input_1);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5889.8);
+
+    // but the next carries on from it, so it was real
+    input_1.e_to_user_day = 40.5;
+    meter.update_input_1(&input_1);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.3);
+    input_1.e_to_user_day = 41.0;
+    meter.update_input_1(&input_1);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.8);
+
+    // the lifetime counter brings in what we missed, the same way
+    let mut input_2 = Factory::read_input_2();
+    input_2.e_to_user_all = 5927.6;
+    meter.update_input_2(&input_2);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.8);
+    input_2.e_to_user_all = 5927.7;
+    meter.update_input_2(&input_2);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5927.7);
+}
+
+#[test]
+fn totals_only_reset_to_zero() {
+    common_setup();
+
+    let mut meter = energy::Meter::default();
+    let mut input = Factory::read_input_all();
+    meter.update_input_all(&input);
+
+    // a bad low reading isn't the daily counter resetting
+    input.e_to_user_day = 1.5;
+    meter.update_input_all(&input);
+    input.e_to_user_day = 3.3;
+    meter.update_input_all(&input);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5889.9);
+
+    // whereas going back to about 0 is
+    input.e_to_user_day = 0.2;
+    meter.update_input_all(&input);
+    assert_eq!(meter.totals().unwrap().e_grid_import_total, 5890.1);
+}
@@ -0,0 +1,96 @@
+mod common;
+use common::*;
+
+use chrono::NaiveDate;
+use hold_writes::Tracker;
+use std::time::{Duration, Instant};
+
+#[test]
+fn coalesces_writes() {
+    common_setup();
+
+    let inverter = Factory::inverter();
+    let mut tracker = Tracker::new();
+    let t0 = Instant::now();
+    let debounce = Duration::from_secs(2);
+
+    tracker.queue(inverter.clone(), 64, 50, None, t0);
+    tracker.queue(inverter.clone(), 64, 60, None, t0 + Duration::from_secs(1));
+    tracker.queue(inverter.clone(), 65, 10, None, t0 + Duration::from_secs(1));
+
+    // the second write to 64 restarted its debounce
+    assert!(tracker
+        .due(t0 + Duration::from_secs(2), debounce)
+        .is_empty());
+
+    let mut due: Vec<(u16, u16)> = tracker
+        .due(t0 + Duration::from_secs(3), debounce)
+        .into_iter()
+        .map(|pending| (pending.register, pending.value))
+        .collect();
+    due.sort();
+    assert_eq!(due, vec![(64, 60), (65, 10)]);
+
+    assert!(tracker
+        .due(t0 + Duration::from_secs(10), debounce)
+        .is_empty());
+}
+
+#[test]
+fn superseded_writes_keep_their_replies() {
+    common_setup();
+
+    let inverter = Factory::inverter();
+    let mut tracker = Tracker::new();
+    let t0 = Instant::now();
+    let reply = |n: u16| coordinator::Reply {
+        topic: format!("result/2222222222/set/hold/{}", n),
+        target: None,
+    };
+
+    tracker.queue(inverter.clone(), 64, 50, Some(reply(1)), t0);
+    tracker.queue(inverter.clone(), 64, 60, Some(reply(2)), t0);
+
+    let due = tracker.due(t0 + Duration::from_secs(2), Duration::from_secs(2));
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].value, 60);
+    assert_eq!(due[0].replies, vec![reply(1), reply(2)]);
+}
+
+#[test]
+fn daily_budget() {
+    common_setup();
+
+    let datalog = Factory::inverter().datalog();
+    let mut tracker = Tracker::new();
+    let today = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+
+    // only acknowledged writes are recorded, so checking uses none of it
+    tracker.check(datalog, 2, today, Some(2)).unwrap();
+    tracker.check(datalog, 2, today, Some(2)).unwrap();
+    assert!(tracker.check(datalog, 3, today, Some(2)).is_err());
+
+    tracker.record(datalog, 64, today, Some(2));
+    tracker.check(datalog, 1, today, Some(2)).unwrap();
+    tracker.record(datalog, 64, today, Some(2));
+    assert!(tracker.check(datalog, 1, today, Some(2)).is_err());
+
+    let counts = tracker.counts(datalog).unwrap();
+    assert_eq!(counts.today, 2);
+    assert_eq!(counts.total, 2);
+    assert_eq!(counts.registers.get(&64), Some(&2));
+    assert_eq!(counts.registers.get(&65), None);
+
+    // a new day, a new budget
+    let tomorrow = today.succ_opt().unwrap();
+    tracker.check(datalog, 2, tomorrow, Some(2)).unwrap();
+    tracker.record(datalog, 65, tomorrow, Some(2));
+    let counts = tracker.counts(datalog).unwrap();
+    assert_eq!(counts.today, 1);
+    assert_eq!(counts.total, 3);
+
+    assert_eq!(
+        serde_json::to_value(counts).unwrap(),
+        json!({ "today": 1, "daily_budget": 2, "total": 3, "registers": { "64": 2, "65": 1 } })
+    );
+}
@@ -12,7 +12,7 @@ async fn all_has_soc() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/sensor/lxp_2222222222/soc/config".to_string(),
         retain: true,
-        payload: r#"{"unique_id":"lxp_2222222222_soc","name":"State of Charge","state_topic":"lxp/2222222222/inputs/all","state_class":"measurement","device_class":"battery","value_template":"{{ value_json.soc }}","unit_of_measurement":"%","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"}}"#.to_string()
+        payload: r#"{"unique_id":"lxp_2222222222_soc","name":"State of Charge","state_topic":"lxp/2222222222/inputs/all","state_class":"measurement","device_class":"battery","value_template":"{{ value_json.soc }}","unit_of_measurement":"%","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
     }));
 }
 
@@ -27,7 +27,7 @@ async fn all_has_v_pv_1() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/sensor/lxp_2222222222/v_pv_1/config".to_string(),
         retain: true,
-        payload: r#"{"unique_id":"lxp_2222222222_v_pv_1","name":"PV Voltage (String 1)","state_topic":"lxp/2222222222/inputs/all","state_class":"measurement","device_class":"voltage","value_template":"{{ value_json.v_pv_1 }}","unit_of_measurement":"V","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"}}"#.to_string()
+        payload: r#"{"unique_id":"lxp_2222222222_v_pv_1","name":"PV Voltage (String 1)","state_topic":"lxp/2222222222/inputs/all","state_class":"measurement","device_class":"voltage","value_template":"{{ value_json.v_pv_1 }}","unit_of_measurement":"V","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
     }));
 }
 
@@ -42,7 +42,7 @@ async fn all_has_p_pv() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/sensor/lxp_2222222222/p_pv/config".to_string(),
         retain: true,
-        payload: r#"{"unique_id":"lxp_2222222222_p_pv","name":"PV Power (Array)","state_topic":"lxp/2222222222/inputs/all","state_class":"measurement","device_class":"power","value_template":"{{ value_json.p_pv }}","unit_of_measurement":"W","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"}}"#.to_string()
+        payload: r#"{"unique_id":"lxp_2222222222_p_pv","name":"PV Power (Array)","state_topic":"lxp/2222222222/inputs/all","state_class":"measurement","device_class":"power","value_template":"{{ value_json.p_pv }}","unit_of_measurement":"W","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
     }));
 }
 
@@ -57,7 +57,22 @@ async fn all_has_e_pv_all() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/sensor/lxp_2222222222/e_pv_all/config".to_string(),
         retain: true,
-        payload: r#"{"unique_id":"lxp_2222222222_e_pv_all","name":"PV Generation (All time)","state_topic":"lxp/2222222222/inputs/all","state_class":"total_increasing","device_class":"energy","value_template":"{{ value_json.e_pv_all }}","unit_of_measurement":"kWh","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"}}"#.to_string()
+        payload: r#"{"unique_id":"lxp_2222222222_e_pv_all","name":"PV Generation (All time)","state_topic":"lxp/2222222222/inputs/all","state_class":"total_increasing","device_class":"energy","value_template":"{{ value_json.e_pv_all }}","unit_of_measurement":"kWh","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_e_grid_import_total() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt).all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: "homeassistant/sensor/lxp_2222222222/e_grid_import_total/config".to_string(),
+        retain: true,
+        payload: r#"{"unique_id":"lxp_2222222222_e_grid_import_total","name":"Grid Import (Total)","state_topic":"lxp/2222222222/inputs/energy","state_class":"total_increasing","device_class":"energy","value_template":"{{ value_json.e_grid_import_total }}","unit_of_measurement":"kWh","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
     }));
 }
 
@@ -72,7 +87,7 @@ async fn all_has_fault_code() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/sensor/lxp_2222222222/fault_code/config".to_string(),
         retain: true,
-        payload: r#"{"unique_id":"lxp_2222222222_fault_code","name":"Fault Code","state_topic":"lxp/2222222222/input/fault_code/parsed","entity_category":"diagnostic","icon":"mdi:alert","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"}}"#.to_string()
+        payload: r#"{"unique_id":"lxp_2222222222_fault_code","name":"Fault Code","state_topic":"lxp/2222222222/input/fault_code/parsed","entity_category":"diagnostic","icon":"mdi:alert","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
     }));
 }
 
@@ -87,7 +102,7 @@ async fn all_has_switch_ac_charge() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/switch/lxp_2222222222/ac_charge/config".to_string(),
         retain: true,
-        payload: r#"{"name":"AC Charge","state_topic":"lxp/2222222222/hold/21/bits","command_topic":"lxp/cmd/2222222222/set/ac_charge","value_template":"{{ value_json.ac_charge_en }}","unique_id":"lxp_2222222222_ac_charge","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"}}"#.to_string()
+        payload: r#"{"name":"AC Charge","state_topic":"lxp/2222222222/hold/21/bits","command_topic":"lxp/cmd/2222222222/set/ac_charge","value_template":"{{ value_json.ac_charge_en }}","unique_id":"lxp_2222222222_ac_charge","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
     }));
 }
 
@@ -102,7 +117,7 @@ async fn all_has_number_ac_charge_soc_limit_pct() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/number/lxp_2222222222/AcChargeSocLimit/config".to_string(),
         retain: true,
-        payload: r#"{"name":"AC Charge Limit %","state_topic":"lxp/2222222222/hold/67","command_topic":"lxp/cmd/2222222222/set/hold/67","value_template":"{{ float(value) }}","unique_id":"lxp_2222222222_number_AcChargeSocLimit","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"},"min":0.0,"max":100.0,"step":1.0,"unit_of_measurement":"%"}"#.to_string()
+        payload: r#"{"name":"AC Charge Limit %","state_topic":"lxp/2222222222/hold/67","command_topic":"lxp/cmd/2222222222/set/hold/67","value_template":"{{ float(value) }}","unique_id":"lxp_2222222222_number_AcChargeSocLimit","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all","min":0.0,"max":100.0,"step":1.0,"unit_of_measurement":"%"}"#.to_string()
     }));
 }
 
@@ -117,6 +132,255 @@ async fn all_has_time_range_ac_charge_1() {
     assert!(r.unwrap().contains(&mqtt::Message {
         topic: "homeassistant/text/lxp_2222222222/ac_charge_1/config".to_string(),
         retain: true,
-        payload: r#"{"name":"AC Charge Timeslot 1","state_topic":"lxp/2222222222/ac_charge/1","command_topic":"lxp/cmd/2222222222/set/ac_charge/1","command_template":"{% set parts = value.split(\"-\") %}{\"start\":\"{{ parts[0] }}\", \"end\":\"{{ parts[1] }}\"}","value_template":"{{ value_json[\"start\"] }}-{{ value_json[\"end\"] }}","unique_id":"lxp_2222222222_text_ac_charge/1","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":{"topic":"lxp/LWT"},"pattern":"([01]?[0-9]|2[0-3]):[0-5][0-9]-([01]?[0-9]|2[0-3]):[0-5][0-9]"}"#.to_string()
+        payload: r#"{"name":"AC Charge Timeslot 1","state_topic":"lxp/2222222222/ac_charge/1","command_topic":"lxp/cmd/2222222222/set/ac_charge/1","command_template":"{% set parts = value.split(\"-\") %}{\"start\":\"{{ parts[0] }}\", \"end\":\"{{ parts[1] }}\"}","value_template":"{{ value_json[\"start\"] }}-{{ value_json[\"end\"] }}","unique_id":"lxp_2222222222_text_ac_charge/1","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all","pattern":"([01]?[0-9]|2[0-3]):[0-5][0-9]-([01]?[0-9]|2[0-3]):[0-5][0-9]"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_number_ac_charge_start_battery_voltage() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt).all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: "homeassistant/number/lxp_2222222222/AcChargeStartBatteryVoltage/config".to_string(),
+        retain: true,
+        payload: r#"{"name":"Charge From AC Lower Limit (V)","state_topic":"lxp/2222222222/hold/158","command_topic":"lxp/cmd/2222222222/set/hold/158","value_template":"{{ float(value) / 10 }}","command_template":"{{ (value | float * 10) | round | int }}","unique_id":"lxp_2222222222_number_AcChargeStartBatteryVoltage","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all","min":40.0,"max":59.0,"step":0.1,"unit_of_measurement":"V"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_select_output_configuration() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let device_info = |fw_code: &str| lxp::packet::DeviceInfo {
+        model: lxp::packet::Model::new(0),
+        serial: "5555555555".to_owned(),
+        fw_code: fw_code.to_owned(),
+        slave_version: 0x10,
+        master_version: 0x11,
+    };
+    let topic = "homeassistant/select/lxp_2222222222/OutputConfiguration/config";
+
+    // off-grid only; on a hybrid register 145 is something else
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt)
+        .with_device_info(Some(device_info("FAAB")))
+        .all()
+        .unwrap();
+    assert!(!r.iter().any(|m| m.topic == topic));
+
+    // and we can't say until we've read the model
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt)
+        .all()
+        .unwrap();
+    assert!(!r.iter().any(|m| m.topic == topic));
+
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt)
+        .with_device_info(Some(device_info("AAAA")))
+        .all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: topic.to_string(),
+        retain: true,
+        payload: r#"{"name":"Output Priority","state_topic":"lxp/2222222222/hold/145","command_topic":"lxp/cmd/2222222222/set/hold/145","value_template":"{{ {0: 'Battery First', 1: 'PV First', 2: 'AC First'}[value | int] }}","command_template":"{{ {'Battery First': 0, 'PV First': 1, 'AC First': 2}[value] }}","options":["Battery First","PV First","AC First"],"unique_id":"lxp_2222222222_select_OutputConfiguration","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"],"model":"LuxPower Off-grid (EU)","sw_version":"AAAA-1011","hw_version":"power rating 0","serial_number":"5555555555","via_device":"lxp_datalog_2222222222"},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_select_working_mode() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt).all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: "homeassistant/select/lxp_2222222222/working_mode/config".to_string(),
+        retain: true,
+        payload: r#"{"name":"Working Mode","state_topic":"lxp/2222222222/hold/21/bits","command_topic":"lxp/cmd/2222222222/set/working_mode","value_template":"{% if value_json.forced_discharge_en == 'ON' %}Forced Discharge{% elif value_json.ac_charge_en == 'ON' %}AC Charge{% elif value_json.charge_priority_en == 'ON' %}Charge Priority{% else %}Self Use{% endif %}","options":["Self Use","AC Charge","Charge Priority","Forced Discharge"],"unique_id":"lxp_2222222222_working_mode","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_button_timesync() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt).all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: "homeassistant/button/lxp_2222222222/set_timesync/config".to_string(),
+        retain: true,
+        payload: r#"{"name":"Sync Time","command_topic":"lxp/cmd/2222222222/set/timesync","payload_press":"","unique_id":"lxp_2222222222_button_set_timesync","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn device_info() {
+    common_setup();
+
+    let config = Factory::example_config();
+
+    let mut values = vec![0x01, 0x02, 0x03, 0x04];
+    values.extend_from_slice(b"5555555555FAAB");
+    values.extend_from_slice(&[0x25, 0, 0x26, 0]);
+    let td = lxp::packet::TranslatedData {
+        datalog: config.inverters[0].datalog(),
+        device_function: lxp::packet::DeviceFunction::ReadHold,
+        inverter: config.inverters[0].serial(),
+        register: 0,
+        values,
+    };
+    let device_info = lxp::packet::DeviceInfo::from_hold(&td).unwrap();
+    assert_eq!(device_info.serial, "5555555555");
+    assert_eq!(device_info.sw_version(), "FAAB-2526");
+    assert_eq!(device_info.model.battery_type, 1);
+    assert_eq!(device_info.model_name(), "LuxPower Hybrid (EU)");
+    assert_eq!(device_info.hw_version(), "power rating 3");
+
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt)
+        .with_device_info(Some(device_info))
+        .all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: "homeassistant/sensor/lxp_2222222222/soc/config".to_string(),
+        retain: true,
+        payload: r#"{"unique_id":"lxp_2222222222_soc","name":"State of Charge","state_topic":"lxp/2222222222/inputs/all","state_class":"measurement","device_class":"battery","value_template":"{{ value_json.soc }}","unit_of_measurement":"%","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"],"model":"LuxPower Hybrid (EU)","sw_version":"FAAB-2526","hw_version":"power rating 3","serial_number":"5555555555","via_device":"lxp_datalog_2222222222"},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn device_info_model() {
+    common_setup();
+
+    let config = Factory::example_config();
+
+    // lithium, power rating 6, US
+    let mut values = vec![0x01, 0x00, 0x06, 0x01];
+    values.extend_from_slice(b"5555555555aaaa");
+    values.extend_from_slice(&[0x10, 0, 0x11, 0]);
+    let td = lxp::packet::TranslatedData {
+        datalog: config.inverters[0].datalog(),
+        device_function: lxp::packet::DeviceFunction::ReadHold,
+        inverter: config.inverters[0].serial(),
+        register: 0,
+        values,
+    };
+    let device_info = lxp::packet::DeviceInfo::from_hold(&td).unwrap();
+
+    assert_eq!(
+        device_info.model,
+        lxp::packet::Model {
+            battery_type: 1,
+            lithium_type: 0,
+            power_rating: 6,
+            us_version: true,
+        }
+    );
+    assert_eq!(device_info.family(), lxp::packet::ModelFamily::OffGrid);
+    assert_eq!(device_info.model_name(), "LuxPower Off-grid (US)");
+    assert_eq!(device_info.hw_version(), "power rating 6");
+}
+
+#[tokio::test]
+async fn all_has_datalogger_connection() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt).all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: "homeassistant/binary_sensor/lxp_2222222222/connection/config".to_string(),
+        retain: true,
+        payload: r#"{"name":"Connection","state_topic":"lxp/2222222222/availability","payload_on":"online","payload_off":"offline","device_class":"connectivity","entity_category":"diagnostic","unique_id":"lxp_datalog_2222222222_connection","device":{"manufacturer":"LuxPower","name":"lxp_datalog_2222222222","identifiers":["lxp_datalog_2222222222"],"model":"Datalogger","serial_number":"2222222222"},"availability":[{"topic":"lxp/LWT"}]}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_bms_cell_overvoltage() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt).all();
+
+    assert!(r.is_ok());
+    assert!(r.unwrap().contains(&mqtt::Message {
+        topic: "homeassistant/binary_sensor/lxp_2222222222/bms_fault_cell_overvoltage/config".to_string(),
+        retain: true,
+        payload: r#"{"name":"BMS Cell Overvoltage","state_topic":"lxp/2222222222/bms","value_template":"{{ value_json.fault.cell_overvoltage }}","payload_on":"ON","payload_off":"OFF","device_class":"problem","entity_category":"diagnostic","unique_id":"lxp_2222222222_bms_fault_cell_overvoltage","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_faults() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt)
+        .all()
+        .unwrap();
+
+    assert!(r.contains(&mqtt::Message {
+        topic: "homeassistant/binary_sensor/lxp_2222222222/faults/config".to_string(),
+        retain: true,
+        payload: r#"{"name":"Faults","state_topic":"lxp/2222222222/faults","value_template":"{{ 'ON' if value_json | length > 0 else 'OFF' }}","payload_on":"ON","payload_off":"OFF","device_class":"problem","entity_category":"diagnostic","unique_id":"lxp_2222222222_faults","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all","json_attributes_topic":"lxp/2222222222/faults","json_attributes_template":"{{ {'active': value_json} | tojson }}"}"#.to_string()
+    }));
+
+    // one sensor per kind, not per code
+    assert!(r.iter().any(|m| m.topic.ends_with("/warnings/config")));
+    assert!(!r.iter().any(|m| m.topic.ends_with("/e001/config")));
+}
+
+#[tokio::test]
+async fn system_has_soc() {
+    common_setup();
+
+    let config = Factory::example_config();
+    let system = config::System {
+        enabled: true,
+        name: "garage".to_owned(),
+        datalog: Serial::from_str("SYSTEM0001").unwrap(),
+        inverters: vec![config.inverters[0].datalog()],
+    };
+    let r = home_assistant::Config::for_system(&system, &config.mqtt).system();
+
+    assert!(r.contains(&mqtt::Message {
+        topic: "homeassistant/sensor/lxp_SYSTEM0001/soc/config".to_string(),
+        retain: true,
+        payload: r#"{"unique_id":"lxp_SYSTEM0001_soc","name":"State of Charge","state_topic":"lxp/SYSTEM0001/inputs/all","state_class":"measurement","device_class":"battery","value_template":"{{ value_json.soc }}","unit_of_measurement":"%","device":{"manufacturer":"LuxPower","name":"lxp_SYSTEM0001","identifiers":["lxp_SYSTEM0001"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/SYSTEM0001/availability"}],"availability_mode":"all"}"#.to_string()
+    }));
+}
+
+#[tokio::test]
+async fn all_has_generator_entities() {
+    common_setup();
+
+    let mut config = Factory::example_config();
+    let topic = "homeassistant/binary_sensor/lxp_2222222222/generator_supplying/config";
+
+    // nothing on the generator port unless we're told otherwise
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt)
+        .all()
+        .unwrap();
+    assert!(!r.iter().any(|m| m.topic == topic));
+    assert!(!r.iter().any(|m| m.topic.ends_with("/GenRatedPower/config")));
+
+    config.inverters[0].generator = Some(true);
+    let r = home_assistant::Config::new(&config.inverters[0], &config.mqtt).all();
+
+    assert!(r.is_ok());
+    let r = r.unwrap();
+    assert!(r.iter().any(|m| m.topic.ends_with("/GenRatedPower/config")));
+    assert!(r.contains(&mqtt::Message {
+        topic: topic.to_string(),
+        retain: true,
+        payload: r#"{"name":"Generator Supplying Power","state_topic":"lxp/2222222222/generator","value_template":"{{ value_json.supplying }}","payload_on":"ON","payload_off":"OFF","device_class":"power","entity_category":"diagnostic","unique_id":"lxp_2222222222_generator_supplying","device":{"manufacturer":"LuxPower","name":"lxp_2222222222","identifiers":["lxp_2222222222"]},"availability":[{"topic":"lxp/LWT"},{"topic":"lxp/2222222222/availability"}],"availability_mode":"all"}"#.to_string()
     }));
 }
@@ -5,32 +5,39 @@ use common::*;
 async fn sends_http_request() {
     common_setup();
 
-    let mut server = mockito::Server::new();
+    let mut server = mockito::Server::new_async().await;
     let mock = server
         .mock("POST", "/write")
         .match_query(Matcher::UrlEncoded("db".to_owned(), "lxp".to_owned()))
         .with_status(204)
-        .match_body("inputs,datalog=BA12345678 p_pv=250i,soc=100i,v_bat=52.4 1000000000")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::Regex("^inputs,datalog=1234567890 ".to_owned()),
+            Matcher::Regex(",soc=55i,".to_owned()),
+            Matcher::Regex(",v_bat=49.1,".to_owned()),
+        ]))
         .create();
 
     let config = Factory::example_config_wrapped();
     //config.set_influx_url(server.url());
     config.influx_mut().url = server.url();
-    let channels = Channels::new();
 
-    let influx = Influx::new(config, channels.clone());
+    let influx = Influx::new(config);
 
-    let tf = async {
-        let json =
-            json!({ "time": 1, "datalog": "BA12345678", "soc": 100, "p_pv": 250, "v_bat": 52.4 });
-        channels
-            .to_influx
-            .send(influx::ChannelData::InputData(json))?;
-        channels.to_influx.send(influx::ChannelData::Shutdown)?;
-        Ok(())
-    };
-
-    futures::try_join!(influx.start(), tf).unwrap();
+    let data = output::OutputData::InputAll(Box::new(Factory::read_input_all()));
+    influx.write(&[data]).await.unwrap();
 
     mock.assert();
 }
+
+#[test]
+fn ignores_holds() {
+    common_setup();
+
+    let influx = Influx::new(Factory::example_config_wrapped());
+    let datalog = Factory::inverter().datalog();
+
+    assert_eq!(
+        influx.filter(output::OutputData::Hold(datalog, vec![(21, 1)])),
+        None
+    );
+}
@@ -22,6 +22,10 @@ async fn serials_fixed_in_outgoing_packets() {
         heartbeats: None,
         publish_holdings_on_connect: None,
         read_timeout: None,
+        timezone: None,
+        timesync_on_connect: None,
+        timesync_tolerance: None,
+        generator: None,
     };
     let channels = Channels::new();
     let inverter = lxp::inverter::Inverter::new(config, &inverter, channels.clone());
@@ -112,6 +116,10 @@ async fn test_replies_to_heartbeats() {
         heartbeats: Some(true),
         publish_holdings_on_connect: None,
         read_timeout: None,
+        timezone: None,
+        timesync_on_connect: None,
+        timesync_tolerance: None,
+        generator: None,
     };
     let channels = Channels::new();
     let inverter = lxp::inverter::Inverter::new(config, &inverter, channels.clone());
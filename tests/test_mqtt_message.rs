@@ -363,3 +363,177 @@ async fn for_input_ignore_127_254() {
 
     assert_eq!(mqtt::Message::for_input(packet, false).unwrap(), vec![]);
 }
+
+#[tokio::test]
+async fn v5_metadata() {
+    common_setup();
+
+    let message = mqtt::Message {
+        topic: "2222222222/inputs/1".to_owned(),
+        retain: false,
+        payload: "{\"status\":16,\"v_bat\":49.1,\"soc\":55,\"p_pv\":0}".to_owned(),
+    };
+    assert_eq!(
+        message.datalog(),
+        Some(Serial::from_str("2222222222").unwrap())
+    );
+    assert!(message.is_telemetry());
+    assert_eq!(
+        message.units(),
+        Some("{\"p_pv\":\"W\",\"soc\":\"%\",\"v_bat\":\"V\"}".to_owned())
+    );
+
+    let message = mqtt::Message {
+        topic: "result/2222222222/read/hold/12".to_owned(),
+        retain: false,
+        payload: "OK".to_owned(),
+    };
+    assert_eq!(
+        message.datalog(),
+        Some(Serial::from_str("2222222222").unwrap())
+    );
+    assert!(!message.is_telemetry());
+    assert_eq!(message.units(), None);
+}
+
+#[tokio::test]
+async fn publish_topic() {
+    common_setup();
+
+    let mut config = Factory::example_config();
+    assert_eq!(
+        mqtt::Message::publish_topic("2222222222/inputs/all", &config.mqtt),
+        "lxp/2222222222/inputs/all"
+    );
+
+    config.mqtt.site = Some("home".to_owned());
+    config.mqtt.topics.inputs.template =
+        Some("{namespace}/{site}/{datalog}/inputs/{rest}".to_owned());
+    config.mqtt.topics.result.template = Some("{namespace}/{site}/{datalog}/{topic}".to_owned());
+
+    assert_eq!(
+        mqtt::Message::publish_topic("2222222222/inputs/all", &config.mqtt),
+        "lxp/home/2222222222/inputs/all"
+    );
+    assert_eq!(
+        mqtt::Message::publish_topic("result/2222222222/read/hold/12", &config.mqtt),
+        "lxp/home/2222222222/result/2222222222/read/hold/12"
+    );
+    // no template for hold, so unchanged
+    assert_eq!(
+        mqtt::Message::publish_topic("2222222222/hold/12", &config.mqtt),
+        "lxp/2222222222/hold/12"
+    );
+}
+
+#[tokio::test]
+async fn topic_classes() {
+    common_setup();
+
+    use mqtt::TopicClass::*;
+
+    for (topic, class) in [
+        ("2222222222/inputs/all", Inputs),
+        ("2222222222/inputs/energy", Inputs),
+        ("2222222222/bms", Inputs),
+        ("2222222222/generator", Inputs),
+        ("2222222222/faults", Inputs),
+        ("2222222222/warnings", Inputs),
+        ("2222222222/input/soc", Input),
+        ("2222222222/hold/21", Hold),
+        ("2222222222/hold/21/bits", Hold),
+        ("2222222222/param/0", Param),
+        ("result/2222222222/read/hold/12", Result),
+        ("2222222222/availability", Availability),
+        ("2222222222/ac_charge/1", TimeRegister),
+        ("2222222222/ac_first/2", TimeRegister),
+        ("2222222222/charge_priority/3", TimeRegister),
+        ("2222222222/forced_discharge/1", TimeRegister),
+        ("2222222222/time_drift", Other),
+        ("2222222222/battery", Other),
+        ("2222222222/hold_writes", Other),
+        ("2222222222/alerts", Other),
+        ("2222222222/schedule/timesync", Other),
+    ] {
+        assert_eq!(mqtt::TopicClass::from_topic(topic), class, "{}", topic);
+    }
+}
+
+#[tokio::test]
+async fn publish_topic_rest() {
+    common_setup();
+
+    let mut config = Factory::example_config();
+    config.mqtt.topics.hold.template = Some("{namespace}/{datalog}/holding/{rest}".to_owned());
+    config.mqtt.topics.time_registers.template =
+        Some("{namespace}/{datalog}/windows/{rest}".to_owned());
+    config.mqtt.topics.other.template = Some("{namespace}/{datalog}/state/{rest}".to_owned());
+
+    // time registers keep their kind, rather than landing on a hold register
+    for (topic, published) in [
+        ("2222222222/hold/1", "lxp/2222222222/holding/1"),
+        (
+            "2222222222/ac_charge/1",
+            "lxp/2222222222/windows/ac_charge/1",
+        ),
+        ("2222222222/ac_first/2", "lxp/2222222222/windows/ac_first/2"),
+        (
+            "2222222222/charge_priority/3",
+            "lxp/2222222222/windows/charge_priority/3",
+        ),
+        (
+            "2222222222/forced_discharge/1",
+            "lxp/2222222222/windows/forced_discharge/1",
+        ),
+        // and single segment topics aren't emptied out
+        ("2222222222/time_drift", "lxp/2222222222/state/time_drift"),
+        ("2222222222/battery", "lxp/2222222222/state/battery"),
+        ("2222222222/hold_writes", "lxp/2222222222/state/hold_writes"),
+        (
+            "2222222222/schedule/timesync",
+            "lxp/2222222222/state/schedule/timesync",
+        ),
+    ] {
+        assert_eq!(
+            mqtt::Message::publish_topic(topic, &config.mqtt),
+            published,
+            "{}",
+            topic
+        );
+    }
+}
+
+#[tokio::test]
+async fn for_input_keys() {
+    common_setup();
+
+    let inverter = Factory::inverter();
+
+    let mut values = vec![0; 80];
+    values[8] = 235; // v_bat, 23.5
+    values[10] = 55; // soc
+    let packet = lxp::packet::TranslatedData {
+        datalog: inverter.datalog(),
+        device_function: lxp::packet::DeviceFunction::ReadInput,
+        inverter: inverter.serial(),
+        register: 0,
+        values,
+    };
+
+    let messages = mqtt::Message::for_input_keys(packet).unwrap();
+
+    assert!(messages.contains(&mqtt::Message {
+        topic: "2222222222/inputs/soc".to_owned(),
+        retain: false,
+        payload: "55".to_owned()
+    }));
+    assert!(messages.contains(&mqtt::Message {
+        topic: "2222222222/inputs/v_bat".to_owned(),
+        retain: false,
+        payload: "23.5".to_owned()
+    }));
+    // only used by influx
+    assert!(!messages
+        .iter()
+        .any(|m| m.topic == "2222222222/inputs/time" || m.topic == "2222222222/inputs/datalog"));
+}
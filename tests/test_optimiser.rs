@@ -0,0 +1,210 @@
+mod common;
+use common::*;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use optimiser::{Adjustment, Controller};
+
+fn config() -> config::Optimiser {
+    config::Optimiser {
+        enabled: true,
+        datalog: Factory::inverter().datalog(),
+        battery_power: 5000,
+        export_limit: None,
+        reserve_soc: None,
+        reserve_until: None,
+        hysteresis: None,
+        min_write_interval: None,
+    }
+}
+
+fn at(hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 6, 21)
+        .unwrap()
+        .and_hms_opt(hour, minute, 0)
+        .unwrap()
+}
+
+fn input(p_to_grid: u16, p_to_user: u16, p_discharge: u16, soc: i8) -> lxp::packet::ReadInputAll {
+    lxp::packet::ReadInputAll {
+        p_to_grid,
+        p_to_user,
+        p_discharge,
+        soc,
+        datalog: Factory::inverter().datalog(),
+        ..Factory::read_input_all()
+    }
+}
+
+#[test]
+fn zero_export() {
+    common_setup();
+
+    let mut controller = Controller::new(config::Optimiser {
+        export_limit: Some(0),
+        ..config()
+    })
+    .unwrap();
+
+    // importing, but no charge rate to step from until the registers are read
+    assert!(controller
+        .update(&input(0, 1000, 0, 50), at(12, 0))
+        .is_empty());
+
+    // charging flat out, and forced discharge on
+    controller.observe_hold(&[(21, 1 << 10), (64, 100)]);
+
+    // importing 1kW, so charge 20% less to let solar cover it
+    assert_eq!(
+        controller.update(&input(0, 1000, 0, 50), at(12, 0)),
+        vec![Adjustment::ChargeRate(80)]
+    );
+
+    // still importing, but too soon to write again
+    assert!(controller
+        .update(&input(0, 500, 0, 50), at(12, 1))
+        .is_empty());
+
+    // inside the hysteresis band, nothing to do
+    assert!(controller
+        .update(&input(50, 0, 0, 50), at(12, 10))
+        .is_empty());
+
+    // exporting from a forced discharge
+    assert_eq!(
+        controller.update(&input(1000, 0, 1000, 50), at(12, 10)),
+        vec![
+            Adjustment::ChargeRate(100),
+            Adjustment::ForcedDischarge(false)
+        ]
+    );
+
+    // still exporting, but there's nothing left to change
+    assert!(controller
+        .update(&input(1000, 0, 1000, 50), at(12, 20))
+        .is_empty());
+
+    // until something switches forced discharge back on
+    controller.observe_hold(&[(21, 1 << 10)]);
+    assert_eq!(
+        controller.update(&input(1000, 0, 1000, 50), at(12, 30)),
+        vec![Adjustment::ForcedDischarge(false)]
+    );
+}
+
+#[test]
+fn export_limit() {
+    common_setup();
+
+    let mut controller = Controller::new(config::Optimiser {
+        export_limit: Some(3600),
+        ..config()
+    })
+    .unwrap();
+
+    // charging at half rate, set elsewhere
+    controller.observe_hold(&[(64, 50)]);
+
+    // under the limit, so let more out
+    assert_eq!(
+        controller.update(&input(2600, 0, 0, 50), at(12, 0)),
+        vec![Adjustment::ChargeRate(30)]
+    );
+
+    // over the limit, but too soon to write again
+    assert!(controller
+        .update(&input(4100, 0, 0, 50), at(12, 1))
+        .is_empty());
+
+    // over the limit, soak it up
+    assert_eq!(
+        controller.update(&input(4100, 0, 0, 50), at(12, 5)),
+        vec![Adjustment::ChargeRate(40)]
+    );
+
+    // inside the hysteresis band, nothing to do
+    assert!(controller
+        .update(&input(3650, 0, 0, 50), at(12, 10))
+        .is_empty());
+}
+
+#[test]
+fn reserve_soc() {
+    common_setup();
+
+    let mut controller = Controller::new(config::Optimiser {
+        reserve_soc: Some(20),
+        reserve_until: Some("16:00".to_owned()),
+        ..config()
+    })
+    .unwrap();
+
+    controller.observe_hold(&[(65, 100)]);
+
+    assert!(controller
+        .update(&input(0, 0, 800, 40), at(10, 0))
+        .is_empty());
+
+    assert_eq!(
+        controller.update(&input(0, 0, 800, 20), at(11, 0)),
+        vec![
+            Adjustment::DischargeRate(0),
+            Adjustment::ForcedDischarge(false)
+        ]
+    );
+
+    // hysteresis; not far enough above the reserve to start again
+    assert!(controller.update(&input(0, 0, 0, 21), at(12, 0)).is_empty());
+
+    // reserve no longer needed
+    assert_eq!(
+        controller.update(&input(0, 0, 0, 21), at(16, 0)),
+        vec![Adjustment::DischargeRate(100)]
+    );
+}
+
+#[tokio::test]
+async fn sends_commands() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+    *config.optimiser_mut() = Some(config::Optimiser {
+        reserve_soc: Some(20),
+        ..self::config()
+    });
+    let channels = Channels::new();
+    let subject = Optimiser::new(config, channels.clone());
+
+    let sf = subject.start();
+
+    let tf =
+        async {
+            let mut from_mqtt = channels.from_mqtt.subscribe();
+
+            // other inverters are none of our business
+            let other = lxp::packet::ReadInputAll {
+                datalog: Serial::from_str("9999999999").unwrap(),
+                ..input(0, 0, 800, 10)
+            };
+            for input in [other, input(0, 0, 800, 20)] {
+                channels.to_outputs.send(output::ChannelData::Data(
+                    output::OutputData::InputAll(Box::new(input)),
+                ))?;
+            }
+
+            for (command, payload) in [("discharge_rate_pct", "0"), ("forced_discharge", "OFF")] {
+                assert_eq!(
+                    from_mqtt.recv().await?,
+                    mqtt::ChannelData::Internal(mqtt::Message {
+                        topic: format!("cmd/2222222222/set/{}", command),
+                        retain: false,
+                        payload: payload.to_owned(),
+                    })
+                );
+            }
+
+            channels.to_outputs.send(output::ChannelData::Shutdown)?;
+            Ok::<(), anyhow::Error>(())
+        };
+
+    futures::try_join!(sf, tf).unwrap();
+}
@@ -0,0 +1,64 @@
+mod common;
+use common::*;
+
+use async_trait::async_trait;
+use output::OutputData;
+
+// records what it was asked to write
+struct Recorder {
+    writes: Rc<RefCell<Vec<Vec<OutputData>>>>,
+}
+
+#[async_trait(?Send)]
+impl Output for Recorder {
+    fn name(&self) -> String {
+        "recorder".to_owned()
+    }
+
+    fn filter(&self, data: OutputData) -> Option<OutputData> {
+        match data {
+            OutputData::InputAll(_) => Some(data),
+            OutputData::Hold(..) | OutputData::Battery(_) => None,
+        }
+    }
+
+    fn batch_size(&self) -> usize {
+        2
+    }
+
+    async fn write(&self, batch: &[OutputData]) -> Result<()> {
+        self.writes.borrow_mut().push(batch.to_vec());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn filters_and_batches() {
+    common_setup();
+
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let channels = Channels::new();
+    let recorder = Recorder {
+        writes: writes.clone(),
+    };
+    let outputs = Outputs::new(vec![Box::new(recorder)], channels.clone());
+
+    let input = OutputData::InputAll(Box::new(Factory::read_input_all()));
+    let hold = OutputData::Hold(Factory::inverter().datalog(), vec![(21, 1)]);
+
+    let tf = async {
+        for data in [input.clone(), hold, input.clone(), input.clone()] {
+            channels.to_outputs.send(output::ChannelData::Data(data))?;
+        }
+        outputs.stop();
+        Ok(())
+    };
+
+    futures::try_join!(outputs.start(), tf).unwrap();
+
+    // a full batch, then what was left over at shutdown
+    assert_eq!(
+        *writes.borrow(),
+        vec![vec![input.clone(), input.clone()], vec![input]]
+    );
+}
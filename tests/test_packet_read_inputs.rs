@@ -18,3 +18,88 @@ async fn handles_missing_read_input() {
     read_inputs.set_read_input_3(Factory::read_input_3());
     assert_eq!(read_inputs.to_input_all(), None);
 }
+
+#[tokio::test]
+async fn bms_status() {
+    common_setup();
+
+    let mut input = Factory::read_input_all();
+    input.bms_event_1 = 1 << 1 | 1 << 8;
+    input.max_cell_voltage = 3.412;
+    input.min_cell_voltage = 3.287;
+
+    let status = lxp::packet::BmsStatus::from_input_all(&input);
+
+    assert_eq!(status.bat_status[5], 192);
+    assert_eq!(status.cell_voltage_delta, 0.125);
+    assert_eq!(status.cell_imbalance, "ON");
+    assert_eq!(status.fault.cell_overvoltage, "ON");
+    assert_eq!(status.fault.charge_overcurrent, "ON");
+    assert_eq!(status.fault.cell_undervoltage, "OFF");
+    assert_eq!(status.warning.high_voltage, "ON"); // bms_event_2 is 2
+    assert_eq!(status.warning.comms_error, "OFF");
+    assert_eq!(status.request.charge_enable, "ON"); // bat_status_5 is 192
+    assert_eq!(status.request.discharge_enable, "ON");
+    assert_eq!(status.request.force_charge_1, "OFF");
+    assert_eq!(status.request.full_charge, "OFF");
+
+    // a ReadInput3 on its own decodes the same
+    assert_eq!(
+        lxp::packet::BmsStatus::from_input_3(&Factory::read_input_3()),
+        lxp::packet::BmsStatus::from_input_all(&Factory::read_input_all())
+    );
+}
+
+#[tokio::test]
+async fn generator_status() {
+    common_setup();
+
+    let mut input = Factory::read_input_all();
+    input.v_gen = 240.1;
+    input.f_gen = 50.02;
+    input.p_gen = 3200;
+
+    let status = lxp::packet::GeneratorStatus::from_input_all(&input);
+
+    assert_eq!(status.v_gen, 240.1);
+    assert_eq!(status.p_gen, 3200);
+    assert_eq!(status.supplying, "ON");
+
+    // running, but nothing coming in
+    input.p_gen = 0;
+    let status = lxp::packet::GeneratorStatus::from_input_all(&input);
+    assert_eq!(status.supplying, "OFF");
+}
+
+#[tokio::test]
+async fn active_fault_and_warning_codes() {
+    common_setup();
+
+    use lxp::packet::{FaultCodeString, WarningCodeString};
+
+    assert!(FaultCodeString::active(0).is_empty());
+
+    let faults = FaultCodeString::active(1 << 3 | 1 << 19);
+    assert_eq!(
+        serde_json::to_string(&faults).unwrap(),
+        r#"[{"bit":3,"code":"E003","description":"CT Fail","severity":"critical"},{"bit":19,"code":"E019","description":"Bus voltage high","severity":"critical"}]"#
+    );
+
+    let warnings = WarningCodeString::active(1 << 16);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].code, "W016");
+    assert_eq!(warnings[0].severity, "warning");
+
+    // severity is per code
+    let severities = |codes: Vec<lxp::packet::ActiveCode>| -> Vec<&'static str> {
+        codes.into_iter().map(|c| c.severity).collect()
+    };
+    assert_eq!(
+        severities(WarningCodeString::active(1 << 15 | 1 << 17)),
+        vec!["critical", "info"]
+    );
+    assert_eq!(
+        severities(FaultCodeString::active(1 << 4 | 1 << 14)),
+        vec!["warning", "critical"]
+    );
+}
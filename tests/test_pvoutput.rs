@@ -0,0 +1,179 @@
+mod common;
+use common::*;
+
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use pvoutput::{Aggregator, Upload};
+
+fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 3, day)
+        .unwrap()
+        .and_hms_opt(hour, minute, 0)
+        .unwrap()
+}
+
+fn param<'a>(upload: &'a Upload, key: &str) -> &'a str {
+    upload
+        .params()
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v.as_str())
+        .unwrap()
+}
+
+#[test]
+fn aggregates_status_and_output() {
+    common_setup();
+
+    let mut aggregator = Aggregator::new(5, true);
+    let mut input = Factory::read_input_all();
+
+    input.p_pv = 1000;
+    aggregator.add(&input, at(1, 12, 1));
+    input.p_pv = 2000;
+    input.e_pv_day = 4.2;
+    aggregator.add(&input, at(1, 12, 3));
+    assert!(aggregator.ready().is_empty());
+
+    // next interval completes the first
+    input.p_pv = 500;
+    aggregator.add(&input, at(1, 12, 6));
+    let uploads = aggregator.ready();
+    assert_eq!(uploads.len(), 1);
+    let status = &uploads[0];
+    assert_eq!(status.path(), "/service/r2/addstatus.jsp");
+    assert_eq!(param(status, "d"), "20240301");
+    assert_eq!(param(status, "t"), "12:03");
+    assert_eq!(param(status, "v1"), "4200");
+    assert_eq!(param(status, "v2"), "1500");
+    assert_eq!(param(status, "v3"), "6700");
+    assert_eq!(param(status, "v4"), "722");
+    assert_eq!(param(status, "v6"), "246.3");
+    assert_eq!(param(status, "v7"), "55");
+    assert_eq!(param(status, "v8"), "-813");
+    aggregator.accepted(status);
+
+    // next day completes the last interval and the day
+    aggregator.add(&input, at(2, 0, 1));
+    let uploads = aggregator.ready();
+    assert_eq!(uploads.len(), 2);
+    assert_eq!(param(&uploads[0], "t"), "12:06");
+    let output = &uploads[1];
+    assert_eq!(output.path(), "/service/r2/addoutput.jsp");
+    assert_eq!(param(output, "d"), "20240301");
+    assert_eq!(param(output, "g"), "4200");
+    assert_eq!(param(output, "e"), "200");
+    assert_eq!(param(output, "ip"), "3200");
+    assert_eq!(param(output, "pp"), "2000");
+    assert_eq!(param(output, "pt"), "12:03");
+
+    // kept until accepted
+    aggregator.accepted(output);
+    assert_eq!(aggregator.ready(), vec![uploads[0].clone()]);
+}
+
+#[tokio::test]
+async fn posts_status() {
+    common_setup();
+
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/service/r2/addstatus.jsp")
+        .match_header("x-pvoutput-apikey", "secret")
+        .match_header("x-pvoutput-systemid", "12345")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("v7".to_owned(), "55".to_owned()),
+            // on the inverter's clock, not ours
+            Matcher::UrlEncoded("t".to_owned(), "07:01".to_owned()),
+        ]))
+        .with_status(200)
+        .create();
+
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::America::New_York),
+        ..Factory::inverter()
+    };
+
+    let pvoutput = PvOutput::new(
+        config::PvOutput {
+            enabled: true,
+            url: server.url(),
+            system_id: "12345".to_owned(),
+            api_key: "secret".to_owned(),
+            datalog: Serial::from_str("1234567890").unwrap(),
+            interval: Some(5),
+            end_of_day: Some(false),
+        },
+        inverter,
+    );
+
+    for minute in [1, 11] {
+        let mut input = Factory::read_input_all();
+        input.time = UnixTime(Utc.with_ymd_and_hms(2024, 3, 1, 12, minute, 0).unwrap());
+        let data = output::OutputData::InputAll(Box::new(input));
+        let data = pvoutput.filter(data).unwrap();
+        pvoutput.write(&[data]).await.unwrap();
+    }
+
+    mock.assert();
+}
+
+#[tokio::test]
+async fn reposts_only_what_was_not_accepted() {
+    common_setup();
+
+    let mut server = mockito::Server::new_async().await;
+    let status = server
+        .mock("POST", "/service/r2/addstatus.jsp")
+        .with_status(500)
+        .create_async()
+        .await;
+    let output = server
+        .mock("POST", "/service/r2/addoutput.jsp")
+        .with_status(200)
+        .expect(1)
+        .create_async()
+        .await;
+
+    let pvoutput = PvOutput::new(
+        config::PvOutput {
+            enabled: true,
+            url: server.url(),
+            system_id: "12345".to_owned(),
+            api_key: "secret".to_owned(),
+            datalog: Serial::from_str("1234567890").unwrap(),
+            interval: Some(5),
+            end_of_day: Some(true),
+        },
+        Factory::inverter(),
+    );
+
+    let pvoutput = &pvoutput;
+    let write = |day: u32, minute: u32| {
+        let mut input = Factory::read_input_all();
+        input.time = UnixTime(Utc.with_ymd_and_hms(2024, 3, day, 12, minute, 0).unwrap());
+        let data = pvoutput
+            .filter(output::OutputData::InputAll(Box::new(input)))
+            .unwrap();
+        async move { pvoutput.write(&[data]).await }
+    };
+
+    write(1, 1).await.unwrap();
+    // completes the status and the day; the status is refused but the
+    // output still goes
+    assert!(write(2, 1).await.is_err());
+    status.assert_async().await;
+
+    // the status is posted again, but not the output
+    status.remove_async().await;
+    let status = server
+        .mock("POST", "/service/r2/addstatus.jsp")
+        .match_body(Matcher::UrlEncoded("d".to_owned(), "20240301".to_owned()))
+        .with_status(200)
+        .expect(1)
+        .create_async()
+        .await;
+    write(2, 2).await.unwrap();
+
+    status.assert_async().await;
+    output.assert_async().await;
+}
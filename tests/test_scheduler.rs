@@ -0,0 +1,130 @@
+mod common;
+use common::*;
+
+use chrono::{TimeZone, Utc};
+
+fn job(inverters: Vec<Serial>) -> config::Job {
+    config::Job {
+        name: "discharge".to_owned(),
+        cron: Some("0 17 * * *".to_owned()),
+        solar: None,
+        command: "set/forced_discharge".to_owned(),
+        payload: "ON".to_owned(),
+        inverters,
+    }
+}
+
+#[tokio::test]
+async fn run_job_sends_commands() {
+    common_setup();
+
+    let config = Factory::example_config_wrapped();
+    let channels = Channels::new();
+    let scheduler = Scheduler::new(config, channels.clone());
+    let mut from_mqtt = channels.from_mqtt.subscribe();
+
+    scheduler.run_job(&job(Vec::new())).unwrap();
+    assert_eq!(
+        from_mqtt.try_recv().unwrap(),
+        mqtt::ChannelData::Internal(mqtt::Message {
+            topic: "cmd/2222222222/set/forced_discharge".to_owned(),
+            retain: false,
+            payload: "ON".to_owned(),
+        })
+    );
+    assert!(from_mqtt.try_recv().is_err());
+
+    // not one of ours, so nothing to do
+    let other = Serial::from_str("9999999999").unwrap();
+    scheduler.run_job(&job(vec![other])).unwrap();
+    assert!(from_mqtt.try_recv().is_err());
+}
+
+#[test]
+fn next_run() {
+    common_setup();
+
+    let input = json!({ "enabled": true, "latitude": 51.5074, "longitude": -0.1278 });
+    let scheduler: config::Scheduler = serde_json::from_value(input).unwrap();
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::UTC),
+        ..Factory::inverter()
+    };
+    let now = Utc.with_ymd_and_hms(2024, 6, 21, 12, 0, 0).unwrap();
+
+    assert_eq!(
+        Scheduler::next_run(&scheduler, &job(Vec::new()), &inverter, now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 6, 21, 17, 0, 0).unwrap()
+    );
+
+    let solar = config::Job {
+        cron: None,
+        solar: Some("sunset-30m".to_owned()),
+        ..job(Vec::new())
+    };
+    assert_eq!(
+        Scheduler::next_run(&scheduler, &solar, &inverter, now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 6, 21, 19, 52, 48).unwrap()
+    );
+
+    // no location, so no solar jobs
+    let input = json!({ "enabled": true });
+    let scheduler: config::Scheduler = serde_json::from_value(input).unwrap();
+    assert!(Scheduler::next_run(&scheduler, &solar, &inverter, now).is_err());
+}
+
+#[test]
+fn next_cron_on_inverter_clock() {
+    common_setup();
+
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::Europe::London),
+        ..Factory::inverter()
+    };
+
+    // 17:00 BST
+    let now = Utc.with_ymd_and_hms(2024, 6, 21, 12, 0, 0).unwrap();
+    assert_eq!(
+        Scheduler::next_cron("0 17 * * *", &inverter, now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 6, 21, 16, 0, 0).unwrap()
+    );
+
+    // 01:30 doesn't exist when the clocks go forward; run as they do
+    let now = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+    assert_eq!(
+        Scheduler::next_cron("30 1 * * *", &inverter, now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 3, 31, 1, 30, 0).unwrap()
+    );
+
+    // and happens twice when they go back; the first one
+    let now = Utc.with_ymd_and_hms(2024, 10, 27, 0, 0, 0).unwrap();
+    assert_eq!(
+        Scheduler::next_cron("30 1 * * *", &inverter, now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 10, 27, 0, 30, 0).unwrap()
+    );
+
+    assert!(Scheduler::next_cron("not a cron", &inverter, now).is_err());
+}
+
+#[test]
+fn next_offset_change() {
+    common_setup();
+
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::Europe::London),
+        ..Factory::inverter()
+    };
+
+    // clocks go forward at 01:00 UTC on the last Sunday in March
+    let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 34, 56).unwrap();
+    let change = Scheduler::next_offset_change(&inverter, now).unwrap();
+    let expected = Utc.with_ymd_and_hms(2024, 3, 31, 1, 0, 0).unwrap();
+    assert!(change >= expected && change - expected < chrono::Duration::minutes(1));
+
+    // no DST at all
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::Asia::Tokyo),
+        ..Factory::inverter()
+    };
+    assert_eq!(Scheduler::next_offset_change(&inverter, now), None);
+}
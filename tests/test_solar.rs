@@ -0,0 +1,87 @@
+mod common;
+use common::*;
+
+use chrono::{Datelike, Duration, NaiveDate, TimeZone, Utc};
+use solar::{Event, Trigger};
+
+const LONDON: (f64, f64) = (51.5074, -0.1278);
+
+#[test]
+fn parses_triggers() {
+    let trigger: Trigger = "sunset-30m".parse().unwrap();
+    assert_eq!(trigger.event, Event::Sunset);
+    assert_eq!(trigger.offset, Duration::minutes(-30));
+
+    let trigger: Trigger = "noon+1h30m".parse().unwrap();
+    assert_eq!(trigger.event, Event::Noon);
+    assert_eq!(trigger.offset, Duration::minutes(90));
+
+    let trigger: Trigger = "sunrise".parse().unwrap();
+    assert_eq!(trigger.offset, Duration::zero());
+
+    assert!("sunrise+30".parse::<Trigger>().is_err());
+    assert!("moonrise".parse::<Trigger>().is_err());
+}
+
+#[test]
+fn event_times() {
+    let (lat, lon) = LONDON;
+    let midsummer = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+    let midwinter = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+
+    let near = |event, date, expected: chrono::DateTime<Utc>| {
+        let time = solar::event_time(event, date, lat, lon).unwrap();
+        assert!((time - expected).num_minutes().abs() <= 2, "{}", time);
+    };
+
+    near(
+        Event::Sunrise,
+        midsummer,
+        Utc.with_ymd_and_hms(2024, 6, 21, 3, 43, 0).unwrap(),
+    );
+    near(
+        Event::Noon,
+        midsummer,
+        Utc.with_ymd_and_hms(2024, 6, 21, 12, 2, 0).unwrap(),
+    );
+    near(
+        Event::Sunset,
+        midsummer,
+        Utc.with_ymd_and_hms(2024, 6, 21, 20, 21, 0).unwrap(),
+    );
+    near(
+        Event::Sunrise,
+        midwinter,
+        Utc.with_ymd_and_hms(2024, 12, 21, 8, 4, 0).unwrap(),
+    );
+    near(
+        Event::Sunset,
+        midwinter,
+        Utc.with_ymd_and_hms(2024, 12, 21, 15, 53, 0).unwrap(),
+    );
+
+    // midnight sun in Svalbard
+    assert_eq!(
+        solar::event_time(Event::Sunset, midsummer, 78.2, 15.6),
+        None
+    );
+}
+
+#[test]
+fn next_after() {
+    let (lat, lon) = LONDON;
+    let trigger: Trigger = "sunrise+1h".parse().unwrap();
+
+    // today's has gone, so tomorrow's
+    let now = Utc.with_ymd_and_hms(2024, 6, 21, 12, 0, 0).unwrap();
+    let next = trigger.next_after(now, lat, lon).unwrap();
+    assert_eq!(
+        next.date_naive(),
+        NaiveDate::from_ymd_opt(2024, 6, 22).unwrap()
+    );
+
+    // Svalbard's next sunset is after the midnight sun ends in late August
+    let trigger: Trigger = "sunset".parse().unwrap();
+    let next = trigger.next_after(now, 78.2, 15.6).unwrap();
+    assert_eq!(next.date_naive().month(), 8);
+}
@@ -0,0 +1,82 @@
+mod common;
+use common::*;
+
+use system::Aggregator;
+
+fn system() -> config::System {
+    config::System {
+        enabled: true,
+        name: "garage".to_owned(),
+        datalog: Serial::from_str("SYSTEM0001").unwrap(),
+        inverters: vec![
+            Serial::from_str("1111111111").unwrap(),
+            Serial::from_str("2222222222").unwrap(),
+        ],
+    }
+}
+
+fn input(datalog: &str, p_pv: u16, soc: i8, e_pv_day: f64) -> lxp::packet::ReadInputAll {
+    lxp::packet::ReadInputAll {
+        datalog: Serial::from_str(datalog).unwrap(),
+        p_pv,
+        soc,
+        e_pv_day,
+        ..Factory::read_input_all()
+    }
+}
+
+#[test]
+fn aggregate() {
+    common_setup();
+
+    let a = input("1111111111", 1500, 50, 1.1);
+    let b = input("2222222222", 2000, 61, 2.2);
+    let r = system::aggregate(&[&a, &b], system().datalog());
+
+    assert_eq!(r.datalog, system().datalog());
+    assert_eq!(r.p_pv, 3500);
+    assert_eq!(r.soc, 56); // 55.5, rounded
+    assert_eq!(r.e_pv_day, 3.3);
+    assert_eq!(r.v_bat, a.v_bat);
+}
+
+#[test]
+fn waits_for_all_inverters() {
+    common_setup();
+
+    let mut subject = Aggregator::new(system());
+    assert!(!subject.online());
+
+    assert!(subject.add(&input("1111111111", 1000, 50, 0.0)).is_none());
+    // a newer input from the same inverter replaces the old one
+    assert!(subject.add(&input("1111111111", 1500, 50, 0.0)).is_none());
+    // not a member
+    assert!(subject.add(&input("3333333333", 9999, 50, 0.0)).is_none());
+
+    let r = subject.add(&input("2222222222", 2000, 50, 0.0)).unwrap();
+    assert_eq!(r.p_pv, 3500);
+    assert!(subject.online());
+
+    // both have to report again before the next total
+    assert!(subject.add(&input("2222222222", 2500, 50, 0.0)).is_none());
+    let r = subject.add(&input("1111111111", 500, 50, 0.0)).unwrap();
+    assert_eq!(r.p_pv, 3000);
+}
+
+#[test]
+fn disconnected() {
+    common_setup();
+
+    let mut subject = Aggregator::new(system());
+    subject.add(&input("1111111111", 1000, 50, 0.0));
+    subject.add(&input("2222222222", 1000, 50, 0.0));
+    assert!(subject.online());
+
+    subject.disconnected(Serial::from_str("2222222222").unwrap());
+    assert!(!subject.online());
+
+    // the other inverter's reading is still fresh, but the disconnected one
+    // has to come back first
+    assert!(subject.add(&input("1111111111", 1000, 50, 0.0)).is_none());
+    assert!(subject.add(&input("2222222222", 1000, 50, 0.0)).is_some());
+}
@@ -0,0 +1,151 @@
+mod common;
+use common::*;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use tariff::{Plan, Slot, Window};
+
+fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 3, day)
+        .unwrap()
+        .and_hms_opt(hour, minute, 0)
+        .unwrap()
+}
+
+fn window(start: &str, end: &str) -> Window {
+    Window {
+        start: NaiveTime::parse_from_str(start, "%H:%M").unwrap(),
+        end: NaiveTime::parse_from_str(end, "%H:%M").unwrap(),
+    }
+}
+
+// half-hourly slots from 16:00 with the given prices
+fn slots(prices: &[f64]) -> Vec<Slot> {
+    prices
+        .iter()
+        .enumerate()
+        .map(|(i, price)| {
+            let start = at(1, 16, 0) + Duration::minutes(30 * i as i64);
+            Slot {
+                start,
+                end: start + Duration::minutes(30),
+                price: *price,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn plans_cheapest_and_dearest() {
+    common_setup();
+
+    let mut prices = vec![20.0; 48];
+    prices[2] = 40.0; // 17:00
+    prices[3] = 35.0; // 17:30
+    prices[20] = 5.0; // 02:00
+    prices[21] = 4.0;
+    prices[22] = 6.0;
+    prices[23] = 5.5; // 03:30
+
+    let plan = Plan::new(&slots(&prices), at(1, 16, 0), 4, 2);
+    assert_eq!(plan.charge, vec![window("02:00", "04:00")]);
+    assert_eq!(plan.discharge, vec![window("17:00", "18:00")]);
+    assert_eq!(plan.charge[0].values(), [2, 0, 4, 0]);
+}
+
+#[test]
+fn splits_at_midnight_and_merges_to_fit() {
+    common_setup();
+
+    let mut prices = vec![20.0; 48];
+    prices[15] = 1.0; // 23:30
+    prices[16] = 1.0; // 00:00
+    prices[24] = 2.0; // 04:00
+    prices[28] = 2.0; // 06:00
+
+    let plan = Plan::new(&slots(&prices), at(1, 16, 0), 4, 0);
+    // 23:30-23:59, 00:00-00:30, 04:00-04:30 and 06:00-06:30 is one too many
+    assert_eq!(
+        plan.charge,
+        vec![
+            window("00:00", "00:30"),
+            window("04:00", "06:30"),
+            window("23:30", "23:59"),
+        ]
+    );
+    assert!(plan.discharge.is_empty());
+}
+
+#[test]
+fn slots_from_bands() {
+    common_setup();
+
+    let bands = vec![
+        config::TariffBand {
+            start: "23:30".to_owned(),
+            end: "05:30".to_owned(),
+            price: 7.5,
+        },
+        config::TariffBand {
+            start: "05:30".to_owned(),
+            end: "23:30".to_owned(),
+            price: 30.0,
+        },
+    ];
+
+    let slots = tariff::slots_from_bands(&bands, at(1, 16, 10)).unwrap();
+    assert_eq!(slots.len(), 48);
+    assert_eq!(slots[0].start, at(1, 16, 0));
+    assert_eq!(slots[0].price, 30.0);
+    assert_eq!(slots[15].start, at(1, 23, 30));
+    assert_eq!(slots[15].price, 7.5);
+
+    let plan = Plan::new(&slots, at(1, 16, 0), 6, 0);
+    assert_eq!(
+        plan.charge,
+        vec![window("00:00", "02:30"), window("23:30", "23:59")]
+    );
+}
+
+#[test]
+fn slots_from_agile() {
+    common_setup();
+
+    let json = r#"{"count": 2, "results": [
+        {"value_exc_vat": 20.0, "value_inc_vat": 21.0, "valid_from": "2024-03-01T00:30:00Z", "valid_to": "2024-03-01T01:00:00Z"},
+        {"value_exc_vat": 10.0, "value_inc_vat": 10.5, "valid_from": "2024-03-01T00:00:00Z", "valid_to": "2024-03-01T00:30:00Z"}
+    ]}"#;
+
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::UTC),
+        ..Factory::inverter()
+    };
+    let slots = tariff::slots_from_agile(json, &inverter).unwrap();
+    assert_eq!(slots.len(), 2);
+    // sorted oldest first
+    assert_eq!(slots[0].price, 10.5);
+    assert_eq!(slots[1].price, 21.0);
+    assert_eq!(slots[0].end, slots[1].start);
+}
+
+#[test]
+fn slots_from_agile_on_inverter_clock() {
+    common_setup();
+
+    let json = r#"{"count": 1, "results": [
+        {"value_exc_vat": 10.0, "value_inc_vat": 10.5, "valid_from": "2024-06-01T16:00:00Z", "valid_to": "2024-06-01T16:30:00Z"}
+    ]}"#;
+
+    // BST, an hour ahead of the UTC the prices come in
+    let inverter = config::Inverter {
+        timezone: Some(chrono_tz::Europe::London),
+        ..Factory::inverter()
+    };
+    let slots = tariff::slots_from_agile(json, &inverter).unwrap();
+    assert_eq!(
+        slots[0].start,
+        NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(17, 0, 0)
+            .unwrap()
+    );
+}
@@ -0,0 +1,101 @@
+mod common;
+use common::*;
+
+fn config(url: String) -> config::Webhook {
+    config::Webhook {
+        enabled: true,
+        url,
+        headers: [("Authorization".to_owned(), "Bearer abc".to_owned())].into(),
+        secret: Some("key".to_owned()),
+        min_interval: None,
+        retries: Some(0),
+    }
+}
+
+#[tokio::test]
+async fn posts_inputs() {
+    common_setup();
+
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/hook")
+        .match_header("content-type", "application/json")
+        .match_header("authorization", "Bearer abc")
+        .match_header(
+            "x-signature-256",
+            Matcher::Regex("^sha256=[0-9a-f]{64}$".to_owned()),
+        )
+        .match_body(Matcher::PartialJson(json!({
+            "type": "inputs",
+            "datalog": "1234567890",
+            "data": { "soc": 55 }
+        })))
+        .with_status(200)
+        .create();
+
+    let webhook = Webhook::new(config(format!("{}/hook", server.url())));
+
+    let data = output::OutputData::InputAll(Box::new(Factory::read_input_all()));
+    webhook.write(&[data]).await.unwrap();
+
+    mock.assert();
+}
+
+#[tokio::test]
+async fn filters_unchanged_holds() {
+    common_setup();
+
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/hook")
+        .with_status(200)
+        .expect(2)
+        .create();
+
+    let webhook = Webhook::new(config(format!("{}/hook", server.url())));
+    let datalog = Factory::inverter().datalog();
+
+    let data = output::OutputData::Hold(datalog, vec![(21, 1), (22, 2)]);
+    assert_eq!(webhook.filter(data.clone()), Some(data.clone()));
+    webhook.write(&[data]).await.unwrap();
+
+    // only 22 changed
+    let data = output::OutputData::Hold(datalog, vec![(21, 1), (22, 3)]);
+    let changed = output::OutputData::Hold(datalog, vec![(22, 3)]);
+    assert_eq!(webhook.filter(data.clone()), Some(changed.clone()));
+    webhook.write(&[changed]).await.unwrap();
+
+    assert_eq!(webhook.filter(data), None);
+
+    mock.assert();
+}
+
+#[tokio::test]
+async fn resends_holds_after_failed_post() {
+    common_setup();
+
+    let mut server = mockito::Server::new_async().await;
+    let mock = server.mock("POST", "/hook").with_status(500).create();
+
+    let webhook = Webhook::new(config(format!("{}/hook", server.url())));
+    let datalog = Factory::inverter().datalog();
+
+    let data = output::OutputData::Hold(datalog, vec![(21, 1)]);
+    assert_eq!(webhook.filter(data.clone()), Some(data.clone()));
+    assert!(webhook.write(std::slice::from_ref(&data)).await.is_err());
+
+    // never got there, so still counts as changed
+    assert_eq!(webhook.filter(data.clone()), Some(data));
+
+    mock.assert();
+}
+
+#[test]
+fn signature() {
+    common_setup();
+
+    assert_eq!(
+        Webhook::signature("key", "The quick brown fox jumps over the lazy dog").unwrap(),
+        "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+}